@@ -1,3 +1,11 @@
+pub mod headers;
 pub mod init_segment;
+pub mod segment;
+pub mod single_flight;
+pub mod stats;
 
-pub use init_segment::InitSegmentCache;
+pub use headers::headers_hash;
+pub use init_segment::{InitSegmentCache, InitSegmentCacheConfig};
+pub use segment::{SegmentCache, SegmentCacheConfig, SegmentCacheKey};
+pub use single_flight::SingleFlight;
+pub use stats::CacheStats;
@@ -0,0 +1,60 @@
+//! Hashing of the request headers that are part of cache keys.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Hash a header set independently of the map's iteration order.
+///
+/// Pairs are sorted and NUL-separated, so equal header sets always hash the
+/// same and names and values cannot run into each other.
+pub fn headers_hash(headers: &HashMap<String, String>) -> [u8; 32] {
+    let mut pairs: Vec<_> = headers.iter().collect();
+    pairs.sort();
+
+    let mut hasher = Sha256::new();
+    for (k, v) in pairs {
+        hasher.update(k.as_bytes());
+        hasher.update([0]);
+        hasher.update(v.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_ignores_order() {
+        let pairs = [
+            ("Authorization", "Bearer token"),
+            ("Cookie", "session=1"),
+            ("Referer", "https://example.com/"),
+            ("User-Agent", "player"),
+        ];
+        let mut reversed = pairs;
+        reversed.reverse();
+
+        // Differently sized maps iterate in different orders
+        let mut large = HashMap::with_capacity(64);
+        large.extend(headers(&reversed));
+
+        assert_eq!(headers_hash(&headers(&pairs)), headers_hash(&large));
+        assert_ne!(
+            headers_hash(&headers(&pairs)),
+            headers_hash(&headers(&pairs[..3]))
+        );
+        assert_ne!(
+            headers_hash(&headers(&[("ab", "c")])),
+            headers_hash(&headers(&[("a", "bc")]))
+        );
+    }
+}
@@ -1,50 +1,105 @@
 use bytes::Bytes;
 use lru::LruCache;
 use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-    num::NonZeroUsize,
-    sync::Mutex,
     collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
 };
 
-use crate::{hls::ByteRange, proxy::ProxyClient, Result};
+use super::{CacheStats, SingleFlight, headers_hash, stats::CacheCounters};
+use crate::{Result, hls::ByteRange, proxy::ProxyClient};
 
 /// Cache key for init segments.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct CacheKey {
     url: String,
-    headers_hash: u64,
+    headers_hash: [u8; 32],
     byterange: Option<(u64, Option<u64>)>,
 }
 
 impl CacheKey {
     fn new(url: &str, headers: &HashMap<String, String>, byterange: Option<&ByteRange>) -> Self {
-        let mut hasher = DefaultHasher::new();
-        for (k, v) in headers {
-            k.hash(&mut hasher);
-            v.hash(&mut hasher);
-        }
-
         Self {
             url: url.to_string(),
-            headers_hash: hasher.finish(),
+            headers_hash: headers_hash(headers),
             byterange: byterange.map(|br| (br.length, br.offset)),
         }
     }
 }
 
-/// LRU cache for init segments.
+/// Configuration for the init segment cache.
+#[derive(Debug, Clone)]
+pub struct InitSegmentCacheConfig {
+    /// Maximum number of cached init segments.
+    pub max_entries: usize,
+
+    /// Maximum total size of cached init segments in bytes.
+    pub max_bytes: usize,
+
+    /// Lifetime of an entry; `None` keeps entries until they are evicted.
+    pub ttl: Option<Duration>,
+}
+
+impl InitSegmentCacheConfig {
+    /// Create config from environment variables, falling back to defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|s| s.parse::<u64>().ok());
+
+        Self {
+            max_entries: var("SHIZU_INIT_CACHE_ENTRIES")
+                .map_or(defaults.max_entries, |n| n as usize),
+            max_bytes: var("SHIZU_INIT_CACHE_MB")
+                .map_or(defaults.max_bytes, |mb| mb as usize * 1024 * 1024),
+            // A TTL of 0 disables expiry
+            ttl: var("SHIZU_INIT_CACHE_TTL_SECS").map_or(defaults.ttl, |secs| {
+                (secs > 0).then(|| Duration::from_secs(secs))
+            }),
+        }
+    }
+}
+
+impl Default for InitSegmentCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 100,
+            max_bytes: 64 * 1024 * 1024,
+            ttl: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+/// LRU cache for init segments, bounded by entry count and total size.
+///
+/// Concurrent misses for the same init segment share one fetch.
 pub struct InitSegmentCache {
-    cache: Mutex<LruCache<CacheKey, Bytes>>,
+    cache: Mutex<Entries>,
+    config: InitSegmentCacheConfig,
+    counters: CacheCounters,
+    in_flight: SingleFlight<CacheKey, Bytes>,
+}
+
+/// Cached init segments and their total size.
+struct Entries {
+    lru: LruCache<CacheKey, Entry>,
+    size: usize,
+}
+
+struct Entry {
+    data: Bytes,
+    expires: Option<Instant>,
 }
 
 impl InitSegmentCache {
-    pub fn new(max_entries: usize) -> Self {
+    pub fn new(config: InitSegmentCacheConfig) -> Self {
         Self {
-            cache: Mutex::new(LruCache::new(
-                NonZeroUsize::new(max_entries).expect("max_entries must be > 0"),
-            )),
+            cache: Mutex::new(Entries {
+                lru: LruCache::unbounded(),
+                size: 0,
+            }),
+            config,
+            counters: CacheCounters::default(),
+            in_flight: SingleFlight::new(),
         }
     }
 
@@ -59,37 +114,96 @@ impl InitSegmentCache {
         let key = CacheKey::new(url, headers, byterange);
 
         // Check cache first
-        {
-            let mut cache = self.cache.lock().unwrap();
-            if let Some(cached) = cache.get(&key) {
-                tracing::debug!("Init segment cache hit: {}", url);
-                return Ok(cached.clone());
-            }
+        if let Some(cached) = self.get(&key) {
+            tracing::debug!("Init segment cache hit: {}", url);
+            self.counters.hit();
+            return Ok(cached);
         }
 
-        // Fetch from URL
-        tracing::debug!("Init segment cache miss, fetching: {}", url);
-        let bytes = client.fetch(url, Some(headers), byterange).await?;
+        self.in_flight
+            .run(&key, || async {
+                // Another call may have filled the cache since we checked
+                if let Some(cached) = self.get(&key) {
+                    self.counters.hit();
+                    return Ok(cached);
+                }
+
+                // Fetch from URL
+                tracing::debug!("Init segment cache miss, fetching: {}", url);
+                self.counters.miss();
+                let bytes = client.fetch(url, Some(headers), byterange).await?;
+
+                // Store in cache
+                self.insert(key.clone(), bytes.clone());
+
+                Ok(bytes)
+            })
+            .await
+    }
 
-        // Store in cache
-        {
-            let mut cache = self.cache.lock().unwrap();
-            cache.put(key, bytes.clone());
+    fn get(&self, key: &CacheKey) -> Option<Bytes> {
+        let mut cache = self.cache.lock().unwrap();
+        let entry = cache.lru.get(key)?;
+        if entry.expires.is_none_or(|expires| expires > Instant::now()) {
+            return Some(entry.data.clone());
         }
 
-        Ok(bytes)
+        // Expired: drop it so the origin's current init segment is fetched
+        if let Some(entry) = cache.lru.pop(key) {
+            cache.size -= entry.data.len();
+        }
+        self.counters.expire();
+        None
+    }
+
+    fn insert(&self, key: CacheKey, data: Bytes) {
+        // Segments that could never fit are served but not cached
+        if data.len() > self.config.max_bytes || self.config.max_entries == 0 {
+            return;
+        }
+
+        let mut cache = self.cache.lock().unwrap();
+        let entry = Entry {
+            expires: self.config.ttl.map(|ttl| Instant::now() + ttl),
+            data,
+        };
+        cache.size += entry.data.len();
+        if let Some(old) = cache.lru.put(key, entry) {
+            cache.size -= old.data.len();
+        }
+
+        while cache.lru.len() > self.config.max_entries || cache.size > self.config.max_bytes {
+            let Some((_, evicted)) = cache.lru.pop_lru() else {
+                break;
+            };
+            cache.size -= evicted.data.len();
+            self.counters.evict();
+        }
     }
 
     /// Clear the cache.
     pub fn clear(&self) {
         let mut cache = self.cache.lock().unwrap();
-        cache.clear();
+        cache.lru.clear();
+        cache.size = 0;
     }
 
     /// Get current cache size.
     pub fn len(&self) -> usize {
         let cache = self.cache.lock().unwrap();
-        cache.len()
+        cache.lru.len()
+    }
+
+    /// Total size of the cached init segments in bytes.
+    pub fn size_bytes(&self) -> usize {
+        let cache = self.cache.lock().unwrap();
+        cache.size
+    }
+
+    /// Counters and current usage, for monitoring.
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().unwrap();
+        self.counters.snapshot(cache.lru.len(), cache.size)
     }
 
     /// Check if cache is empty.
@@ -100,7 +214,7 @@ impl InitSegmentCache {
 
 impl Default for InitSegmentCache {
     fn default() -> Self {
-        Self::new(100)
+        Self::new(InitSegmentCacheConfig::default())
     }
 }
 
@@ -122,4 +236,138 @@ mod tests {
         // Same headers should produce same key
         assert_eq!(key1, key2);
     }
+
+    #[test]
+    fn test_cache_key_ignores_header_order() {
+        let pairs = [
+            ("Authorization", "Bearer token"),
+            ("Cookie", "session=1"),
+            ("Referer", "https://example.com/"),
+        ];
+
+        let headers1: HashMap<String, String> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        // A map of another capacity iterates in another order
+        let mut headers2 = HashMap::with_capacity(64);
+        for (k, v) in pairs.iter().rev() {
+            headers2.insert(k.to_string(), v.to_string());
+        }
+
+        let key1 = CacheKey::new("https://example.com/init.mp4", &headers1, None);
+        let key2 = CacheKey::new("https://example.com/init.mp4", &headers2, None);
+        assert_eq!(key1, key2);
+
+        headers2.remove("Cookie");
+        let key3 = CacheKey::new("https://example.com/init.mp4", &headers2, None);
+        assert_ne!(key1, key3);
+    }
+
+    #[tokio::test]
+    async fn test_coalesces_concurrent_misses() {
+        use axum::{Router, routing::get};
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let upstream = Router::new().route(
+            "/init.mp4",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                "init"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/init.mp4", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let cache = Arc::new(InitSegmentCache::default());
+        let client = ProxyClient::local();
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let (cache, client, url) = (cache.clone(), client.clone(), url.clone());
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch(&url, &HashMap::new(), None, &client)
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), "init");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.len(), 1);
+    }
+
+    /// Serve 40-byte init segments at any path and return the base URL.
+    async fn spawn_upstream() -> String {
+        let upstream = axum::Router::new().fallback(|| async { vec![0u8; 40] });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_bounded_by_size() {
+        let base = spawn_upstream().await;
+        let client = ProxyClient::local();
+        let cache = InitSegmentCache::new(InitSegmentCacheConfig {
+            max_bytes: 100,
+            ..Default::default()
+        });
+
+        for name in ["a", "b", "c", "a"] {
+            let url = format!("{}/{}.mp4", base, name);
+            cache
+                .get_or_fetch(&url, &HashMap::new(), None, &client)
+                .await
+                .unwrap();
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.size_bytes, 80);
+        // "a" was evicted by "c", so it is fetched again
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.hits, 0);
+        assert_eq!(stats.evictions, 2);
+    }
+
+    #[tokio::test]
+    async fn test_expires_entries() {
+        let base = spawn_upstream().await;
+        let client = ProxyClient::local();
+        let url = format!("{}/init.mp4", base);
+
+        let cache = InitSegmentCache::default();
+        for _ in 0..2 {
+            cache
+                .get_or_fetch(&url, &HashMap::new(), None, &client)
+                .await
+                .unwrap();
+        }
+        assert_eq!(cache.stats().hits, 1);
+
+        let cache = InitSegmentCache::new(InitSegmentCacheConfig {
+            ttl: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            cache
+                .get_or_fetch(&url, &HashMap::new(), None, &client)
+                .await
+                .unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.expirations, 1);
+    }
 }
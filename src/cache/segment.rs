@@ -0,0 +1,696 @@
+use bytes::Bytes;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use super::{CacheStats, SingleFlight, headers_hash, stats::CacheCounters};
+use crate::{
+    Result,
+    decrypt::{DecryptionKey, SegmentDecryptMethod},
+    hls::{ByteRange, SegmentFormat},
+};
+
+/// Cache key for decrypted segments.
+///
+/// Besides the upstream request, the key covers everything that changes
+/// the output: method, output format, key material, IV and init segment.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SegmentCacheKey {
+    url: String,
+    headers_hash: [u8; 32],
+    byterange: Option<(u64, Option<u64>)>,
+    method: &'static str,
+    format: &'static str,
+    key_fingerprint: u64,
+}
+
+impl SegmentCacheKey {
+    pub fn new(
+        url: &str,
+        headers: &HashMap<String, String>,
+        byterange: Option<&ByteRange>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            headers_hash: headers_hash(headers),
+            byterange: byterange.map(|br| (br.length, br.offset)),
+            method: "",
+            format: "",
+            key_fingerprint: 0,
+        }
+    }
+
+    /// Identify the decryption applied to the segment.
+    pub fn with_decryption(
+        mut self,
+        method: SegmentDecryptMethod,
+        format: SegmentFormat,
+        key: &DecryptionKey,
+        iv: &[u8; 16],
+        init: Option<(&str, Option<&ByteRange>)>,
+    ) -> Self {
+        let mut keys: Vec<String> = key.to_string().split(',').map(String::from).collect();
+        keys.sort();
+
+        let mut hasher = Sha256::new();
+        hasher.update(keys.join(",").as_bytes());
+        hasher.update(iv);
+        if let Some((init_url, init_br)) = init {
+            hasher.update(init_url.as_bytes());
+            if let Some(br) = init_br {
+                hasher.update(br.to_query_param().as_bytes());
+            }
+        }
+
+        self.method = method.as_str();
+        self.format = format.as_str();
+        self.key_fingerprint = truncate(hasher.finalize().into());
+        self
+    }
+
+    /// Stable hex digest of the key, used as the disk file name.
+    fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.url.as_bytes());
+        hasher.update(self.headers_hash);
+        hasher.update(format!("{:?}", self.byterange).as_bytes());
+        hasher.update(self.method.as_bytes());
+        hasher.update(self.format.as_bytes());
+        hasher.update(self.key_fingerprint.to_be_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+fn truncate(digest: [u8; 32]) -> u64 {
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+/// Configuration for the segment cache.
+#[derive(Debug, Clone)]
+pub struct SegmentCacheConfig {
+    /// Maximum total size of the memory tier in bytes.
+    pub memory_bytes: usize,
+
+    /// Lifetime of memory entries.
+    pub memory_ttl: Duration,
+
+    /// Directory of the disk tier, if enabled.
+    pub disk_dir: Option<PathBuf>,
+
+    /// Lifetime of disk entries.
+    pub disk_ttl: Duration,
+
+    /// Maximum total size of the disk tier in bytes.
+    pub disk_bytes: u64,
+}
+
+impl SegmentCacheConfig {
+    /// Create config from environment variables.
+    ///
+    /// Returns `None` when neither tier is configured.
+    pub fn from_env() -> Option<Self> {
+        let memory_mb: usize = std::env::var("SHIZU_SEGMENT_CACHE_MB")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0);
+        let disk_dir = std::env::var("SHIZU_SEGMENT_CACHE_DIR")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from);
+
+        if memory_mb == 0 && disk_dir.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            memory_bytes: memory_mb * 1024 * 1024,
+            memory_ttl: Duration::from_secs(
+                std::env::var("SHIZU_SEGMENT_CACHE_TTL_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(300),
+            ),
+            disk_dir,
+            disk_ttl: Duration::from_secs(
+                std::env::var("SHIZU_SEGMENT_CACHE_DISK_TTL_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(3600),
+            ),
+            disk_bytes: std::env::var("SHIZU_SEGMENT_CACHE_DISK_MB")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(1024)
+                * 1024
+                * 1024,
+        })
+    }
+}
+
+/// Cache for decrypted segments, with a size-bounded memory LRU and an
+/// optional disk tier.
+///
+/// Concurrent misses for the same key share one fetch.
+pub struct SegmentCache {
+    memory: Mutex<MemoryTier>,
+    memory_ttl: Duration,
+    disk: Option<Arc<DiskTier>>,
+    counters: CacheCounters,
+    in_flight: SingleFlight<SegmentCacheKey, Bytes>,
+}
+
+impl SegmentCache {
+    /// Create the cache, clearing the disk tier's directory.
+    ///
+    /// Inside a Tokio runtime, expired disk entries are swept periodically.
+    pub fn new(config: SegmentCacheConfig) -> Self {
+        let disk = config
+            .disk_dir
+            .map(|dir| Arc::new(DiskTier::open(dir, config.disk_ttl, config.disk_bytes)));
+        if let Some(ref disk) = disk
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            runtime.spawn(DiskTier::sweep_periodically(Arc::downgrade(disk)));
+        }
+
+        Self {
+            memory: Mutex::new(MemoryTier {
+                entries: LruCache::unbounded(),
+                size: 0,
+                max_size: config.memory_bytes,
+            }),
+            memory_ttl: config.memory_ttl,
+            disk,
+            counters: CacheCounters::default(),
+            in_flight: SingleFlight::new(),
+        }
+    }
+
+    /// Get a segment from the cache or produce it with `fetch`.
+    pub async fn get_or_fetch<F, Fut>(&self, key: SegmentCacheKey, fetch: F) -> Result<Bytes>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Bytes>>,
+    {
+        if let Some(cached) = self.get_memory(&key) {
+            tracing::debug!("Segment cache hit: {}", key.url);
+            self.counters.hit();
+            return Ok(cached);
+        }
+
+        self.in_flight
+            .run(&key, || async {
+                // Another call may have filled the cache since we checked
+                if let Some(cached) = self.get_memory(&key) {
+                    self.counters.hit();
+                    return Ok(cached);
+                }
+
+                if let Some(ref disk) = self.disk
+                    && let Some(cached) = disk.get(&key).await
+                {
+                    tracing::debug!("Segment disk cache hit: {}", key.url);
+                    self.counters.hit();
+                    self.insert_memory(key.clone(), cached.clone());
+                    return Ok(cached);
+                }
+
+                tracing::debug!("Segment cache miss, fetching: {}", key.url);
+                self.counters.miss();
+                let data = fetch().await?;
+
+                self.insert_memory(key.clone(), data.clone());
+                if let Some(ref disk) = self.disk {
+                    disk.put(&key, &data).await;
+                }
+                Ok(data)
+            })
+            .await
+    }
+
+    fn get_memory(&self, key: &SegmentCacheKey) -> Option<Bytes> {
+        let mut memory = self.memory.lock().unwrap();
+        let entry = memory.entries.get(key)?;
+        if entry.expires > Instant::now() {
+            return Some(entry.data.clone());
+        }
+        memory.remove(key);
+        self.counters.expire();
+        None
+    }
+
+    fn insert_memory(&self, key: SegmentCacheKey, data: Bytes) {
+        let mut memory = self.memory.lock().unwrap();
+        if data.len() > memory.max_size {
+            return;
+        }
+
+        let entry = MemoryEntry {
+            data,
+            expires: Instant::now() + self.memory_ttl,
+        };
+        memory.size += entry.data.len();
+        if let Some(old) = memory.entries.put(key, entry) {
+            memory.size -= old.data.len();
+        }
+
+        while memory.size > memory.max_size {
+            let Some((_, evicted)) = memory.entries.pop_lru() else {
+                break;
+            };
+            memory.size -= evicted.data.len();
+            self.counters.evict();
+        }
+    }
+
+    /// Clear the memory tier.
+    pub fn clear(&self) {
+        let mut memory = self.memory.lock().unwrap();
+        memory.entries.clear();
+        memory.size = 0;
+    }
+
+    /// Number of segments in the memory tier.
+    pub fn len(&self) -> usize {
+        self.memory.lock().unwrap().entries.len()
+    }
+
+    /// Check if the memory tier is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of the segments in the memory tier.
+    pub fn size_bytes(&self) -> usize {
+        self.memory.lock().unwrap().size
+    }
+
+    /// Counters and memory tier usage, for monitoring.
+    pub fn stats(&self) -> CacheStats {
+        let memory = self.memory.lock().unwrap();
+        self.counters.snapshot(memory.entries.len(), memory.size)
+    }
+}
+
+struct MemoryTier {
+    entries: LruCache<SegmentCacheKey, MemoryEntry>,
+    size: usize,
+    max_size: usize,
+}
+
+impl MemoryTier {
+    fn remove(&mut self, key: &SegmentCacheKey) {
+        if let Some(entry) = self.entries.pop(key) {
+            self.size -= entry.data.len();
+        }
+    }
+}
+
+struct MemoryEntry {
+    data: Bytes,
+    expires: Instant,
+}
+
+/// How often expired disk entries are removed.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Segments stored as files named by key digest.
+///
+/// The directory is cleared when the tier is opened, so the index tracks
+/// every file in it; the least recently used files are removed once the
+/// tier grows past its size limit.
+struct DiskTier {
+    dir: PathBuf,
+    ttl: Duration,
+    index: Mutex<DiskIndex>,
+}
+
+struct DiskIndex {
+    files: LruCache<String, DiskEntry>,
+    size: u64,
+    max_size: u64,
+}
+
+impl DiskIndex {
+    fn remove(&mut self, digest: &str) {
+        if let Some(entry) = self.files.pop(digest) {
+            self.size -= entry.size;
+        }
+    }
+}
+
+struct DiskEntry {
+    size: u64,
+    expires: Instant,
+}
+
+impl DiskTier {
+    fn open(dir: PathBuf, ttl: Duration, max_size: u64) -> Self {
+        let removed = clear_dir(&dir);
+        if removed > 0 {
+            tracing::info!("Removed {} segment cache files from {:?}", removed, dir);
+        }
+
+        Self {
+            dir,
+            ttl,
+            index: Mutex::new(DiskIndex {
+                files: LruCache::unbounded(),
+                size: 0,
+                max_size,
+            }),
+        }
+    }
+
+    async fn get(&self, key: &SegmentCacheKey) -> Option<Bytes> {
+        let digest = key.digest();
+        let expired = {
+            let mut index = self.index.lock().unwrap();
+            let expired = index.files.get(&digest)?.expires <= Instant::now();
+            if expired {
+                index.remove(&digest);
+            }
+            expired
+        };
+        if expired {
+            self.remove_files([digest]).await;
+            return None;
+        }
+
+        match tokio::fs::read(self.dir.join(&digest)).await {
+            Ok(data) => Some(Bytes::from(data)),
+            Err(_) => {
+                self.index.lock().unwrap().remove(&digest);
+                None
+            }
+        }
+    }
+
+    /// Write a segment; failures only lose the cache entry.
+    async fn put(&self, key: &SegmentCacheKey, data: &Bytes) {
+        let size = data.len() as u64;
+        if size > self.index.lock().unwrap().max_size {
+            return;
+        }
+
+        let digest = key.digest();
+        let path = self.dir.join(&digest);
+        // Write to a temporary file first so readers never see partial data
+        let tmp = path.with_extension(format!("tmp-{}", uuid::Uuid::new_v4()));
+
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(&tmp, data).await?;
+            tokio::fs::rename(&tmp, &path).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to write segment cache file {:?}: {}", path, e);
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return;
+        }
+
+        let mut evicted = Vec::new();
+        {
+            let mut index = self.index.lock().unwrap();
+            let entry = DiskEntry {
+                size,
+                expires: Instant::now() + self.ttl,
+            };
+            index.size += size;
+            if let Some(old) = index.files.put(digest, entry) {
+                index.size -= old.size;
+            }
+
+            while index.size > index.max_size {
+                let Some((digest, entry)) = index.files.pop_lru() else {
+                    break;
+                };
+                index.size -= entry.size;
+                evicted.push(digest);
+            }
+        }
+        self.remove_files(evicted).await;
+    }
+
+    /// Remove expired files.
+    async fn sweep(&self) {
+        let now = Instant::now();
+        let expired: Vec<String> = {
+            let mut index = self.index.lock().unwrap();
+            let expired: Vec<String> = index
+                .files
+                .iter()
+                .filter(|(_, entry)| entry.expires <= now)
+                .map(|(digest, _)| digest.clone())
+                .collect();
+            for digest in &expired {
+                index.remove(digest);
+            }
+            expired
+        };
+
+        if !expired.is_empty() {
+            tracing::debug!("Removing {} expired segment cache files", expired.len());
+        }
+        self.remove_files(expired).await;
+    }
+
+    /// Sweep until the cache is dropped.
+    async fn sweep_periodically(disk: Weak<DiskTier>) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        // The first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let Some(disk) = disk.upgrade() else {
+                break;
+            };
+            disk.sweep().await;
+        }
+    }
+
+    async fn remove_files(&self, digests: impl IntoIterator<Item = String>) {
+        for digest in digests {
+            let path = self.dir.join(digest);
+            if let Err(e) = tokio::fs::remove_file(&path).await
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                tracing::warn!("Failed to remove segment cache file {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Remove the cache files left in `dir` by an earlier run.
+///
+/// Only files named like cache entries are touched, in case the directory
+/// is shared.
+fn clear_dir(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let digest = name.split_once(".tmp-").map_or(name, |(digest, _)| digest);
+        if digest.len() == 64
+            && digest.bytes().all(|b| b.is_ascii_hexdigit())
+            && std::fs::remove_file(entry.path()).is_ok()
+        {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn config(memory_bytes: usize) -> SegmentCacheConfig {
+        SegmentCacheConfig {
+            memory_bytes,
+            memory_ttl: Duration::from_secs(60),
+            disk_dir: None,
+            disk_ttl: Duration::from_secs(60),
+            disk_bytes: 0,
+        }
+    }
+
+    fn disk_config(dir: &Path, disk_bytes: u64) -> SegmentCacheConfig {
+        SegmentCacheConfig {
+            disk_dir: Some(dir.to_path_buf()),
+            disk_bytes,
+            ..config(0)
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("shizu-test-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn file_count(dir: &Path) -> usize {
+        std::fs::read_dir(dir).map_or(0, |entries| entries.count())
+    }
+
+    fn key(url: &str) -> SegmentCacheKey {
+        SegmentCacheKey::new(url, &HashMap::new(), None).with_decryption(
+            SegmentDecryptMethod::SampleAes,
+            SegmentFormat::MpegTS,
+            &DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+            &[0; 16],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_key_covers_decryption() {
+        let other_key = DecryptionKey::parse("00000000000000000000000000000000").unwrap();
+        let with_other_key =
+            SegmentCacheKey::new("https://example.com/seg.ts", &HashMap::new(), None)
+                .with_decryption(
+                    SegmentDecryptMethod::SampleAes,
+                    SegmentFormat::MpegTS,
+                    &other_key,
+                    &[0; 16],
+                    None,
+                );
+
+        assert_eq!(
+            key("https://example.com/seg.ts"),
+            key("https://example.com/seg.ts")
+        );
+        assert_ne!(key("https://example.com/seg.ts"), with_other_key);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_by_size() {
+        let cache = SegmentCache::new(config(10));
+
+        for url in ["a", "b", "c"] {
+            cache
+                .get_or_fetch(key(url), || async { Ok(Bytes::from_static(b"12345")) })
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size_bytes(), 10);
+        assert!(cache.get_memory(&key("a")).is_none());
+        assert!(cache.get_memory(&key("c")).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_coalesces_concurrent_misses() {
+        let cache = Arc::new(SegmentCache::new(config(1024)));
+        let fetches = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                let fetches = fetches.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch(key("seg"), || async {
+                            fetches.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(Bytes::from_static(b"decrypted"))
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), "decrypted");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disk_tier() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(key("old").digest()), "stale").unwrap();
+        std::fs::write(dir.join("notes.txt"), "unrelated").unwrap();
+
+        // Files of an earlier run are removed on startup
+        let cache = SegmentCache::new(disk_config(&dir, 1024));
+        assert!(!dir.join(key("old").digest()).exists());
+        assert!(dir.join("notes.txt").exists());
+
+        cache
+            .get_or_fetch(key("seg"), || async {
+                Ok(Bytes::from_static(b"decrypted"))
+            })
+            .await
+            .unwrap();
+        assert!(cache.is_empty());
+
+        // With no memory tier, the segment is read back from disk
+        let data = cache
+            .get_or_fetch(key("seg"), || async { Err(crate::Error::KeyRequired) })
+            .await
+            .unwrap();
+        assert_eq!(data, "decrypted");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_tier_sweeps_expired() {
+        let dir = temp_dir();
+        let cache = SegmentCache::new(SegmentCacheConfig {
+            disk_ttl: Duration::ZERO,
+            ..disk_config(&dir, 1024)
+        });
+        let disk = cache.disk.as_ref().unwrap();
+
+        disk.put(&key("seg"), &Bytes::from_static(b"decrypted"))
+            .await;
+        assert_eq!(file_count(&dir), 1);
+
+        disk.sweep().await;
+        assert_eq!(file_count(&dir), 0);
+        assert!(disk.get(&key("seg")).await.is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_disk_tier_evicts_by_size() {
+        let dir = temp_dir();
+        let cache = SegmentCache::new(disk_config(&dir, 10));
+        let disk = cache.disk.as_ref().unwrap();
+
+        for url in ["a", "b", "c"] {
+            disk.put(&key(url), &Bytes::from_static(b"12345")).await;
+        }
+        // Too large to cache at all
+        disk.put(&key("d"), &Bytes::from_static(b"12345678901"))
+            .await;
+
+        assert_eq!(file_count(&dir), 2);
+        assert_eq!(disk.index.lock().unwrap().size, 10);
+        assert!(disk.get(&key("a")).await.is_none());
+        assert_eq!(disk.get(&key("b")).await.unwrap(), "12345");
+        assert_eq!(disk.get(&key("c")).await.unwrap(), "12345");
+        assert!(disk.get(&key("d")).await.is_none());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+use crate::Result;
+
+/// Coalesces concurrent loads of the same key into one.
+///
+/// While a load is in flight, other callers for the key wait for its result
+/// instead of starting their own. Errors are shared with the waiters of the
+/// failed load, but a later call starts a new load.
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Arc<OnceCell<Result<V>>>>>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `load` for `key`, or wait for the load already running.
+    pub async fn run<F, Fut>(&self, key: &K, load: F) -> Result<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V>>,
+    {
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            calls.entry(key.clone()).or_default().clone()
+        };
+
+        let result = call.get_or_init(load).await.clone();
+
+        // Later callers should see the cache, not this finished call
+        let mut calls = self.calls.lock().unwrap();
+        if calls.get(key).is_some_and(|c| Arc::ptr_eq(c, &call)) {
+            calls.remove(key);
+        }
+
+        result
+    }
+
+    /// Number of loads in flight.
+    pub fn len(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+
+    /// Check if no load is in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> Default for SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn test_coalesces_concurrent_loads() {
+        let flight = Arc::new(SingleFlight::<&str, u32>::new());
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let flight = flight.clone();
+                let loads = loads.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run(&"seg", || async {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(42)
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), 42);
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shares_errors_without_caching() {
+        let flight = Arc::new(SingleFlight::<&str, u32>::new());
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let flight = flight.clone();
+                let loads = loads.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run(&"seg", || async {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Err(crate::Error::KeyRequired)
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert!(matches!(
+                task.await.unwrap(),
+                Err(crate::Error::KeyRequired)
+            ));
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        let result = flight.run(&"seg", || async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+    }
+}
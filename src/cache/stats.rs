@@ -0,0 +1,62 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Snapshot of a cache's counters, for monitoring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+
+    /// Lookups that went to the origin.
+    pub misses: u64,
+
+    /// Entries dropped to stay within the cache limits.
+    pub evictions: u64,
+
+    /// Entries dropped because their TTL ran out.
+    pub expirations: u64,
+
+    /// Entries currently cached.
+    pub entries: usize,
+
+    /// Total size of the cached entries in bytes.
+    pub size_bytes: usize,
+}
+
+/// Counters updated as a cache is used.
+#[derive(Debug, Default)]
+pub(crate) struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl CacheCounters {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evict(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn expire(&self) {
+        self.expirations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, entries: usize, size_bytes: usize) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            entries,
+            size_bytes,
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod template;
+
+pub use template::TemplateValues;
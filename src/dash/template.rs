@@ -0,0 +1,244 @@
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+/// `SegmentTemplate` identifiers and the /segment query parameter that
+/// carries each substituted value.
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("RepresentationID", "rid"),
+    ("Number", "num"),
+    ("Bandwidth", "bw"),
+    ("Time", "time"),
+    ("SubNumber", "sub"),
+];
+
+/// Values for the identifiers of a DASH `SegmentTemplate` URL.
+///
+/// Template URLs are signed with their `$Identifier$`s intact. The player
+/// substitutes the identifiers into the query parameters added by
+/// [`TemplateValues::query_params`], and the segment endpoint expands the
+/// template from them.
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateValues {
+    /// `$RepresentationID$`
+    #[serde(default)]
+    pub rid: Option<String>,
+
+    /// `$Number$`
+    #[serde(default)]
+    pub num: Option<String>,
+
+    /// `$Bandwidth$`
+    #[serde(default)]
+    pub bw: Option<String>,
+
+    /// `$Time$`
+    #[serde(default)]
+    pub time: Option<String>,
+
+    /// `$SubNumber$`
+    #[serde(default)]
+    pub sub: Option<String>,
+}
+
+impl TemplateValues {
+    /// Check if no values were given.
+    pub fn is_empty(&self) -> bool {
+        self.rid.is_none()
+            && self.num.is_none()
+            && self.bw.is_none()
+            && self.time.is_none()
+            && self.sub.is_none()
+    }
+
+    /// Build the query parameters (with leading `&`) for the identifiers
+    /// used in `templates`, leaving the identifiers for the player to fill in.
+    pub fn query_params(templates: &[&str]) -> String {
+        IDENTIFIERS
+            .iter()
+            .filter(|(name, _)| {
+                templates
+                    .iter()
+                    .any(|t| Self::identifiers(t).any(|(id, _)| id == *name))
+            })
+            .map(|(name, param)| format!("&{}=${}$", param, name))
+            .collect()
+    }
+
+    /// Expand the identifiers in a template URL.
+    ///
+    /// URLs are returned unchanged when no values were given.
+    pub fn expand(&self, template: &str) -> Result<String> {
+        if self.is_empty() {
+            return Ok(template.to_string());
+        }
+
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('$') {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = after
+                .find('$')
+                .ok_or_else(|| Error::InvalidUrl(format!("Unterminated identifier: {}", rest)))?;
+
+            let identifier = &after[..end];
+            if identifier.is_empty() {
+                // `$$` is an escaped dollar sign
+                result.push('$');
+            } else {
+                result.push_str(&self.substitute(identifier)?);
+            }
+            rest = &after[end + 1..];
+        }
+        result.push_str(rest);
+
+        Ok(result)
+    }
+
+    /// Substitute one identifier, with an optional `%0<width>d` format tag.
+    fn substitute(&self, identifier: &str) -> Result<String> {
+        let (name, format) = match identifier.split_once('%') {
+            Some((name, format)) => (name, Some(format)),
+            None => (identifier, None),
+        };
+
+        let value = match name {
+            "RepresentationID" => self.rid.as_deref(),
+            "Number" => self.num.as_deref(),
+            "Bandwidth" => self.bw.as_deref(),
+            "Time" => self.time.as_deref(),
+            "SubNumber" => self.sub.as_deref(),
+            _ => {
+                return Err(Error::InvalidUrl(format!(
+                    "Unknown template identifier: ${}$",
+                    identifier
+                )));
+            }
+        }
+        .ok_or_else(|| Error::InvalidUrl(format!("Missing value for ${}$", name)))?;
+
+        if !Self::is_valid_value(name, value) {
+            return Err(Error::InvalidUrl(format!("Invalid value for ${}$", name)));
+        }
+
+        let Some(format) = format else {
+            return Ok(value.to_string());
+        };
+
+        let width = format
+            .strip_prefix('0')
+            .and_then(|f| f.strip_suffix('d'))
+            .and_then(|w| w.parse::<usize>().ok())
+            .ok_or_else(|| Error::InvalidUrl(format!("Invalid format tag: ${}$", identifier)))?;
+        let number = value
+            .parse::<u64>()
+            .map_err(|_| Error::InvalidUrl(format!("Non-numeric value for ${}$", name)))?;
+
+        Ok(format!("{:0width$}", number, width = width))
+    }
+
+    /// Check a value filled in by the player, so it cannot reach beyond its
+    /// identifier into the rest of the URL.
+    ///
+    /// Numeric identifiers must be digits. Representation IDs must not
+    /// contain path, query, fragment or escape characters, nor `..`.
+    fn is_valid_value(name: &str, value: &str) -> bool {
+        if name == "RepresentationID" {
+            return !value.is_empty()
+                && !value.contains("..")
+                && !value
+                    .chars()
+                    .any(|c| matches!(c, '/' | '\\' | '?' | '#' | '%') || c.is_control());
+        }
+        !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    /// Iterate over the `(name, format)` identifiers of a template.
+    fn identifiers(template: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+        template
+            .split('$')
+            .skip(1)
+            .step_by(2)
+            .filter(|id| !id.is_empty())
+            .map(|id| match id.split_once('%') {
+                Some((name, format)) => (name, Some(format)),
+                None => (id, None),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_params() {
+        let params = TemplateValues::query_params(&[
+            "https://cdn.example.com/$RepresentationID$/seg-$Number%05d$.m4s",
+            "https://cdn.example.com/$RepresentationID$/init.mp4",
+        ]);
+        assert_eq!(params, "&rid=$RepresentationID$&num=$Number$");
+
+        assert_eq!(TemplateValues::query_params(&["https://a/b.mp4"]), "");
+    }
+
+    #[test]
+    fn test_expand() {
+        let values = TemplateValues {
+            rid: Some("video-1".to_string()),
+            num: Some("42".to_string()),
+            ..Default::default()
+        };
+
+        let url = values
+            .expand("https://cdn.example.com/$RepresentationID$/seg-$Number%05d$.m4s?a=$$")
+            .unwrap();
+        assert_eq!(url, "https://cdn.example.com/video-1/seg-00042.m4s?a=$");
+    }
+
+    #[test]
+    fn test_expand_missing_value() {
+        let values = TemplateValues {
+            num: Some("1".to_string()),
+            ..Default::default()
+        };
+
+        assert!(values.expand("https://a/$Time$.m4s").is_err());
+    }
+
+    #[test]
+    fn test_expand_rejects_unsafe_values() {
+        let template = "https://a/$RepresentationID$/$Number$.m4s";
+        let expand = |rid: &str, num: &str| {
+            TemplateValues {
+                rid: Some(rid.to_string()),
+                num: Some(num.to_string()),
+                ..Default::default()
+            }
+            .expand(template)
+        };
+
+        assert!(expand("video_1.5", "7").is_ok());
+        for (rid, num) in [
+            ("..", "1"),
+            ("a/../b", "1"),
+            ("a?x=1", "1"),
+            ("a#x", "1"),
+            ("a%2F", "1"),
+            ("a\\b", "1"),
+            ("a", "1/../x"),
+            ("a", "-1"),
+            ("a", ""),
+        ] {
+            assert!(expand(rid, num).is_err(), "{} {}", rid, num);
+        }
+    }
+
+    #[test]
+    fn test_expand_without_values_is_unchanged() {
+        let values = TemplateValues::default();
+        assert_eq!(values.expand("https://a/$x.ts").unwrap(), "https://a/$x.ts");
+    }
+}
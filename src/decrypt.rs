@@ -1,5 +1,7 @@
 pub mod decryptor;
+pub mod fetcher;
 pub mod key;
 
-pub use decryptor::{SegmentDecryptMethod, SegmentDecryptor};
+pub use decryptor::{ByteStream, SegmentDecryptMethod, SegmentDecryptor};
+pub use fetcher::KeyFetcher;
 pub use key::DecryptionKey;
@@ -1,6 +1,13 @@
 use crate::{Error, Result, hls::SegmentFormat};
+use aes::cipher::{BlockDecryptMut, KeyIvInit, block_padding::Pkcs7};
 use bytes::Bytes;
-use std::io::Cursor;
+use futures::{Stream, StreamExt, TryStreamExt, stream::BoxStream};
+use std::{
+    io::{self, Cursor, Write},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+use tokio_util::io::{StreamReader, SyncIoBridge};
 
 use super::DecryptionKey;
 
@@ -13,6 +20,8 @@ pub enum SegmentDecryptMethod {
     SampleAesCtr,
     /// Common Encryption (fMP4).
     Cenc,
+    /// Whole-segment AES-128-CBC with PKCS7 padding.
+    Aes128,
 }
 
 impl SegmentDecryptMethod {
@@ -22,6 +31,7 @@ impl SegmentDecryptMethod {
             "ssa" => Ok(Self::SampleAes),
             "ssa-ctr" => Ok(Self::SampleAesCtr),
             "cenc" => Ok(Self::Cenc),
+            "aes" | "aes-128" => Ok(Self::Aes128),
             other => Err(Error::UnsupportedMethod(other.to_string())),
         }
     }
@@ -31,10 +41,22 @@ impl SegmentDecryptMethod {
             Self::SampleAes => "ssa",
             Self::SampleAesCtr => "ssa-ctr",
             Self::Cenc => "cenc",
+            Self::Aes128 => "aes",
         }
     }
+
+    /// Returns true if decryption needs the init segment prepended.
+    pub fn uses_init_segment(&self) -> bool {
+        matches!(self, Self::SampleAesCtr | Self::Cenc)
+    }
 }
 
+/// Stream of segment bytes produced while the upstream body is still arriving.
+pub type ByteStream = BoxStream<'static, Result<Bytes>>;
+
+/// Size of the chunks emitted by [`SegmentDecryptor::decrypt_stream`].
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
 /// Decryptor that wraps iori-ssa and mp4decrypt.
 pub struct SegmentDecryptor {
     method: SegmentDecryptMethod,
@@ -51,6 +73,7 @@ impl SegmentDecryptor {
     ///
     /// For SAMPLE-AES (MPEG-TS/AAC): uses iori-ssa.
     /// For CENC (fMP4): uses mp4decrypt.
+    /// For AES-128: decrypts the whole segment, regardless of format.
     pub async fn decrypt(
         &self,
         data: Bytes,
@@ -68,6 +91,7 @@ impl SegmentDecryptor {
                 SegmentDecryptMethod::SampleAesCtr | SegmentDecryptMethod::Cenc,
                 SegmentFormat::Mp4,
             ) => self.decrypt_cenc(data, init_segment).await,
+            (SegmentDecryptMethod::Aes128, _) => self.decrypt_aes128(data),
             _ => Err(Error::UnsupportedCombination {
                 method: self.method.as_str().to_string(),
                 format: format.as_str().to_string(),
@@ -105,6 +129,80 @@ impl SegmentDecryptor {
         Ok(Bytes::from(output))
     }
 
+    /// Returns true if [`Self::decrypt_stream`] can handle this format.
+    ///
+    /// Only MPEG-TS SAMPLE-AES is packet based; everything else needs the
+    /// whole segment at once.
+    pub fn supports_streaming(&self, format: SegmentFormat) -> bool {
+        matches!(
+            (self.method, format),
+            (SegmentDecryptMethod::SampleAes, SegmentFormat::MpegTS)
+        )
+    }
+
+    /// Decrypt a segment incrementally as its bytes arrive.
+    ///
+    /// Decryption runs on a blocking thread, reading from `input` and
+    /// emitting decrypted chunks as they become available.
+    pub fn decrypt_stream<S>(&self, input: S, format: SegmentFormat) -> Result<ByteStream>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static,
+    {
+        if !self.supports_streaming(format) {
+            return Err(Error::UnsupportedCombination {
+                method: self.method.as_str().to_string(),
+                format: format.as_str().to_string(),
+            });
+        }
+
+        let key = *self.key.require_single()?;
+        let iv = self.iv;
+
+        // iori-ssa stops quietly on read errors, so remember them here
+        let upstream_error = Arc::new(Mutex::new(None));
+        let input = {
+            let upstream_error = upstream_error.clone();
+            input.inspect_err(move |e| *upstream_error.lock().unwrap() = Some(e.to_string()))
+        };
+        let reader = SyncIoBridge::new(StreamReader::new(input));
+        let (tx, rx) = mpsc::channel(8);
+
+        tokio::task::spawn_blocking(move || {
+            let mut writer = ChannelWriter::new(tx.clone());
+            let mut result = iori_ssa::decrypt_mpegts(reader, &mut writer, key, iv)
+                .map_err(|e| Error::DecryptionFailed(e.to_string()))
+                .and_then(|()| writer.finish());
+
+            if let Some(e) = upstream_error.lock().unwrap().take() {
+                result = Err(Error::DecryptionFailed(format!(
+                    "Upstream read failed: {}",
+                    e
+                )));
+            }
+            if let Err(e) = result {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        Ok(futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        })
+        .boxed())
+    }
+
+    fn decrypt_aes128(&self, data: Bytes) -> Result<Bytes> {
+        let key = self.key.require_single()?;
+
+        let mut buf = data.to_vec();
+        let len = cbc::Decryptor::<aes::Aes128>::new(key.into(), &self.iv.into())
+            .decrypt_padded_mut::<Pkcs7>(&mut buf)
+            .map_err(|e| Error::DecryptionFailed(e.to_string()))?
+            .len();
+        buf.truncate(len);
+
+        Ok(Bytes::from(buf))
+    }
+
     async fn decrypt_cenc(&self, data: Bytes, init_segment: Option<Bytes>) -> Result<Bytes> {
         let keys = self.key.to_mp4decrypt_keys()?;
 
@@ -128,3 +226,175 @@ impl SegmentDecryptor {
         Ok(Bytes::from(decrypted))
     }
 }
+
+/// `Write` adapter that sends output to an async channel in chunks.
+struct ChannelWriter {
+    tx: mpsc::Sender<Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(tx: mpsc::Sender<Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        }
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        // The receiver is gone when the client disconnected
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    /// Send any buffered output.
+    fn finish(mut self) -> Result<()> {
+        self.send_chunk()
+            .map_err(|e| Error::DecryptionFailed(e.to_string()))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= STREAM_CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Chunks are sent once full; `finish` sends the remainder
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    #[test]
+    fn test_parse_aes128_method() {
+        assert_eq!(
+            SegmentDecryptMethod::parse("aes").unwrap(),
+            SegmentDecryptMethod::Aes128
+        );
+        assert_eq!(
+            SegmentDecryptMethod::parse("AES-128").unwrap(),
+            SegmentDecryptMethod::Aes128
+        );
+        assert!(!SegmentDecryptMethod::Aes128.uses_init_segment());
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_aes128() {
+        let key = [0x11u8; 16];
+        let iv = [0x22u8; 16];
+        let plaintext = b"segment payload".repeat(7);
+
+        let mut encrypted = plaintext.clone();
+        encrypted.resize((plaintext.len() / 16 + 1) * 16, 0);
+        cbc::Encryptor::<aes::Aes128>::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut encrypted, plaintext.len())
+            .unwrap();
+
+        let decryptor =
+            SegmentDecryptor::new(SegmentDecryptMethod::Aes128, DecryptionKey::Single(key), iv);
+        let decrypted = decryptor
+            .decrypt(Bytes::from(encrypted), None, SegmentFormat::MpegTS)
+            .await
+            .unwrap();
+
+        assert_eq!(decrypted.as_ref(), plaintext.as_slice());
+    }
+
+    /// MPEG-TS null packets, which carry no encrypted payload.
+    fn null_packets(count: usize) -> Vec<u8> {
+        let mut packet = [0xFFu8; 188];
+        packet[..4].copy_from_slice(&[0x47, 0x1F, 0xFF, 0x10]);
+        packet.repeat(count)
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_stream_matches_buffered() {
+        let data = Bytes::from(null_packets(1000));
+        let decryptor = SegmentDecryptor::new(
+            SegmentDecryptMethod::SampleAes,
+            DecryptionKey::Single([0u8; 16]),
+            [0u8; 16],
+        );
+
+        let buffered = decryptor
+            .decrypt(data.clone(), None, SegmentFormat::MpegTS)
+            .await
+            .unwrap();
+
+        // Feed the input in uneven chunks
+        let chunks: Vec<io::Result<Bytes>> = data
+            .chunks(1000)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        let streamed: Vec<Bytes> = decryptor
+            .decrypt_stream(futures::stream::iter(chunks), SegmentFormat::MpegTS)
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(streamed.len() > 1);
+        assert_eq!(streamed.concat(), buffered.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_stream_reports_upstream_error() {
+        let decryptor = SegmentDecryptor::new(
+            SegmentDecryptMethod::SampleAes,
+            DecryptionKey::Single([0u8; 16]),
+            [0u8; 16],
+        );
+        let chunks = vec![
+            Ok(Bytes::from(null_packets(2))),
+            Err(io::Error::other("connection reset")),
+        ];
+
+        let result: Result<Vec<Bytes>> = decryptor
+            .decrypt_stream(futures::stream::iter(chunks), SegmentFormat::MpegTS)
+            .unwrap()
+            .try_collect()
+            .await;
+
+        assert!(matches!(result, Err(Error::DecryptionFailed(_))));
+    }
+
+    #[test]
+    fn test_supports_streaming() {
+        let key = DecryptionKey::Single([0u8; 16]);
+        let ssa = SegmentDecryptor::new(SegmentDecryptMethod::SampleAes, key.clone(), [0u8; 16]);
+        let cenc = SegmentDecryptor::new(SegmentDecryptMethod::Cenc, key, [0u8; 16]);
+
+        assert!(ssa.supports_streaming(SegmentFormat::MpegTS));
+        assert!(!ssa.supports_streaming(SegmentFormat::Aac));
+        assert!(!cenc.supports_streaming(SegmentFormat::Mp4));
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_aes128_wrong_key() {
+        let decryptor = SegmentDecryptor::new(
+            SegmentDecryptMethod::Aes128,
+            DecryptionKey::Single([0u8; 16]),
+            [0u8; 16],
+        );
+        let result = decryptor
+            .decrypt(Bytes::from_static(&[0u8; 15]), None, SegmentFormat::Aac)
+            .await;
+
+        assert!(matches!(result, Err(Error::DecryptionFailed(_))));
+    }
+}
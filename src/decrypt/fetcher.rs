@@ -0,0 +1,235 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use lru::LruCache;
+use std::{collections::HashMap, num::NonZeroUsize, sync::Mutex};
+
+use crate::{
+    Error, Result,
+    cache::{SingleFlight, headers_hash},
+    proxy::ProxyClient,
+};
+
+use super::DecryptionKey;
+
+/// Fetches AES-128 / SAMPLE-AES keys from `#EXT-X-KEY` URIs.
+///
+/// Supports `http(s)://` URIs (fetched with the segment headers) and
+/// `data:` URIs. Keys are cached per URI and header set, and
+/// concurrent misses for them share one fetch.
+pub struct KeyFetcher {
+    cache: Mutex<LruCache<KeyCacheKey, [u8; 16]>>,
+    in_flight: SingleFlight<KeyCacheKey, [u8; 16]>,
+}
+
+/// Cache key for fetched keys.
+///
+/// Keys fetched with credentials must not be served to requests without
+/// them, so the request headers are part of the key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct KeyCacheKey {
+    uri: String,
+    headers_hash: [u8; 32],
+}
+
+impl KeyCacheKey {
+    fn new(uri: &str, headers: &HashMap<String, String>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            headers_hash: headers_hash(headers),
+        }
+    }
+}
+
+impl KeyFetcher {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(max_entries).expect("max_entries must be > 0"),
+            )),
+            in_flight: SingleFlight::new(),
+        }
+    }
+
+    /// Get the key for a URI from cache or acquire it with `headers`.
+    pub async fn get_or_fetch(
+        &self,
+        uri: &str,
+        headers: &HashMap<String, String>,
+        client: &ProxyClient,
+    ) -> Result<DecryptionKey> {
+        let cache_key = KeyCacheKey::new(uri, headers);
+
+        // Check cache first
+        if let Some(key) = self.get(&cache_key) {
+            tracing::debug!("Key cache hit: {}", uri);
+            return Ok(DecryptionKey::Single(key));
+        }
+
+        let key = self
+            .in_flight
+            .run(&cache_key, || async {
+                // Another call may have filled the cache since we checked
+                if let Some(key) = self.get(&cache_key) {
+                    return Ok(key);
+                }
+
+                tracing::debug!("Key cache miss, fetching: {}", uri);
+                let key = Self::acquire(uri, headers, client).await?;
+
+                // Store in cache
+                let mut cache = self.cache.lock().unwrap();
+                cache.put(cache_key.clone(), key);
+
+                Ok(key)
+            })
+            .await?;
+
+        Ok(DecryptionKey::Single(key))
+    }
+
+    fn get(&self, key: &KeyCacheKey) -> Option<[u8; 16]> {
+        let mut cache = self.cache.lock().unwrap();
+        cache.get(key).copied()
+    }
+
+    async fn acquire(
+        uri: &str,
+        headers: &HashMap<String, String>,
+        client: &ProxyClient,
+    ) -> Result<[u8; 16]> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            return Self::to_key(&Self::decode_data_uri(data)?);
+        }
+
+        if uri.starts_with("http://") || uri.starts_with("https://") {
+            return Self::to_key(&client.fetch(uri, Some(headers), None).await?);
+        }
+
+        Err(Error::InvalidUrl(format!("Unsupported key URI: {}", uri)))
+    }
+
+    /// Decode the part of a `data:` URI after the scheme.
+    fn decode_data_uri(data: &str) -> Result<Vec<u8>> {
+        let (meta, payload) = data
+            .split_once(',')
+            .ok_or_else(|| Error::InvalidKeyFormat("Malformed data URI".to_string()))?;
+
+        if meta.ends_with(";base64") {
+            STANDARD
+                .decode(payload)
+                .map_err(|e| Error::InvalidKeyFormat(e.to_string()))
+        } else {
+            Ok(urlencoding::decode_binary(payload.as_bytes()).into_owned())
+        }
+    }
+
+    fn to_key(bytes: &[u8]) -> Result<[u8; 16]> {
+        bytes.try_into().map_err(|_| Error::InvalidKeyLength)
+    }
+
+    /// Get current cache size.
+    pub fn len(&self) -> usize {
+        let cache = self.cache.lock().unwrap();
+        cache.len()
+    }
+
+    /// Check if cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for KeyFetcher {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::HeaderMap, routing::get};
+
+    const KEY_HEX: &str = "0123456789abcdef0123456789abcdef";
+
+    fn key() -> [u8; 16] {
+        hex::decode(KEY_HEX).unwrap().try_into().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_data_uri_base64() {
+        let fetcher = KeyFetcher::default();
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            STANDARD.encode(key())
+        );
+
+        let result = fetcher
+            .get_or_fetch(&uri, &HashMap::new(), &ProxyClient::local())
+            .await
+            .unwrap();
+
+        assert_eq!(result.as_single(), Some(&key()));
+        assert_eq!(fetcher.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_data_uri_wrong_length() {
+        let fetcher = KeyFetcher::default();
+        let result = fetcher
+            .get_or_fetch("data:,short", &HashMap::new(), &ProxyClient::local())
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidKeyLength)));
+        assert!(fetcher.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_skd_uri() {
+        let fetcher = KeyFetcher::default();
+        let result = fetcher
+            .get_or_fetch(
+                &format!("skd://{}", KEY_HEX),
+                &HashMap::new(),
+                &ProxyClient::local(),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::InvalidUrl(_))));
+    }
+
+    #[tokio::test]
+    async fn test_http_key_uses_headers_and_cache() {
+        let app = Router::new().route(
+            "/key",
+            get(|headers: HeaderMap| async move {
+                if headers.get("authorization").is_some() {
+                    Ok(key().to_vec())
+                } else {
+                    Err(axum::http::StatusCode::FORBIDDEN)
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}/key", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let fetcher = KeyFetcher::default();
+        let client = ProxyClient::local();
+
+        let missing = fetcher.get_or_fetch(&uri, &HashMap::new(), &client).await;
+        assert!(matches!(missing, Err(Error::FetchFailed { .. })));
+
+        let headers = HashMap::from([("Authorization".to_string(), "Bearer t".to_string())]);
+        let result = fetcher.get_or_fetch(&uri, &headers, &client).await.unwrap();
+        assert_eq!(result.as_single(), Some(&key()));
+
+        // Cached for the same headers only, so requests without the
+        // credentials still reach the origin
+        let cached = fetcher.get_or_fetch(&uri, &headers, &client).await.unwrap();
+        assert_eq!(cached.as_single(), Some(&key()));
+        assert_eq!(fetcher.len(), 1);
+
+        let missing = fetcher.get_or_fetch(&uri, &HashMap::new(), &client).await;
+        assert!(matches!(missing, Err(Error::FetchFailed { .. })));
+    }
+}
@@ -5,7 +5,7 @@ use axum::{
 };
 use serde::Serialize;
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum Error {
     #[error("Failed to fetch URL: {url} - {reason}")]
     FetchFailed { url: String, reason: String },
@@ -19,12 +19,24 @@ pub enum Error {
     #[error("Invalid or missing URL signature")]
     InvalidSignature,
 
+    #[error("URL signature has expired")]
+    SignatureExpired,
+
+    #[error("Invalid or tampered URL token")]
+    InvalidToken,
+
+    #[error("Destination not allowed: {0}")]
+    DestinationForbidden(String),
+
     #[error("Invalid key format: {0}")]
     InvalidKeyFormat(String),
 
     #[error("Invalid key length: expected 16 bytes")]
     InvalidKeyLength,
 
+    #[error("Decryption key required: provide k or ku")]
+    KeyRequired,
+
     #[error("Single key required but multiple keys provided")]
     SingleKeyRequired,
 
@@ -52,10 +64,25 @@ pub enum Error {
     #[error("Unknown segment format: {0}")]
     UnknownSegmentFormat(String),
 
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
+/// Error details attached to the extensions of error responses.
+///
+/// Lets middleware (e.g. request logging) see which error produced a response.
+#[derive(Debug, Clone)]
+pub struct ErrorInfo {
+    pub code: &'static str,
+    pub message: String,
+}
+
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
@@ -63,14 +90,19 @@ struct ErrorResponse {
 }
 
 impl Error {
-    fn error_code(&self) -> &'static str {
+    /// Stable machine-readable code for this error.
+    pub fn error_code(&self) -> &'static str {
         match self {
             Self::FetchFailed { .. } => "FETCH_FAILED",
             Self::FetchTimeout(_) => "FETCH_TIMEOUT",
             Self::InvalidUrl(_) => "INVALID_URL",
             Self::InvalidSignature => "INVALID_SIGNATURE",
+            Self::SignatureExpired => "SIGNATURE_EXPIRED",
+            Self::InvalidToken => "INVALID_TOKEN",
+            Self::DestinationForbidden(_) => "DESTINATION_FORBIDDEN",
             Self::InvalidKeyFormat(_) => "INVALID_KEY_FORMAT",
             Self::InvalidKeyLength => "INVALID_KEY_LENGTH",
+            Self::KeyRequired => "KEY_REQUIRED",
             Self::SingleKeyRequired => "SINGLE_KEY_REQUIRED",
             Self::MultipleKeysRequired => "MULTIPLE_KEYS_REQUIRED",
             Self::UnsupportedMethod(_) => "UNSUPPORTED_METHOD",
@@ -80,24 +112,32 @@ impl Error {
             Self::InvalidByteRange(_) => "INVALID_BYTE_RANGE",
             Self::InvalidIv(_) => "INVALID_IV",
             Self::UnknownSegmentFormat(_) => "UNKNOWN_SEGMENT_FORMAT",
+            Self::InvalidManifest(_) => "INVALID_MANIFEST",
+            Self::InvalidParameter(_) => "INVALID_PARAMETER",
             Self::Internal(_) => "INTERNAL_ERROR",
         }
     }
 
-    fn status_code(&self) -> StatusCode {
+    /// HTTP status code returned for this error.
+    pub fn status_code(&self) -> StatusCode {
         match self {
-            Self::FetchFailed { .. } => StatusCode::BAD_GATEWAY,
+            Self::FetchFailed { .. } | Self::InvalidManifest(_) => StatusCode::BAD_GATEWAY,
             Self::FetchTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
-            Self::InvalidSignature => StatusCode::FORBIDDEN,
+            Self::InvalidSignature
+            | Self::SignatureExpired
+            | Self::InvalidToken
+            | Self::DestinationForbidden(_) => StatusCode::FORBIDDEN,
             Self::InvalidUrl(_)
             | Self::InvalidKeyFormat(_)
             | Self::InvalidKeyLength
+            | Self::KeyRequired
             | Self::SingleKeyRequired
             | Self::MultipleKeysRequired
             | Self::InvalidHeaderEncoding(_)
             | Self::InvalidByteRange(_)
             | Self::InvalidIv(_)
-            | Self::UnknownSegmentFormat(_) => StatusCode::BAD_REQUEST,
+            | Self::UnknownSegmentFormat(_)
+            | Self::InvalidParameter(_) => StatusCode::BAD_REQUEST,
             Self::UnsupportedMethod(_) | Self::UnsupportedCombination { .. } => {
                 StatusCode::NOT_IMPLEMENTED
             }
@@ -109,11 +149,18 @@ impl Error {
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let status = self.status_code();
+        let info = ErrorInfo {
+            code: self.error_code(),
+            message: self.to_string(),
+        };
         let body = ErrorResponse {
-            error: self.to_string(),
-            code: self.error_code().to_string(),
+            error: info.message.clone(),
+            code: info.code.to_string(),
         };
-        (status, Json(body)).into_response()
+
+        let mut response = (status, Json(body)).into_response();
+        response.extensions_mut().insert(info);
+        response
     }
 }
 
@@ -141,3 +188,9 @@ impl From<hex::FromHexError> for Error {
         Self::InvalidKeyFormat(e.to_string())
     }
 }
+
+impl From<quick_xml::Error> for Error {
+    fn from(e: quick_xml::Error) -> Self {
+        Self::InvalidManifest(e.to_string())
+    }
+}
@@ -1,3 +1,4 @@
+pub mod attributes;
 pub mod byterange;
 pub mod key;
 pub mod segment;
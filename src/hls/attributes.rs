@@ -0,0 +1,82 @@
+//! Attribute lists of HLS tags.
+
+/// Split an attribute list into its `NAME=VALUE` attributes.
+///
+/// Commas inside quoted strings do not separate attributes.
+pub fn split(s: &str) -> Vec<&str> {
+    let mut attrs = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+
+    for (i, c) in s.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                attrs.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if start < s.len() {
+        attrs.push(s[start..].trim());
+    }
+
+    attrs
+}
+
+/// Rebuild a tag, rewriting only its `URI` attribute.
+///
+/// `prefix` is the tag name with its colon, e.g. `#EXT-X-KEY:`. `rewrite`
+/// gets the unquoted URI; when it returns `None`, the URI is kept.
+pub fn rewrite_uri(
+    prefix: &str,
+    content: &str,
+    mut rewrite: impl FnMut(&str) -> Option<String>,
+) -> String {
+    let attrs: Vec<String> = split(content)
+        .into_iter()
+        .map(|attr| {
+            if let Some((key, value)) = attr.split_once('=')
+                && key.trim().eq_ignore_ascii_case("URI")
+                && let Some(uri) = rewrite(value.trim().trim_matches('"'))
+            {
+                return format!("URI=\"{}\"", uri);
+            }
+            attr.to_string()
+        })
+        .collect();
+
+    format!("{}{}", prefix, attrs.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            split(r#"METHOD=AES-128,URI="key?a=1,b=2", IV=0x01"#),
+            vec!["METHOD=AES-128", r#"URI="key?a=1,b=2""#, "IV=0x01"]
+        );
+        assert!(split("").is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_uri() {
+        let line = rewrite_uri(
+            "#EXT-X-MEDIA:",
+            r#"TYPE=AUDIO,uri="a,b.m3u8",NAME="x""#,
+            |uri| Some(format!("/manifest?url={}", uri)),
+        );
+        assert_eq!(
+            line,
+            r#"#EXT-X-MEDIA:TYPE=AUDIO,URI="/manifest?url=a,b.m3u8",NAME="x""#
+        );
+
+        let kept = rewrite_uri("#EXT-X-KEY:", r#"METHOD=NONE,URI="data:x""#, |_| None);
+        assert_eq!(kept, r#"#EXT-X-KEY:METHOD=NONE,URI="data:x""#);
+    }
+}
@@ -52,6 +52,56 @@ impl ByteRange {
         }
     }
 
+    /// Parse a client `Range` header against a resource of `total` bytes.
+    ///
+    /// Supports a single `bytes=` range in the `first-last`, `first-` and
+    /// `-suffix` forms. Returns `Ok(None)` if the range is unsatisfiable and
+    /// an error if the header is malformed (which clients should ignore).
+    pub fn from_range_header(value: &str, total: u64) -> Result<Option<Self>> {
+        let invalid = || Error::InvalidByteRange(value.to_string());
+
+        let spec = value.trim().strip_prefix("bytes=").ok_or_else(invalid)?;
+        // Multiple ranges are not supported
+        if spec.contains(',') {
+            return Err(invalid());
+        }
+        let (first, last) = spec.split_once('-').ok_or_else(invalid)?;
+        let (first, last) = (first.trim(), last.trim());
+
+        if first.is_empty() {
+            // Suffix range: the last N bytes
+            let suffix: u64 = last.parse().map_err(|_| invalid())?;
+            if suffix == 0 || total == 0 {
+                return Ok(None);
+            }
+            let length = suffix.min(total);
+            return Ok(Some(Self::new(length, Some(total - length))));
+        }
+
+        let first: u64 = first.parse().map_err(|_| invalid())?;
+        if first >= total {
+            return Ok(None);
+        }
+
+        let last = if last.is_empty() {
+            total - 1
+        } else {
+            let last: u64 = last.parse().map_err(|_| invalid())?;
+            if last < first {
+                return Err(invalid());
+            }
+            last.min(total - 1)
+        };
+
+        Ok(Some(Self::new(last - first + 1, Some(first))))
+    }
+
+    /// Convert to HTTP Content-Range header value within `total` bytes.
+    pub fn to_content_range(&self, total: u64) -> String {
+        let offset = self.offset.unwrap_or(0);
+        format!("bytes {}-{}/{}", offset, offset + self.length - 1, total)
+    }
+
     /// Convert to query parameter format.
     pub fn to_query_param(&self) -> String {
         match self.offset {
@@ -98,6 +148,47 @@ mod tests {
         assert_eq!(br.to_range_header(), "bytes=500-1499");
     }
 
+    #[test]
+    fn test_from_range_header() {
+        let br = ByteRange::from_range_header("bytes=100-199", 1000).unwrap();
+        assert_eq!(br, Some(ByteRange::new(100, Some(100))));
+
+        let br = ByteRange::from_range_header("bytes=900-", 1000).unwrap();
+        assert_eq!(br, Some(ByteRange::new(100, Some(900))));
+
+        let br = ByteRange::from_range_header("bytes=-50", 1000).unwrap();
+        assert_eq!(br, Some(ByteRange::new(50, Some(950))));
+
+        // Last byte is clamped to the resource length
+        let br = ByteRange::from_range_header("bytes=500-5000", 1000).unwrap();
+        assert_eq!(br, Some(ByteRange::new(500, Some(500))));
+    }
+
+    #[test]
+    fn test_from_range_header_unsatisfiable() {
+        assert_eq!(
+            ByteRange::from_range_header("bytes=1000-", 1000).unwrap(),
+            None
+        );
+        assert_eq!(
+            ByteRange::from_range_header("bytes=-0", 1000).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_range_header_malformed() {
+        assert!(ByteRange::from_range_header("items=0-10", 1000).is_err());
+        assert!(ByteRange::from_range_header("bytes=0-10,20-30", 1000).is_err());
+        assert!(ByteRange::from_range_header("bytes=20-10", 1000).is_err());
+    }
+
+    #[test]
+    fn test_to_content_range() {
+        let br = ByteRange::new(100, Some(900));
+        assert_eq!(br.to_content_range(1000), "bytes 900-999/1000");
+    }
+
     #[test]
     fn test_to_query_param() {
         let br = ByteRange::new(1000, Some(500));
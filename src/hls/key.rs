@@ -1,3 +1,5 @@
+use super::attributes;
+
 /// Represents an HLS encryption method.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KeyMethod {
@@ -31,6 +33,12 @@ impl KeyMethod {
         )
     }
 
+    /// Returns true if the key for this method can be fetched from the key URI.
+    /// DRM methods (CTR/CENC) carry license data in their URIs instead.
+    pub fn supports_key_fetch(&self) -> bool {
+        matches!(self, Self::Aes128 | Self::SampleAes)
+    }
+
     /// Returns true if clients can handle this encryption natively.
     pub fn is_client_supported(&self) -> bool {
         matches!(self, Self::None | Self::Aes128)
@@ -42,6 +50,7 @@ impl KeyMethod {
             Self::SampleAes => Some("ssa"),
             Self::SampleAesCtr => Some("ssa-ctr"),
             Self::SampleAesCenc => Some("cenc"),
+            Self::Aes128 => Some("aes"),
             _ => None,
         }
     }
@@ -69,9 +78,11 @@ pub struct KeyInfo {
 }
 
 impl KeyInfo {
-    /// Parse from #EXT-X-KEY tag line.
+    /// Parse from #EXT-X-KEY or #EXT-X-SESSION-KEY tag line.
     pub fn parse(line: &str) -> Option<Self> {
-        let content = line.strip_prefix("#EXT-X-KEY:")?;
+        let content = line
+            .strip_prefix("#EXT-X-KEY:")
+            .or_else(|| line.strip_prefix("#EXT-X-SESSION-KEY:"))?;
 
         let mut method = KeyMethod::None;
         let mut uri = None;
@@ -80,7 +91,7 @@ impl KeyInfo {
         let mut keyformatversions = None;
 
         // Simple attribute parser
-        for attr in Self::parse_attributes(content) {
+        for attr in attributes::split(content) {
             let (key, value) = attr.split_once('=')?;
             let key = key.trim().to_uppercase();
             let value = value.trim().trim_matches('"');
@@ -120,30 +131,6 @@ impl KeyInfo {
         }
     }
 
-    /// Simple attribute parser that handles quoted values.
-    fn parse_attributes(s: &str) -> Vec<&str> {
-        let mut attrs = Vec::new();
-        let mut start = 0;
-        let mut in_quotes = false;
-
-        for (i, c) in s.char_indices() {
-            match c {
-                '"' => in_quotes = !in_quotes,
-                ',' if !in_quotes => {
-                    attrs.push(s[start..i].trim());
-                    start = i + 1;
-                }
-                _ => {}
-            }
-        }
-
-        if start < s.len() {
-            attrs.push(s[start..].trim());
-        }
-
-        attrs
-    }
-
     /// Check if this key requires server-side decryption.
     pub fn requires_server_decrypt(&self) -> bool {
         self.method.requires_server_decrypt()
@@ -169,6 +156,14 @@ mod tests {
         assert!(KeyMethod::SampleAesCenc.requires_server_decrypt());
     }
 
+    #[test]
+    fn test_key_method_supports_key_fetch() {
+        assert!(KeyMethod::Aes128.supports_key_fetch());
+        assert!(KeyMethod::SampleAes.supports_key_fetch());
+        assert!(!KeyMethod::SampleAesCenc.supports_key_fetch());
+        assert!(!KeyMethod::None.supports_key_fetch());
+    }
+
     #[test]
     fn test_key_info_parse() {
         let line = r#"#EXT-X-KEY:METHOD=SAMPLE-AES,URI="https://example.com/key",IV=0x00000000000000000000000000000001"#;
@@ -177,4 +172,12 @@ mod tests {
         assert_eq!(info.uri, Some("https://example.com/key".to_string()));
         assert!(info.iv.is_some());
     }
+
+    #[test]
+    fn test_session_key_parse() {
+        let line = r#"#EXT-X-SESSION-KEY:METHOD=SAMPLE-AES,URI="skd://abc",KEYFORMAT="com.apple.streamingkeydelivery""#;
+        let info = KeyInfo::parse(line).unwrap();
+        assert_eq!(info.method, KeyMethod::SampleAes);
+        assert_eq!(info.uri.as_deref(), Some("skd://abc"));
+    }
 }
@@ -1,3 +1,5 @@
+use super::attributes;
+
 /// Represents parsed stream information from #EXT-X-STREAM-INF tag.
 #[derive(Debug, Clone, Default)]
 pub struct StreamInfo {
@@ -22,7 +24,7 @@ impl StreamInfo {
 
         let mut info = Self::default();
 
-        for attr in Self::parse_attributes(content) {
+        for attr in attributes::split(content) {
             if let Some((key, value)) = attr.split_once('=') {
                 let key = key.trim().to_uppercase();
                 let value = value.trim().trim_matches('"');
@@ -49,29 +51,6 @@ impl StreamInfo {
         let (w, h) = s.split_once('x')?;
         Some((w.parse().ok()?, h.parse().ok()?))
     }
-
-    fn parse_attributes(s: &str) -> Vec<&str> {
-        let mut attrs = Vec::new();
-        let mut start = 0;
-        let mut in_quotes = false;
-
-        for (i, c) in s.char_indices() {
-            match c {
-                '"' => in_quotes = !in_quotes,
-                ',' if !in_quotes => {
-                    attrs.push(s[start..i].trim());
-                    start = i + 1;
-                }
-                _ => {}
-            }
-        }
-
-        if start < s.len() {
-            attrs.push(s[start..].trim());
-        }
-
-        attrs
-    }
 }
 
 #[cfg(test)]
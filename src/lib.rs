@@ -1,4 +1,5 @@
 pub mod cache;
+pub mod dash;
 pub mod decrypt;
 pub mod error;
 pub mod hls;
@@ -1,5 +1,11 @@
+pub mod catalog;
 pub mod iceberg;
 pub mod record;
+pub mod schema;
+pub mod sink;
+pub mod writer;
 
-pub use iceberg::IcebergLogger;
+pub use iceberg::{IcebergLogger, NoOpLogger};
 pub use record::RequestLogRecord;
+pub use sink::{RequestLogSink, sink_from_env};
+pub use writer::IcebergWriter;
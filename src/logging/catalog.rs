@@ -0,0 +1,555 @@
+//! Minimal Iceberg REST catalog client.
+//!
+//! Implements the subset of the Iceberg REST catalog protocol needed to
+//! create, load and commit to the request log table (e.g. R2 Data Catalog).
+//! Operations the logger never uses return `FeatureUnsupported`.
+
+use async_trait::async_trait;
+use iceberg::{
+    Catalog, Error, ErrorKind, Namespace, NamespaceIdent, Result, TableCommit, TableCreation,
+    TableIdent, TableRequirement, TableUpdate,
+    io::FileIO,
+    spec::{SortOrder, TableMetadata, UnboundPartitionSpec},
+    table::Table,
+};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Separator for multi-level namespaces in REST paths.
+const NAMESPACE_SEPARATOR: &str = "\u{1f}";
+
+/// Catalog configuration returned by `GET /v1/config`.
+#[derive(Debug, Default, Deserialize)]
+struct CatalogConfig {
+    #[serde(default)]
+    defaults: HashMap<String, String>,
+    #[serde(default)]
+    overrides: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreateNamespaceRequest {
+    namespace: NamespaceIdent,
+    #[serde(default)]
+    properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct CreateTableRequest {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    pub schema: iceberg::spec::Schema,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partition_spec: Option<UnboundPartitionSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_order: Option<SortOrder>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CommitTableRequest {
+    pub identifier: TableIdent,
+    pub requirements: Vec<TableRequirement>,
+    pub updates: Vec<TableUpdate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LoadTableResponse {
+    #[serde(default)]
+    pub metadata_location: Option<String>,
+    pub metadata: TableMetadata,
+    #[serde(default)]
+    pub config: HashMap<String, String>,
+}
+
+/// Iceberg catalog backed by the REST catalog protocol.
+#[derive(Debug)]
+pub struct RestCatalog {
+    client: Client,
+    /// Base URL including the catalog prefix, e.g. `https://catalog/v1/{prefix}`.
+    base_url: String,
+    token: Option<String>,
+    /// FileIO properties used when loading tables (merged with catalog config).
+    file_io_props: HashMap<String, String>,
+    /// Tables as of their last load or commit. A table is only loaded again
+    /// after a commit to it conflicts.
+    tables: Mutex<HashMap<TableIdent, Table>>,
+}
+
+impl RestCatalog {
+    /// Connect to a REST catalog and resolve its configuration.
+    pub async fn connect(
+        uri: &str,
+        warehouse: &str,
+        token: Option<String>,
+        file_io_props: HashMap<String, String>,
+    ) -> Result<Self> {
+        let uri = uri.trim_end_matches('/');
+        let client = Client::new();
+
+        let mut request = client
+            .get(format!("{}/v1/config", uri))
+            .query(&[("warehouse", warehouse)]);
+        if let Some(token) = &token {
+            request = request.bearer_auth(token);
+        }
+        let config: CatalogConfig = Self::send_request(request).await?;
+
+        let prefix = config
+            .overrides
+            .get("prefix")
+            .or_else(|| config.defaults.get("prefix"))
+            .map(|p| p.trim_matches('/').to_string())
+            .unwrap_or_default();
+
+        let base_url = if prefix.is_empty() {
+            format!("{}/v1", uri)
+        } else {
+            format!("{}/v1/{}", uri, prefix)
+        };
+
+        let mut props = config.defaults;
+        props.extend(file_io_props);
+        props.extend(config.overrides);
+        props.remove("prefix");
+
+        Ok(Self {
+            client,
+            base_url,
+            token,
+            file_io_props: props,
+            tables: Mutex::default(),
+        })
+    }
+
+    fn namespace_path(namespace: &NamespaceIdent) -> String {
+        urlencoding::encode(&namespace.as_ref().join(NAMESPACE_SEPARATOR)).into_owned()
+    }
+
+    fn table_url(&self, table: &TableIdent) -> String {
+        format!(
+            "{}/namespaces/{}/tables/{}",
+            self.base_url,
+            Self::namespace_path(table.namespace()),
+            urlencoding::encode(table.name())
+        )
+    }
+
+    fn request(&self, method: Method, url: String) -> RequestBuilder {
+        let request = self.client.request(method, url);
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send_request<T: DeserializeOwned>(request: RequestBuilder) -> Result<T> {
+        let response = Self::send(request).await?;
+        response.json().await.map_err(|e| {
+            Error::new(ErrorKind::Unexpected, "Invalid REST catalog response").with_source(e)
+        })
+    }
+
+    async fn send(request: RequestBuilder) -> Result<reqwest::Response> {
+        let response = request.send().await.map_err(|e| {
+            Error::new(ErrorKind::Unexpected, "REST catalog request failed")
+                .with_source(e)
+                .with_retryable(true)
+        })?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = format!("REST catalog returned HTTP {}: {}", status, body);
+        Err(match status {
+            StatusCode::CONFLICT => {
+                Error::new(ErrorKind::CatalogCommitConflicts, message).with_retryable(true)
+            }
+            StatusCode::NOT_FOUND => Error::new(ErrorKind::TableNotFound, message),
+            s if s.is_server_error() => {
+                Error::new(ErrorKind::Unexpected, message).with_retryable(true)
+            }
+            _ => Error::new(ErrorKind::Unexpected, message),
+        })
+    }
+
+    fn build_table(&self, ident: TableIdent, response: LoadTableResponse) -> Result<Table> {
+        let mut props = self.file_io_props.clone();
+        props.extend(response.config);
+
+        let file_io = FileIO::from_path(response.metadata.location())?
+            .with_props(props)
+            .build()?;
+
+        let mut builder = Table::builder()
+            .identifier(ident)
+            .file_io(file_io)
+            .metadata(response.metadata);
+        if let Some(location) = response.metadata_location {
+            builder = builder.metadata_location(location);
+        }
+        builder.build()
+    }
+
+    fn cache_table(&self, table: &Table) {
+        self.tables
+            .lock()
+            .unwrap()
+            .insert(table.identifier().clone(), table.clone());
+    }
+
+    fn unsupported(operation: &str) -> Error {
+        Error::new(
+            ErrorKind::FeatureUnsupported,
+            format!("{} is not supported by the REST catalog client", operation),
+        )
+    }
+}
+
+#[async_trait]
+impl Catalog for RestCatalog {
+    async fn list_namespaces(
+        &self,
+        _parent: Option<&NamespaceIdent>,
+    ) -> Result<Vec<NamespaceIdent>> {
+        Err(Self::unsupported("list_namespaces"))
+    }
+
+    async fn create_namespace(
+        &self,
+        namespace: &NamespaceIdent,
+        properties: HashMap<String, String>,
+    ) -> Result<Namespace> {
+        let body = CreateNamespaceRequest {
+            namespace: namespace.clone(),
+            properties: properties.clone(),
+        };
+        let request = self
+            .request(Method::POST, format!("{}/namespaces", self.base_url))
+            .json(&body);
+
+        match Self::send(request).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::CatalogCommitConflicts => {
+                return Err(Error::new(
+                    ErrorKind::NamespaceAlreadyExists,
+                    format!("Namespace {:?} already exists", namespace),
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(Namespace::with_properties(namespace.clone(), properties))
+    }
+
+    async fn get_namespace(&self, _namespace: &NamespaceIdent) -> Result<Namespace> {
+        Err(Self::unsupported("get_namespace"))
+    }
+
+    async fn namespace_exists(&self, namespace: &NamespaceIdent) -> Result<bool> {
+        let url = format!(
+            "{}/namespaces/{}",
+            self.base_url,
+            Self::namespace_path(namespace)
+        );
+        match Self::send(self.request(Method::HEAD, url)).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::TableNotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_namespace(
+        &self,
+        _namespace: &NamespaceIdent,
+        _properties: HashMap<String, String>,
+    ) -> Result<()> {
+        Err(Self::unsupported("update_namespace"))
+    }
+
+    async fn drop_namespace(&self, _namespace: &NamespaceIdent) -> Result<()> {
+        Err(Self::unsupported("drop_namespace"))
+    }
+
+    async fn list_tables(&self, _namespace: &NamespaceIdent) -> Result<Vec<TableIdent>> {
+        Err(Self::unsupported("list_tables"))
+    }
+
+    async fn create_table(
+        &self,
+        namespace: &NamespaceIdent,
+        creation: TableCreation,
+    ) -> Result<Table> {
+        let ident = TableIdent::new(namespace.clone(), creation.name.clone());
+        let body = CreateTableRequest {
+            name: creation.name,
+            location: creation.location,
+            schema: creation.schema,
+            partition_spec: creation.partition_spec,
+            write_order: creation.sort_order,
+            properties: creation.properties,
+        };
+        let url = format!(
+            "{}/namespaces/{}/tables",
+            self.base_url,
+            Self::namespace_path(namespace)
+        );
+
+        let response = Self::send_request(self.request(Method::POST, url).json(&body)).await?;
+        let table = self.build_table(ident, response)?;
+        self.cache_table(&table);
+        Ok(table)
+    }
+
+    async fn load_table(&self, table: &TableIdent) -> Result<Table> {
+        if let Some(cached) = self.tables.lock().unwrap().get(table) {
+            return Ok(cached.clone());
+        }
+
+        let response = Self::send_request(self.request(Method::GET, self.table_url(table))).await?;
+        let table = self.build_table(table.clone(), response)?;
+        self.cache_table(&table);
+        Ok(table)
+    }
+
+    async fn drop_table(&self, _table: &TableIdent) -> Result<()> {
+        Err(Self::unsupported("drop_table"))
+    }
+
+    async fn table_exists(&self, table: &TableIdent) -> Result<bool> {
+        match Self::send(self.request(Method::HEAD, self.table_url(table))).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::TableNotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn rename_table(&self, _src: &TableIdent, _dest: &TableIdent) -> Result<()> {
+        Err(Self::unsupported("rename_table"))
+    }
+
+    async fn register_table(
+        &self,
+        _table: &TableIdent,
+        _metadata_location: String,
+    ) -> Result<Table> {
+        Err(Self::unsupported("register_table"))
+    }
+
+    async fn update_table(&self, mut commit: TableCommit) -> Result<Table> {
+        let ident = commit.identifier().clone();
+        let body = CommitTableRequest {
+            identifier: ident.clone(),
+            requirements: commit.take_requirements(),
+            updates: commit.take_updates(),
+        };
+
+        let response = match Self::send_request(
+            self.request(Method::POST, self.table_url(&ident))
+                .json(&body),
+        )
+        .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                // Someone else committed (or dropped the table), so the
+                // retried commit must start from a fresh load
+                self.tables.lock().unwrap().remove(&ident);
+                return Err(e);
+            }
+        };
+
+        let table = self.build_table(ident, response)?;
+        self.cache_table(&table);
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use axum::{
+        Json, Router,
+        extract::{Path, State},
+        http::StatusCode,
+        routing::{get, post},
+    };
+    use iceberg::spec::TableMetadataBuilder;
+    use std::sync::{Arc, Mutex};
+
+    pub(crate) type Tables = Arc<Mutex<HashMap<String, LoadTableResponse>>>;
+
+    #[derive(Clone)]
+    struct StandInState {
+        warehouse: String,
+        tables: Tables,
+    }
+
+    /// Spawn an in-process REST catalog stand-in on a random local port.
+    ///
+    /// Tables are created under `warehouse` (a `file://` URL) and kept in memory.
+    /// Returns the catalog base URI.
+    pub(crate) async fn spawn_catalog(warehouse: String) -> String {
+        spawn_catalog_with(warehouse, Tables::default()).await
+    }
+
+    /// Spawn a catalog stand-in keeping its tables in `tables`, so tests can
+    /// change them behind the catalog's clients.
+    pub(crate) async fn spawn_catalog_with(warehouse: String, tables: Tables) -> String {
+        let state = StandInState { warehouse, tables };
+
+        let app = Router::new()
+            .route(
+                "/v1/config",
+                get(|| async { Json(serde_json::json!({"overrides": {"prefix": "test"}})) }),
+            )
+            .route("/v1/test/namespaces", post(create_namespace))
+            .route("/v1/test/namespaces/{ns}/tables", post(create_table))
+            .route(
+                "/v1/test/namespaces/{ns}/tables/{table}",
+                get(load_table).head(table_exists).post(commit_table),
+            )
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        format!("http://{}", addr)
+    }
+
+    async fn create_namespace(Json(body): Json<CreateNamespaceRequest>) -> Json<serde_json::Value> {
+        Json(serde_json::json!({ "namespace": body.namespace, "properties": body.properties }))
+    }
+
+    async fn create_table(
+        State(state): State<StandInState>,
+        Path(ns): Path<String>,
+        Json(body): Json<CreateTableRequest>,
+    ) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+        let key = format!("{}.{}", ns, body.name);
+        let location = body
+            .location
+            .unwrap_or_else(|| format!("{}/{}/{}", state.warehouse, ns, body.name));
+        let creation = TableCreation::builder()
+            .name(body.name)
+            .location(location)
+            .schema(body.schema)
+            .properties(body.properties)
+            .build();
+        let metadata = TableMetadataBuilder::from_table_creation(creation)
+            .and_then(|b| b.build())
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .metadata;
+
+        let response = LoadTableResponse {
+            metadata_location: Some(format!("{}/metadata/v0.metadata.json", metadata.location())),
+            metadata,
+            config: HashMap::new(),
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        state.tables.lock().unwrap().insert(key, response);
+        Ok(Json(json))
+    }
+
+    async fn load_table(
+        State(state): State<StandInState>,
+        Path((ns, table)): Path<(String, String)>,
+    ) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+        let tables = state.tables.lock().unwrap();
+        let response = tables
+            .get(&format!("{}.{}", ns, table))
+            .ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(serde_json::to_value(response).unwrap()))
+    }
+
+    async fn table_exists(
+        State(state): State<StandInState>,
+        Path((ns, table)): Path<(String, String)>,
+    ) -> StatusCode {
+        if state
+            .tables
+            .lock()
+            .unwrap()
+            .contains_key(&format!("{}.{}", ns, table))
+        {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::NOT_FOUND
+        }
+    }
+
+    async fn commit_table(
+        State(state): State<StandInState>,
+        Path((ns, table)): Path<(String, String)>,
+        Json(body): Json<CommitTableRequest>,
+    ) -> std::result::Result<Json<serde_json::Value>, StatusCode> {
+        let mut tables = state.tables.lock().unwrap();
+        let current = tables
+            .get_mut(&format!("{}.{}", ns, table))
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        for requirement in &body.requirements {
+            requirement
+                .check(Some(&current.metadata))
+                .map_err(|_| StatusCode::CONFLICT)?;
+        }
+
+        let mut builder = current
+            .metadata
+            .clone()
+            .into_builder(current.metadata_location.clone());
+        for update in body.updates {
+            builder = update.apply(builder).map_err(|_| StatusCode::BAD_REQUEST)?;
+        }
+        current.metadata = builder
+            .build()
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+            .metadata;
+        current.metadata_location = Some(format!(
+            "{}/metadata/{}.metadata.json",
+            current.metadata.location(),
+            uuid::Uuid::new_v4()
+        ));
+
+        Ok(Json(serde_json::to_value(&*current).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn test_create_and_load_table() {
+        let warehouse = format!(
+            "file://{}/shizu-catalog-{}",
+            std::env::temp_dir().display(),
+            uuid::Uuid::new_v4()
+        );
+        let uri = spawn_catalog(warehouse).await;
+        let catalog = RestCatalog::connect(&uri, "test", None, HashMap::new())
+            .await
+            .unwrap();
+
+        let ident = TableIdent::from_strs(["shizu", "logs"]).unwrap();
+        assert!(!catalog.table_exists(&ident).await.unwrap());
+
+        let creation = TableCreation::builder()
+            .name("logs".to_string())
+            .schema(crate::logging::schema::iceberg_schema().unwrap())
+            .build();
+        catalog
+            .create_table(ident.namespace(), creation)
+            .await
+            .unwrap();
+
+        assert!(catalog.table_exists(&ident).await.unwrap());
+        let table = catalog.load_table(&ident).await.unwrap();
+        assert_eq!(table.identifier(), &ident);
+        assert!(table.metadata().current_snapshot().is_none());
+    }
+}
@@ -1,12 +1,19 @@
-use super::record::RequestLogRecord;
-use std::time::{Duration, Instant};
+use super::{record::RequestLogRecord, sink::RequestLogSink, writer::IcebergWriter};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 use tokio::sync::mpsc;
 
 /// Configuration for Iceberg logging.
 #[derive(Debug, Clone)]
 pub struct IcebergConfig {
     pub catalog_uri: String,
+    pub catalog_token: Option<String>,
     pub warehouse: String,
+    pub namespace: String,
+    pub table: String,
     pub r2_endpoint: String,
     pub r2_access_key: String,
     pub r2_secret_key: String,
@@ -20,7 +27,10 @@ impl IcebergConfig {
     pub fn from_env() -> Option<Self> {
         Some(Self {
             catalog_uri: std::env::var("ICEBERG_CATALOG_URI").ok()?,
+            catalog_token: std::env::var("ICEBERG_CATALOG_TOKEN").ok(),
             warehouse: std::env::var("ICEBERG_WAREHOUSE").ok()?,
+            namespace: std::env::var("ICEBERG_NAMESPACE").unwrap_or_else(|_| "shizu".to_string()),
+            table: std::env::var("ICEBERG_TABLE").unwrap_or_else(|_| "request_logs".to_string()),
             r2_endpoint: std::env::var("R2_ENDPOINT").ok()?,
             r2_access_key: std::env::var("R2_ACCESS_KEY_ID").ok()?,
             r2_secret_key: std::env::var("R2_SECRET_ACCESS_KEY").ok()?,
@@ -37,80 +47,119 @@ impl IcebergConfig {
             ),
         })
     }
+
+    /// FileIO properties for reading and writing table metadata on R2.
+    pub(super) fn file_io_props(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("s3.endpoint".to_string(), self.r2_endpoint.clone()),
+            ("s3.access-key-id".to_string(), self.r2_access_key.clone()),
+            (
+                "s3.secret-access-key".to_string(),
+                self.r2_secret_key.clone(),
+            ),
+            ("s3.region".to_string(), "auto".to_string()),
+        ])
+    }
 }
 
 /// Logger that writes request logs to Iceberg tables via R2 Data Catalog.
 pub struct IcebergLogger {
-    batch: Vec<RequestLogRecord>,
+    pending: Mutex<PendingBatch>,
     batch_size: usize,
     flush_interval: Duration,
-    last_flush: Instant,
     flush_tx: mpsc::Sender<Vec<RequestLogRecord>>,
 }
 
+/// Records waiting to be flushed.
+struct PendingBatch {
+    records: Vec<RequestLogRecord>,
+    last_flush: Instant,
+}
+
 impl IcebergLogger {
     /// Create a new Iceberg logger.
     ///
-    /// Note: This is a stub implementation. Full Iceberg integration requires:
-    /// - Connecting to R2 Data Catalog via REST Catalog API
-    /// - Creating/loading Iceberg tables
-    /// - Writing Parquet files to R2
-    /// - Committing to Iceberg metadata
+    /// Connects to the R2 Data Catalog via the REST catalog API and creates
+    /// the log table if it does not exist yet.
     pub async fn new(config: IcebergConfig) -> anyhow::Result<Self> {
+        let writer = IcebergWriter::connect(&config).await?;
+        Ok(Self::with_writer(
+            writer,
+            config.batch_size,
+            config.flush_interval,
+        ))
+    }
+
+    /// Create a logger that flushes batches through the given writer.
+    pub fn with_writer(writer: IcebergWriter, batch_size: usize, flush_interval: Duration) -> Self {
         let (flush_tx, flush_rx) = mpsc::channel(16);
 
         // Spawn background flush task
-        tokio::spawn(Self::flush_task(flush_rx, config.clone()));
-
-        Ok(Self {
-            batch: Vec::with_capacity(config.batch_size),
-            batch_size: config.batch_size,
-            flush_interval: config.flush_interval,
-            last_flush: Instant::now(),
+        tokio::spawn(Self::flush_task(flush_rx, writer));
+
+        Self {
+            pending: Mutex::new(PendingBatch {
+                records: Vec::with_capacity(batch_size),
+                last_flush: Instant::now(),
+            }),
+            batch_size,
+            flush_interval,
             flush_tx,
-        })
+        }
     }
 
     /// Log a request record.
-    pub fn log(&mut self, record: RequestLogRecord) {
-        self.batch.push(record);
+    pub fn log(&self, record: RequestLogRecord) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.records.push(record);
 
-        if self.should_flush() {
-            self.trigger_flush();
+        if self.should_flush(&pending) {
+            self.trigger_flush(&mut pending);
         }
     }
 
-    fn should_flush(&self) -> bool {
-        self.batch.len() >= self.batch_size || self.last_flush.elapsed() > self.flush_interval
+    fn should_flush(&self, pending: &PendingBatch) -> bool {
+        pending.records.len() >= self.batch_size
+            || pending.last_flush.elapsed() > self.flush_interval
     }
 
-    fn trigger_flush(&mut self) {
-        if self.batch.is_empty() {
+    fn trigger_flush(&self, pending: &mut PendingBatch) {
+        if pending.records.is_empty() {
             return;
         }
 
-        let batch = std::mem::take(&mut self.batch);
-        self.batch = Vec::with_capacity(self.batch_size);
-        self.last_flush = Instant::now();
+        let batch = std::mem::replace(&mut pending.records, Vec::with_capacity(self.batch_size));
+        pending.last_flush = Instant::now();
 
         // Non-blocking send to background task
-        let _ = self.flush_tx.try_send(batch);
+        if self.flush_tx.try_send(batch).is_err() {
+            tracing::warn!("Iceberg flush queue is full, dropping request log batch");
+        }
     }
 
-    async fn flush_task(mut rx: mpsc::Receiver<Vec<RequestLogRecord>>, _config: IcebergConfig) {
+    async fn flush_task(mut rx: mpsc::Receiver<Vec<RequestLogRecord>>, writer: IcebergWriter) {
         while let Some(batch) = rx.recv().await {
-            // TODO: Implement actual Iceberg write
-            // This would involve:
-            // 1. Convert records to Arrow RecordBatch
-            // 2. Write Parquet file to R2
-            // 3. Commit to Iceberg table
-            tracing::debug!("Would write {} records to Iceberg", batch.len());
+            match writer.write(&batch).await {
+                Ok(()) => tracing::debug!("Wrote {} records to Iceberg", batch.len()),
+                Err(e) => tracing::error!(
+                    "Failed to write to Iceberg, dropping {} records: {:#}",
+                    batch.len(),
+                    e
+                ),
+            }
         }
     }
 
     /// Flush any pending records.
-    pub fn flush(&mut self) {
-        self.trigger_flush();
+    pub fn flush(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        self.trigger_flush(&mut pending);
+    }
+}
+
+impl RequestLogSink for IcebergLogger {
+    fn log(&self, record: RequestLogRecord) {
+        IcebergLogger::log(self, record);
     }
 }
 
@@ -122,3 +171,9 @@ impl NoOpLogger {
         // No-op
     }
 }
+
+impl RequestLogSink for NoOpLogger {
+    fn log(&self, record: RequestLogRecord) {
+        NoOpLogger::log(self, record);
+    }
+}
@@ -0,0 +1,196 @@
+use arrow::{
+    array::{
+        ArrayRef, BooleanArray, Int32Array, Int64Array, RecordBatch, StringArray,
+        TimestampMicrosecondArray,
+    },
+    datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef, TimeUnit},
+};
+use iceberg::spec::{NestedField, PrimitiveType, Schema, Type};
+use std::{collections::HashMap, sync::Arc};
+
+use super::record::RequestLogRecord;
+
+/// Parquet metadata key carrying the Iceberg field id of a column.
+const PARQUET_FIELD_ID_KEY: &str = "PARQUET:field_id";
+
+/// Timezone used for `timestamptz` columns, as expected by Iceberg readers.
+const UTC_TIMEZONE: &str = "+00:00";
+
+/// Column layout of the request log table: (field id, name, type, required).
+const COLUMNS: &[(i32, &str, PrimitiveType, bool)] = &[
+    (1, "request_id", PrimitiveType::String, true),
+    (2, "timestamp", PrimitiveType::Timestamptz, true),
+    (3, "endpoint", PrimitiveType::String, true),
+    (4, "original_url", PrimitiveType::String, true),
+    (5, "manifest_headers", PrimitiveType::String, false),
+    (6, "segment_headers", PrimitiveType::String, false),
+    (7, "key_provided", PrimitiveType::Boolean, true),
+    (8, "key_type", PrimitiveType::String, false),
+    (9, "decrypt_enabled", PrimitiveType::Boolean, true),
+    (10, "response_status", PrimitiveType::Int, true),
+    (11, "response_time_ms", PrimitiveType::Long, true),
+    (12, "content_length", PrimitiveType::Long, false),
+    (13, "error_type", PrimitiveType::String, false),
+    (14, "error_message", PrimitiveType::String, false),
+    (15, "client_ip", PrimitiveType::String, false),
+    (16, "user_agent", PrimitiveType::String, false),
+];
+
+/// Iceberg schema of the request log table.
+pub fn iceberg_schema() -> iceberg::Result<Schema> {
+    let fields = COLUMNS.iter().map(|(id, name, ty, required)| {
+        let ty = Type::Primitive(ty.clone());
+        Arc::new(if *required {
+            NestedField::required(*id, *name, ty)
+        } else {
+            NestedField::optional(*id, *name, ty)
+        })
+    });
+
+    Schema::builder()
+        .with_schema_id(0)
+        .with_fields(fields)
+        .build()
+}
+
+/// Arrow schema matching [`iceberg_schema`], with Parquet field ids attached.
+pub fn arrow_schema() -> SchemaRef {
+    let fields: Vec<Field> = COLUMNS
+        .iter()
+        .map(|(id, name, ty, required)| {
+            let data_type = match ty {
+                PrimitiveType::Boolean => DataType::Boolean,
+                PrimitiveType::Int => DataType::Int32,
+                PrimitiveType::Long => DataType::Int64,
+                PrimitiveType::Timestamptz => {
+                    DataType::Timestamp(TimeUnit::Microsecond, Some(UTC_TIMEZONE.into()))
+                }
+                _ => DataType::Utf8,
+            };
+            Field::new(*name, data_type, !required).with_metadata(HashMap::from([(
+                PARQUET_FIELD_ID_KEY.to_string(),
+                id.to_string(),
+            )]))
+        })
+        .collect();
+
+    Arc::new(ArrowSchema::new(fields))
+}
+
+/// Convert a batch of log records into an Arrow record batch.
+pub fn to_record_batch(records: &[RequestLogRecord]) -> arrow::error::Result<RecordBatch> {
+    fn strings<'a>(values: impl Iterator<Item = Option<&'a str>>) -> ArrayRef {
+        Arc::new(values.collect::<StringArray>())
+    }
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.request_id.to_string()))
+                .collect::<StringArray>(),
+        ),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.timestamp.timestamp_micros()))
+                .collect::<TimestampMicrosecondArray>()
+                .with_timezone(UTC_TIMEZONE),
+        ),
+        strings(records.iter().map(|r| Some(r.endpoint.as_str()))),
+        strings(records.iter().map(|r| Some(r.original_url.as_str()))),
+        strings(records.iter().map(|r| r.manifest_headers.as_deref())),
+        strings(records.iter().map(|r| r.segment_headers.as_deref())),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.key_provided))
+                .collect::<BooleanArray>(),
+        ),
+        strings(records.iter().map(|r| r.key_type.as_deref())),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.decrypt_enabled))
+                .collect::<BooleanArray>(),
+        ),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.response_status))
+                .collect::<Int32Array>(),
+        ),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| Some(r.response_time_ms))
+                .collect::<Int64Array>(),
+        ),
+        Arc::new(
+            records
+                .iter()
+                .map(|r| r.content_length)
+                .collect::<Int64Array>(),
+        ),
+        strings(records.iter().map(|r| r.error_type.as_deref())),
+        strings(records.iter().map(|r| r.error_message.as_deref())),
+        strings(records.iter().map(|r| r.client_ip.as_deref())),
+        strings(records.iter().map(|r| r.user_agent.as_deref())),
+    ];
+
+    RecordBatch::try_new(arrow_schema(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+
+    #[test]
+    fn test_schemas_have_same_columns() {
+        let iceberg = iceberg_schema().unwrap();
+        let arrow = arrow_schema();
+
+        assert_eq!(iceberg.as_struct().fields().len(), arrow.fields().len());
+        for field in arrow.fields() {
+            let id: i32 = field.metadata()[PARQUET_FIELD_ID_KEY].parse().unwrap();
+            let iceberg_field = iceberg.field_by_id(id).unwrap();
+            assert_eq!(iceberg_field.name, *field.name());
+            assert_eq!(iceberg_field.required, !field.is_nullable());
+        }
+    }
+
+    #[test]
+    fn test_to_record_batch() {
+        let records =
+            vec![
+                RequestLogRecord::new("/manifest", "https://example.com/master.m3u8")
+                    .with_response(200, 12, Some(512)),
+                RequestLogRecord::new("/segment", "https://example.com/seg.ts")
+                    .with_error("FETCH_FAILED", "HTTP 503")
+                    .with_client_info(Some("127.0.0.1"), None),
+            ];
+
+        let batch = to_record_batch(&records).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), COLUMNS.len());
+
+        let content_length = batch
+            .column_by_name("content_length")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(content_length.value(0), 512);
+        assert!(content_length.is_null(1));
+
+        let error_type = batch
+            .column_by_name("error_type")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(error_type.is_null(0));
+        assert_eq!(error_type.value(1), "FETCH_FAILED");
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use super::{
+    iceberg::{IcebergConfig, IcebergLogger, NoOpLogger},
+    record::RequestLogRecord,
+};
+
+/// Destination for request log records.
+pub trait RequestLogSink: Send + Sync {
+    /// Record a completed request.
+    fn log(&self, record: RequestLogRecord);
+}
+
+/// Create the log sink configured by the environment.
+///
+/// Uses [`IcebergLogger`] when the Iceberg variables are set and the catalog
+/// is reachable, and [`NoOpLogger`] otherwise.
+pub async fn sink_from_env() -> Arc<dyn RequestLogSink> {
+    let Some(config) = IcebergConfig::from_env() else {
+        tracing::info!("Iceberg logging not configured, request logs are disabled");
+        return Arc::new(NoOpLogger);
+    };
+
+    match IcebergLogger::new(config).await {
+        Ok(logger) => {
+            tracing::info!("Iceberg request logging enabled");
+            Arc::new(logger)
+        }
+        Err(e) => {
+            tracing::error!("Failed to initialize Iceberg logging: {:#}", e);
+            Arc::new(NoOpLogger)
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use iceberg::{
+    Catalog, ErrorKind, TableCreation, TableIdent,
+    spec::{DataContentType, DataFileBuilder, DataFileFormat},
+    table::Table,
+    transaction::{ApplyTransactionAction, Transaction},
+};
+use object_store::{ObjectStore, ObjectStoreExt, PutPayload, aws::AmazonS3Builder, path::Path};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use std::{collections::HashMap, sync::Arc};
+
+use super::{catalog::RestCatalog, iceberg::IcebergConfig, record::RequestLogRecord, schema};
+
+/// Writes batches of request log records to an Iceberg table.
+///
+/// Each batch becomes one Parquet data file, uploaded through `object_store`
+/// and committed to the table with a fast-append snapshot.
+pub struct IcebergWriter {
+    catalog: Arc<dyn Catalog>,
+    store: Arc<dyn ObjectStore>,
+    table: TableIdent,
+}
+
+impl IcebergWriter {
+    pub fn new(catalog: Arc<dyn Catalog>, store: Arc<dyn ObjectStore>, table: TableIdent) -> Self {
+        Self {
+            catalog,
+            store,
+            table,
+        }
+    }
+
+    /// Connect to the REST catalog and R2 bucket described by `config`.
+    pub async fn connect(config: &IcebergConfig) -> anyhow::Result<Self> {
+        let catalog = RestCatalog::connect(
+            &config.catalog_uri,
+            &config.warehouse,
+            config.catalog_token.clone(),
+            config.file_io_props(),
+        )
+        .await?;
+
+        let store = AmazonS3Builder::new()
+            .with_endpoint(&config.r2_endpoint)
+            .with_bucket_name(&config.r2_bucket)
+            .with_access_key_id(&config.r2_access_key)
+            .with_secret_access_key(&config.r2_secret_key)
+            .with_region("auto")
+            .build()?;
+
+        let table = TableIdent::from_strs([&config.namespace, &config.table])?;
+        let writer = Self::new(Arc::new(catalog), Arc::new(store), table);
+        writer.ensure_table().await?;
+
+        Ok(writer)
+    }
+
+    /// Load the log table, creating the namespace and table if missing.
+    pub async fn ensure_table(&self) -> anyhow::Result<Table> {
+        match self.catalog.load_table(&self.table).await {
+            Ok(table) => return Ok(table),
+            Err(e) if e.kind() == ErrorKind::TableNotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        tracing::info!("Creating Iceberg table {}", self.table);
+
+        let namespace = self.table.namespace();
+        if let Err(e) = self
+            .catalog
+            .create_namespace(namespace, HashMap::new())
+            .await
+            && e.kind() != ErrorKind::NamespaceAlreadyExists
+        {
+            return Err(e.into());
+        }
+
+        let creation = TableCreation::builder()
+            .name(self.table.name().to_string())
+            .schema(schema::iceberg_schema()?)
+            .build();
+
+        Ok(self.catalog.create_table(namespace, creation).await?)
+    }
+
+    /// Write a batch of records as a new Parquet data file and commit it.
+    ///
+    /// If the commit fails, the uploaded file is deleted again.
+    pub async fn write(&self, records: &[RequestLogRecord]) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let table = self.ensure_table().await?;
+        let parquet = Self::encode_parquet(records)?;
+        let file_size = parquet.len() as u64;
+
+        let file_url = format!(
+            "{}/data/{}-{}.parquet",
+            table.metadata().location().trim_end_matches('/'),
+            chrono::Utc::now().format("%Y%m%d%H%M%S"),
+            uuid::Uuid::new_v4()
+        );
+        let path = Self::object_path(&file_url)?;
+        self.store.put(&path, PutPayload::from(parquet)).await?;
+
+        let result = self
+            .commit(&table, file_url, records.len() as u64, file_size)
+            .await;
+        if result.is_err() {
+            // No snapshot refers to the file, so it would never be read
+            if let Err(e) = self.store.delete(&path).await {
+                tracing::warn!("Failed to delete uncommitted data file {}: {}", path, e);
+            }
+        }
+        result
+    }
+
+    /// Append a data file to the table in a new snapshot.
+    async fn commit(
+        &self,
+        table: &Table,
+        file_url: String,
+        record_count: u64,
+        file_size: u64,
+    ) -> anyhow::Result<()> {
+        let data_file = DataFileBuilder::default()
+            .content(DataContentType::Data)
+            .file_path(file_url)
+            .file_format(DataFileFormat::Parquet)
+            .record_count(record_count)
+            .file_size_in_bytes(file_size)
+            .partition_spec_id(table.metadata().default_partition_spec_id())
+            .build()?;
+
+        let tx = Transaction::new(table);
+        let tx = tx.fast_append().add_data_files([data_file]).apply(tx)?;
+        tx.commit(self.catalog.as_ref()).await?;
+
+        Ok(())
+    }
+
+    fn encode_parquet(records: &[RequestLogRecord]) -> anyhow::Result<Vec<u8>> {
+        let batch = schema::to_record_batch(records)?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+
+        let mut writer = ArrowWriter::try_new(Vec::new(), batch.schema(), Some(props))?;
+        writer.write(&batch)?;
+        Ok(writer.into_inner()?)
+    }
+
+    /// Map a table file URL (`s3://bucket/a/b`, `file:///a/b`) to an object store path.
+    ///
+    /// The object store is expected to be rooted at the bucket (or filesystem root).
+    fn object_path(file_url: &str) -> anyhow::Result<Path> {
+        let url = url::Url::parse(file_url)?;
+        Ok(Path::parse(url.path().trim_start_matches('/'))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::catalog::{
+        RestCatalog,
+        tests::{Tables, spawn_catalog, spawn_catalog_with},
+    };
+    use object_store::local::LocalFileSystem;
+
+    #[test]
+    fn test_object_path() {
+        let path = IcebergWriter::object_path("s3://bucket/warehouse/logs/data/a.parquet").unwrap();
+        assert_eq!(path.as_ref(), "warehouse/logs/data/a.parquet");
+
+        let path = IcebergWriter::object_path("file:///tmp/logs/data/a.parquet").unwrap();
+        assert_eq!(path.as_ref(), "tmp/logs/data/a.parquet");
+    }
+
+    #[tokio::test]
+    async fn test_write_commits_snapshot() {
+        let dir = std::env::temp_dir().join(format!("shizu-iceberg-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let uri = spawn_catalog(format!("file://{}", dir.display())).await;
+        let catalog = RestCatalog::connect(&uri, "test", None, HashMap::new())
+            .await
+            .unwrap();
+        let table = TableIdent::from_strs(["shizu", "request_logs"]).unwrap();
+        let writer = IcebergWriter::new(
+            Arc::new(catalog),
+            Arc::new(LocalFileSystem::new()),
+            table.clone(),
+        );
+
+        let records =
+            vec![
+                RequestLogRecord::new("/manifest", "https://example.com/master.m3u8")
+                    .with_response(200, 5, Some(100)),
+                RequestLogRecord::new("/segment", "https://example.com/seg.ts")
+                    .with_response(502, 30, None),
+            ];
+        writer.write(&records).await.unwrap();
+        writer.write(&records[..1]).await.unwrap();
+
+        let table = writer.catalog.load_table(&table).await.unwrap();
+        let added_records: Vec<_> = table
+            .metadata()
+            .snapshots()
+            .map(|s| s.summary().additional_properties["added-records"].clone())
+            .collect();
+        assert_eq!(added_records.len(), 2);
+        assert!(added_records.contains(&"2".to_string()));
+        assert!(added_records.contains(&"1".to_string()));
+
+        let data_files = std::fs::read_dir(dir.join("shizu/request_logs/data"))
+            .unwrap()
+            .count();
+        assert_eq!(data_files, 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reloads_table_after_conflict() {
+        let dir = std::env::temp_dir().join(format!("shizu-iceberg-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let uri = spawn_catalog(format!("file://{}", dir.display())).await;
+        let table = TableIdent::from_strs(["shizu", "request_logs"]).unwrap();
+        let writer = || async {
+            let catalog = RestCatalog::connect(&uri, "test", None, HashMap::new())
+                .await
+                .unwrap();
+            IcebergWriter::new(
+                Arc::new(catalog),
+                Arc::new(LocalFileSystem::new()),
+                table.clone(),
+            )
+        };
+        let (first, second) = (writer().await, writer().await);
+        let records = vec![RequestLogRecord::new(
+            "/manifest",
+            "https://example.com/a.m3u8",
+        )];
+        let snapshots = |table: Table| table.metadata().snapshots().count();
+
+        first.write(&records).await.unwrap();
+        second.write(&records).await.unwrap();
+
+        // The first writer keeps its table until a commit conflicts
+        let cached = first.catalog.load_table(&table).await.unwrap();
+        assert_eq!(snapshots(cached), 1);
+
+        first.write(&records).await.unwrap();
+        let reloaded = first.catalog.load_table(&table).await.unwrap();
+        assert_eq!(snapshots(reloaded), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deletes_data_file_of_failed_commit() {
+        let dir = std::env::temp_dir().join(format!("shizu-iceberg-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let tables = Tables::default();
+        let uri = spawn_catalog_with(format!("file://{}", dir.display()), tables.clone()).await;
+        let catalog = RestCatalog::connect(&uri, "test", None, HashMap::new())
+            .await
+            .unwrap();
+        let table = TableIdent::from_strs(["shizu", "request_logs"]).unwrap();
+        let writer = IcebergWriter::new(Arc::new(catalog), Arc::new(LocalFileSystem::new()), table);
+        let records = vec![RequestLogRecord::new(
+            "/manifest",
+            "https://example.com/a.m3u8",
+        )];
+        writer.write(&records).await.unwrap();
+
+        // The table disappears behind the writer's cached copy
+        tables.lock().unwrap().clear();
+        assert!(writer.write(&records).await.is_err());
+
+        let data_files = std::fs::read_dir(dir.join("shizu/request_logs/data"))
+            .unwrap()
+            .count();
+        assert_eq!(data_files, 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
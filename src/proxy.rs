@@ -1,5 +1,13 @@
 pub mod client;
+pub mod destination;
+pub mod egress;
 pub mod headers;
+pub mod mirror;
+pub mod retry;
 
-pub use client::ProxyClient;
+pub use client::{ClientConfig, ProxyClient, TextResponse};
+pub use destination::DestinationPolicy;
+pub use egress::EgressPools;
 pub use headers::HeaderCodec;
+pub use mirror::Mirrors;
+pub use retry::RetryPolicy;
@@ -1,26 +1,206 @@
-use crate::{hls::ByteRange, Result};
+use crate::{Error, Result, hls::ByteRange};
 use bytes::Bytes;
-use reqwest::Client;
-use std::collections::HashMap;
+use reqwest::{Client, Proxy, Response, header::HeaderMap, redirect};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+use super::{
+    destination::{DestinationPolicy, PolicyResolver},
+    mirror::Mirrors,
+    retry::{RetryPolicy, parse_retry_after},
+};
+
+/// Redirects followed for a single request.
+const MAX_REDIRECTS: usize = 10;
+
+/// Timeouts and retries for upstream requests.
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Timeout for establishing a connection.
+    pub connect_timeout: Duration,
+
+    /// Timeout for each read of the response, so stalled bodies fail.
+    pub read_timeout: Duration,
+
+    /// Retries for failed requests.
+    pub retry: RetryPolicy,
+
+    /// Hosts to fail over to when a request fails.
+    pub mirrors: Mirrors,
+
+    /// Outbound proxy for all requests (`http://`, `https://`, `socks5://`
+    /// or `socks5h://`).
+    pub proxy: Option<String>,
+
+    /// Destinations requests may go to.
+    pub destinations: DestinationPolicy,
+}
+
+impl ClientConfig {
+    /// Create config from environment variables, falling back to defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|s| s.parse::<u64>().ok());
+
+        Self {
+            connect_timeout: var("SHIZU_CONNECT_TIMEOUT_SECS")
+                .map_or(defaults.connect_timeout, Duration::from_secs),
+            read_timeout: var("SHIZU_READ_TIMEOUT_SECS")
+                .map_or(defaults.read_timeout, Duration::from_secs),
+            retry: RetryPolicy {
+                max_retries: var("SHIZU_RETRY_MAX")
+                    .map_or(defaults.retry.max_retries, |n| n as u32),
+                base_delay: var("SHIZU_RETRY_BASE_MS")
+                    .map_or(defaults.retry.base_delay, Duration::from_millis),
+                max_delay: var("SHIZU_RETRY_MAX_DELAY_MS")
+                    .map_or(defaults.retry.max_delay, Duration::from_millis),
+            },
+            mirrors: Mirrors::from_env(),
+            proxy: std::env::var("SHIZU_UPSTREAM_PROXY")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            destinations: DestinationPolicy::from_env(),
+        }
+    }
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            mirrors: Mirrors::default(),
+            proxy: None,
+            destinations: DestinationPolicy::default(),
+        }
+    }
+}
+
+/// Body and headers of a text response.
+#[derive(Debug, Clone)]
+pub struct TextResponse {
+    pub text: String,
+    pub headers: HeaderMap,
+
+    /// URL that served the response: the requested URL or one of its
+    /// mirrors.
+    pub url: String,
+}
 
 /// HTTP client for proxying requests to upstream servers.
+///
+/// Cloning is cheap; clones share the connection pool.
 #[derive(Clone)]
 pub struct ProxyClient {
     client: Client,
+    retry: RetryPolicy,
+    mirrors: Arc<Mirrors>,
+    destinations: Arc<DestinationPolicy>,
+    deadline: Option<Instant>,
 }
 
 impl ProxyClient {
     pub fn new() -> Self {
-        Self {
-            client: Client::builder()
-                .timeout(std::time::Duration::from_secs(30))
-                .build()
-                .expect("Failed to create HTTP client"),
+        Self::from_config(ClientConfig::default())
+    }
+
+    pub fn from_config(config: ClientConfig) -> Self {
+        Self::try_from_config(config).expect("Failed to create HTTP client")
+    }
+
+    /// Create a client, failing if the outbound proxy is invalid.
+    ///
+    /// An outbound proxy resolves upstream hosts itself, and checking our
+    /// own lookup would race with its (DNS rebinding). Proxies are only
+    /// accepted when the destination policy allows private networks, so
+    /// the policy is never assumed to cover addresses it cannot see.
+    pub fn try_from_config(config: ClientConfig) -> Result<Self> {
+        if config.proxy.is_some() && !config.destinations.allow_private_networks {
+            return Err(Error::InvalidParameter(
+                "Outbound proxies cannot refuse private networks; \
+                 set SHIZU_ALLOW_PRIVATE_NETWORKS=true and block them at the proxy"
+                    .to_string(),
+            ));
         }
+
+        let destinations = Arc::new(config.destinations);
+        let redirect_policy = destinations.clone();
+        let mut builder = Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .read_timeout(config.read_timeout)
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("too many redirects")
+                } else if let Err(e) = redirect_policy.check_url(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            }));
+
+        // Behind an outbound proxy, the proxy resolves upstream hosts and
+        // our resolver would only see the proxy's own name
+        if config.proxy.is_none() && !destinations.allow_private_networks {
+            builder = builder.dns_resolver(PolicyResolver {
+                policy: destinations.clone(),
+            });
+        }
+
+        if let Some(proxy) = &config.proxy {
+            let scheme = proxy.split_once("://").map(|(scheme, _)| scheme);
+            if !matches!(scheme, Some("http" | "https" | "socks5" | "socks5h")) {
+                return Err(Error::InvalidParameter(format!(
+                    "Unsupported proxy URL: {}",
+                    proxy
+                )));
+            }
+            let proxy = Proxy::all(proxy.as_str())
+                .map_err(|e| Error::InvalidParameter(format!("Invalid proxy URL: {}", e)))?;
+            builder = builder.proxy(proxy);
+        }
+
+        Ok(Self {
+            client: builder
+                .build()
+                .map_err(|e| Error::Internal(format!("Failed to create HTTP client: {}", e)))?,
+            retry: config.retry,
+            mirrors: Arc::new(config.mirrors),
+            destinations,
+            deadline: None,
+        })
+    }
+
+    /// Client allowed to reach local test servers.
+    #[cfg(test)]
+    pub fn local() -> Self {
+        Self::from_config(ClientConfig {
+            destinations: DestinationPolicy::allow_all(),
+            ..Default::default()
+        })
     }
 
     pub fn with_client(client: Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            retry: RetryPolicy::default(),
+            mirrors: Arc::default(),
+            destinations: Arc::new(DestinationPolicy::allow_all()),
+            deadline: None,
+        }
+    }
+
+    /// A client whose requests, including retries and reading the body,
+    /// fail once `deadline` has passed.
+    pub fn with_deadline(&self, deadline: Instant) -> Self {
+        Self {
+            deadline: Some(self.deadline.map_or(deadline, |d| d.min(deadline))),
+            ..self.clone()
+        }
     }
 
     /// Fetch content from a URL with optional headers and byte range.
@@ -30,31 +210,140 @@ impl ProxyClient {
         headers: Option<&HashMap<String, String>>,
         byterange: Option<&ByteRange>,
     ) -> Result<Bytes> {
-        let mut request = self.client.get(url);
+        let response = self.send(url, headers, byterange).await?;
+        Ok(response.bytes().await?)
+    }
 
-        // Apply custom headers
-        if let Some(headers) = headers {
-            for (key, value) in headers {
-                request = request.header(key.as_str(), value.as_str());
-            }
-        }
+    /// Start fetching a URL without buffering the body.
+    ///
+    /// The status has been checked; consume the body with `bytes_stream()`.
+    pub async fn fetch_stream(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+        byterange: Option<&ByteRange>,
+    ) -> Result<Response> {
+        self.send(url, headers, byterange).await
+    }
 
-        // Apply byte range header
-        if let Some(br) = byterange {
-            request = request.header("Range", br.to_range_header());
-        }
+    /// Send a GET, failing over to the mirrors of the URL's host.
+    async fn send(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+        byterange: Option<&ByteRange>,
+    ) -> Result<Response> {
+        let (response, _) = self.send_any(url, headers, byterange).await?;
+        Ok(response)
+    }
 
-        let response = request.send().await?;
-        let status = response.status();
+    /// Try `url` and then each of its mirrors in order, returning the first
+    /// successful response and the URL that served it.
+    async fn send_any(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+        byterange: Option<&ByteRange>,
+    ) -> Result<(Response, String)> {
+        let mut candidates = self.mirrors.candidates(url).into_iter().peekable();
 
-        if !status.is_success() && status.as_u16() != 206 {
-            return Err(crate::Error::FetchFailed {
-                url: url.to_string(),
-                reason: format!("HTTP {}", status),
-            });
+        loop {
+            let candidate = candidates.next().expect("candidates include the URL");
+            let error = match self.send_to(&candidate, headers, byterange).await {
+                Ok(response) => return Ok((response, candidate)),
+                // The deadline is shared, so the mirrors would time out too
+                Err(e @ Error::FetchTimeout(_)) => return Err(e),
+                Err(e @ Error::DestinationForbidden(_)) => return Err(e),
+                Err(e) => e,
+            };
+
+            let Some(next) = candidates.peek() else {
+                return Err(error);
+            };
+            tracing::warn!("Failing over from {} to {}: {}", candidate, next, error);
         }
+    }
 
-        Ok(response.bytes().await?)
+    /// Send a GET, retrying transient failures within the deadline.
+    async fn send_to(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+        byterange: Option<&ByteRange>,
+    ) -> Result<Response> {
+        self.destinations.check_url(&Url::parse(url)?)?;
+        let mut attempt = 0;
+
+        loop {
+            let mut request = self.client.get(url);
+
+            // Apply custom headers
+            if let Some(headers) = headers {
+                for (key, value) in headers {
+                    request = request.header(key.as_str(), value.as_str());
+                }
+            }
+
+            // Apply byte range header
+            if let Some(br) = byterange {
+                request = request.header("Range", br.to_range_header());
+            }
+
+            // The timeout also covers reading the body
+            if let Some(deadline) = self.deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(Error::FetchTimeout(url.to_string()));
+                }
+                request = request.timeout(remaining);
+            }
+
+            let (error, retry_after) = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let error = Error::FetchFailed {
+                        url: url.to_string(),
+                        reason: format!("HTTP {}", status),
+                    };
+                    if !RetryPolicy::is_retryable_status(status) {
+                        return Err(error);
+                    }
+                    let retry_after = response
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(parse_retry_after);
+                    (error, retry_after)
+                }
+                Err(e) => {
+                    if let Some(forbidden) = destination_error(&e) {
+                        return Err(forbidden);
+                    }
+                    if !RetryPolicy::is_retryable_error(&e) {
+                        return Err(e.into());
+                    }
+                    (e.into(), None)
+                }
+            };
+
+            if attempt >= self.retry.max_retries {
+                return Err(error);
+            }
+            let Some(delay) = self.retry.delay(attempt, retry_after) else {
+                return Err(error);
+            };
+            if self
+                .deadline
+                .is_some_and(|deadline| Instant::now() + delay >= deadline)
+            {
+                return Err(error);
+            }
+
+            tracing::warn!("Retrying {} in {:?} after: {}", url, delay, error);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
 
     /// Fetch content and return as string.
@@ -63,16 +352,201 @@ impl ProxyClient {
         url: &str,
         headers: Option<&HashMap<String, String>>,
     ) -> Result<String> {
-        let bytes = self.fetch(url, headers, None).await?;
-        String::from_utf8(bytes.to_vec()).map_err(|e| crate::Error::FetchFailed {
+        let response = self.fetch_text_response(url, headers).await?;
+        Ok(response.text)
+    }
+
+    /// Fetch content as string along with the upstream response headers and
+    /// the URL that served it.
+    pub async fn fetch_text_response(
+        &self,
+        url: &str,
+        headers: Option<&HashMap<String, String>>,
+    ) -> Result<TextResponse> {
+        let (response, served_url) = self.send_any(url, headers, None).await?;
+        let response_headers = response.headers().clone();
+
+        let bytes = response.bytes().await?;
+        let text = String::from_utf8(bytes.to_vec()).map_err(|e| Error::FetchFailed {
             url: url.to_string(),
             reason: format!("Invalid UTF-8: {}", e),
+        })?;
+
+        Ok(TextResponse {
+            text,
+            headers: response_headers,
+            url: served_url,
         })
     }
 }
 
+/// Policy violation behind a request error, from a redirect or DNS.
+fn destination_error(error: &reqwest::Error) -> Option<Error> {
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        if let Some(e @ Error::DestinationForbidden(_)) = e.downcast_ref::<Error>() {
+            return Some(e.clone());
+        }
+        source = e.source();
+    }
+    None
+}
+
 impl Default for ProxyClient {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, response::IntoResponse, routing::get};
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Serve `/` failing with `status` for the first `failures` requests.
+    async fn spawn_flaky(failures: usize, status: StatusCode, retry_after: &'static str) -> String {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let upstream = Router::new().route(
+            "/",
+            get(move || async move {
+                if requests.fetch_add(1, Ordering::SeqCst) < failures {
+                    (status, [(reqwest::header::RETRY_AFTER, retry_after)], "").into_response()
+                } else {
+                    "ok".into_response()
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+        url
+    }
+
+    fn client(max_retries: u32) -> ProxyClient {
+        ProxyClient::from_config(ClientConfig {
+            retry: RetryPolicy {
+                max_retries,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_secs(1),
+            },
+            destinations: DestinationPolicy::allow_all(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let url = spawn_flaky(2, StatusCode::SERVICE_UNAVAILABLE, "0").await;
+        assert_eq!(client(2).fetch_text(&url, None).await.unwrap(), "ok");
+
+        let url = spawn_flaky(2, StatusCode::SERVICE_UNAVAILABLE, "0").await;
+        assert!(matches!(
+            client(1).fetch_text(&url, None).await,
+            Err(Error::FetchFailed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let url = spawn_flaky(1, StatusCode::NOT_FOUND, "0").await;
+        assert!(client(2).fetch_text(&url, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_on_long_retry_after() {
+        let url = spawn_flaky(1, StatusCode::TOO_MANY_REQUESTS, "120").await;
+        assert!(client(2).fetch_text(&url, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deadline_stops_retries() {
+        let url = spawn_flaky(5, StatusCode::BAD_GATEWAY, "1").await;
+        let client = client(5).with_deadline(Instant::now() + Duration::from_millis(500));
+
+        let started = Instant::now();
+        assert!(client.fetch_text(&url, None).await.is_err());
+        assert!(started.elapsed() < Duration::from_millis(500));
+
+        let expired = client.with_deadline(Instant::now());
+        assert!(matches!(
+            expired.fetch_text(&url, None).await,
+            Err(Error::FetchTimeout(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_mirror() {
+        let primary = spawn_flaky(5, StatusCode::SERVICE_UNAVAILABLE, "0").await;
+        let mirror = spawn_flaky(0, StatusCode::OK, "0").await;
+        let host = |url: &str| {
+            url.trim_start_matches("http://")
+                .trim_end_matches('/')
+                .to_string()
+        };
+
+        let client = ProxyClient::from_config(ClientConfig {
+            retry: RetryPolicy::none(),
+            mirrors: Mirrors::parse(&format!("{},{}", host(&primary), host(&mirror))),
+            destinations: DestinationPolicy::allow_all(),
+            ..Default::default()
+        });
+
+        let response = client.fetch_text_response(&primary, None).await.unwrap();
+        assert_eq!(response.text, "ok");
+        assert_eq!(response.url, mirror);
+    }
+
+    #[test]
+    fn test_refuses_proxy_with_private_network_policy() {
+        let proxy = Some("http://10.1.0.1:3128".to_string());
+        assert!(matches!(
+            ProxyClient::try_from_config(ClientConfig {
+                proxy: proxy.clone(),
+                ..Default::default()
+            }),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(
+            ProxyClient::try_from_config(ClientConfig {
+                proxy,
+                destinations: DestinationPolicy::allow_all(),
+                ..Default::default()
+            })
+            .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enforces_destination_policy() {
+        for url in ["http://127.0.0.1:1/", "http://localhost:1/"] {
+            assert!(matches!(
+                ProxyClient::new().fetch_text(url, None).await,
+                Err(Error::DestinationForbidden(_))
+            ));
+        }
+
+        let upstream = Router::new().route(
+            "/",
+            get(|| async { axum::response::Redirect::temporary("http://localhost/admin") }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let client = ProxyClient::from_config(ClientConfig {
+            destinations: DestinationPolicy {
+                denied_hosts: vec!["localhost".to_string()],
+                ..DestinationPolicy::allow_all()
+            },
+            ..Default::default()
+        });
+        assert!(matches!(
+            client.fetch_text(&url, None).await,
+            Err(Error::DestinationForbidden(_))
+        ));
+    }
+}
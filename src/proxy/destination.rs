@@ -0,0 +1,287 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use url::Url;
+
+use crate::{Error, Result};
+
+/// Upstream destinations the proxy may connect to.
+///
+/// Checked on every request URL and redirect, and again on the addresses a
+/// host name resolves to, so a signed URL (or one fetched with signing
+/// disabled) cannot reach internal services.
+#[derive(Debug, Clone)]
+pub struct DestinationPolicy {
+    /// Host patterns to allow (`*` matches any characters). Empty allows
+    /// every host not denied.
+    pub allowed_hosts: Vec<String>,
+
+    /// Host patterns to deny, checked before the allowlist.
+    pub denied_hosts: Vec<String>,
+
+    /// Allowed URL schemes.
+    pub allowed_schemes: Vec<String>,
+
+    /// Allowed ports. Empty allows every port.
+    pub allowed_ports: Vec<u16>,
+
+    /// Whether private, loopback and link-local addresses may be reached.
+    pub allow_private_networks: bool,
+}
+
+impl DestinationPolicy {
+    /// A policy that allows every destination.
+    pub fn allow_all() -> Self {
+        Self {
+            allowed_schemes: Vec::new(),
+            allow_private_networks: true,
+            ..Self::default()
+        }
+    }
+
+    /// Create a policy from environment variables, falling back to defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let list = |name: &str| {
+            std::env::var(name).ok().map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().to_ascii_lowercase())
+                    .filter(|item| !item.is_empty())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        Self {
+            allowed_hosts: list("SHIZU_ALLOWED_HOSTS").unwrap_or(defaults.allowed_hosts),
+            denied_hosts: list("SHIZU_DENIED_HOSTS").unwrap_or(defaults.denied_hosts),
+            allowed_schemes: list("SHIZU_ALLOWED_SCHEMES").unwrap_or(defaults.allowed_schemes),
+            allowed_ports: list("SHIZU_ALLOWED_PORTS")
+                .map(|ports| ports.iter().filter_map(|p| p.parse().ok()).collect())
+                .unwrap_or(defaults.allowed_ports),
+            allow_private_networks: std::env::var("SHIZU_ALLOW_PRIVATE_NETWORKS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(defaults.allow_private_networks),
+        }
+    }
+
+    /// Check a request or redirect URL.
+    pub fn check_url(&self, url: &Url) -> Result<()> {
+        let forbidden = |reason: String| Err(Error::DestinationForbidden(reason));
+
+        if !self.allowed_schemes.is_empty()
+            && !self.allowed_schemes.iter().any(|s| s == url.scheme())
+        {
+            return forbidden(format!("scheme {} is not allowed", url.scheme()));
+        }
+
+        let Some(host) = url.host_str() else {
+            return forbidden(format!("{} has no host", url));
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+
+        if let Some(port) = url.port_or_known_default()
+            && !self.allowed_ports.is_empty()
+            && !self.allowed_ports.contains(&port)
+        {
+            return forbidden(format!("port {} is not allowed", port));
+        }
+
+        if self.denied_hosts.iter().any(|p| glob_matches(p, &host)) {
+            return forbidden(format!("host {} is denied", host));
+        }
+        if !self.allowed_hosts.is_empty()
+            && !self.allowed_hosts.iter().any(|p| glob_matches(p, &host))
+        {
+            return forbidden(format!("host {} is not allowed", host));
+        }
+
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            self.check_ip(&host, ip)?;
+        }
+        Ok(())
+    }
+
+    /// Check an address a host resolved to.
+    pub fn check_ip(&self, host: &str, ip: IpAddr) -> Result<()> {
+        if !self.allow_private_networks && is_private(ip) {
+            return Err(Error::DestinationForbidden(format!(
+                "{} resolves to private address {}",
+                host, ip
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for DestinationPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            allowed_schemes: vec!["http".to_string(), "https".to_string()],
+            allowed_ports: Vec::new(),
+            allow_private_networks: false,
+        }
+    }
+}
+
+/// DNS resolver that rejects hosts resolving to addresses the policy denies.
+///
+/// Checking the resolved addresses, not the host name, also covers
+/// redirects and names that resolve differently over time.
+pub(crate) struct PolicyResolver {
+    pub policy: Arc<DestinationPolicy>,
+}
+
+impl Resolve for PolicyResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.policy.clone();
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+
+            // Deny the host if any of its addresses is denied, since the
+            // connector may try each of them
+            for addr in &addrs {
+                policy.check_ip(&host, addr.ip())?;
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Check if an address is private, loopback, link-local or otherwise not
+/// publicly routable.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_private_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private_v4(ip),
+            None => is_private_v6(ip),
+        },
+    }
+}
+
+fn is_private_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn is_private_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local (fc00::/7) and link-local (fe80::/10)
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// Match a host against a pattern where `*` matches any characters.
+fn glob_matches(pattern: &str, host: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = host.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(policy: &DestinationPolicy, url: &str) -> bool {
+        policy.check_url(&Url::parse(url).unwrap()).is_ok()
+    }
+
+    #[test]
+    fn test_denies_private_addresses() {
+        let policy = DestinationPolicy::default();
+
+        assert!(check(&policy, "https://93.184.215.14/a.m3u8"));
+        assert!(check(&policy, "https://example.com/a.m3u8"));
+        assert!(!check(&policy, "http://127.0.0.1:8080/a.m3u8"));
+        assert!(!check(&policy, "http://10.1.2.3/a.m3u8"));
+        assert!(!check(&policy, "http://169.254.169.254/latest/meta-data"));
+        assert!(!check(&policy, "http://100.64.0.1/a.m3u8"));
+        assert!(!check(&policy, "http://[::1]/a.m3u8"));
+        assert!(!check(&policy, "http://[fd00::1]/a.m3u8"));
+        assert!(!check(&policy, "http://[::ffff:192.168.0.1]/a.m3u8"));
+
+        assert!(check(
+            &DestinationPolicy::allow_all(),
+            "http://127.0.0.1/a.m3u8"
+        ));
+    }
+
+    #[test]
+    fn test_host_patterns() {
+        let policy = DestinationPolicy {
+            allowed_hosts: vec!["*.example.com".to_string(), "cdn-*.example.net".to_string()],
+            denied_hosts: vec!["internal.example.com".to_string()],
+            ..Default::default()
+        };
+
+        assert!(check(&policy, "https://video.example.com/a.m3u8"));
+        assert!(check(&policy, "https://cdn-3.example.net/a.m3u8"));
+        assert!(!check(&policy, "https://example.com.evil.org/a.m3u8"));
+        assert!(!check(&policy, "https://internal.example.com/a.m3u8"));
+        assert!(!check(&policy, "https://img.example.net/a.m3u8"));
+    }
+
+    #[test]
+    fn test_schemes_and_ports() {
+        let policy = DestinationPolicy {
+            allowed_ports: vec![443],
+            ..Default::default()
+        };
+
+        assert!(check(&policy, "https://example.com/a.m3u8"));
+        assert!(!check(&policy, "http://example.com/a.m3u8"));
+        assert!(!check(&policy, "https://example.com:8443/a.m3u8"));
+        assert!(!check(&policy, "file:///etc/passwd"));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_denies_private_hosts() {
+        let resolver = PolicyResolver {
+            policy: Arc::new(DestinationPolicy::default()),
+        };
+
+        let result = resolver.resolve("localhost".parse().unwrap()).await;
+        let error = result.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::DestinationForbidden(_))
+        ));
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use super::client::{ClientConfig, ProxyClient};
+use crate::{Error, Result};
+
+/// Named pools of outbound proxies that requests can be routed through
+/// (e.g. to reach region-locked origins).
+///
+/// Each proxy of a pool gets its own client. Requests to the same upstream
+/// host always leave through the same proxy, so origins that tie tokens to
+/// the client IP see a stable address.
+#[derive(Clone, Default)]
+pub struct EgressPools {
+    pools: HashMap<String, Vec<ProxyClient>>,
+}
+
+impl EgressPools {
+    /// Parse pools separated by `;`, each `name=proxy,proxy,...`.
+    ///
+    /// Example: `jp=socks5h://10.0.0.1:1080,socks5h://10.0.0.2:1080;us=http://10.1.0.1:3128`
+    pub fn parse(spec: &str, config: &ClientConfig) -> Result<Self> {
+        let mut pools = HashMap::new();
+
+        for pool in spec.split(';').filter(|p| !p.trim().is_empty()) {
+            let (name, proxies) = pool.split_once('=').ok_or_else(|| {
+                Error::InvalidParameter(format!("Egress pool without a name: {}", pool))
+            })?;
+
+            let clients = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| {
+                    ProxyClient::try_from_config(ClientConfig {
+                        proxy: Some(proxy.to_string()),
+                        ..config.clone()
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            if clients.is_empty() {
+                return Err(Error::InvalidParameter(format!(
+                    "Egress pool without proxies: {}",
+                    name
+                )));
+            }
+            pools.insert(name.trim().to_string(), clients);
+        }
+
+        Ok(Self { pools })
+    }
+
+    /// Create pools from the `SHIZU_EGRESS_POOLS` environment variable.
+    ///
+    /// An invalid value is logged and leaves no pools configured.
+    pub fn from_env(config: &ClientConfig) -> Self {
+        let Ok(spec) = std::env::var("SHIZU_EGRESS_POOLS") else {
+            return Self::default();
+        };
+
+        Self::parse(&spec, config).unwrap_or_else(|e| {
+            tracing::error!("Ignoring SHIZU_EGRESS_POOLS: {}", e);
+            Self::default()
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// Names of the configured pools.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.pools.keys().map(String::as_str)
+    }
+
+    /// Client of pool `name` to fetch `url` with.
+    pub fn client(&self, name: &str, url: &str) -> Result<&ProxyClient> {
+        let clients = self
+            .pools
+            .get(name)
+            .ok_or_else(|| Error::InvalidParameter(format!("Unknown egress pool: {}", name)))?;
+
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        let mut hasher = DefaultHasher::new();
+        host.hash(&mut hasher);
+
+        Ok(&clients[hasher.finish() as usize % clients.len()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::DestinationPolicy;
+
+    fn config() -> ClientConfig {
+        ClientConfig {
+            destinations: DestinationPolicy::allow_all(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_pools() {
+        let pools = EgressPools::parse(
+            "jp=socks5h://10.0.0.1:1080, socks5h://10.0.0.2:1080;us=http://10.1.0.1:3128",
+            &config(),
+        )
+        .unwrap();
+
+        let mut names: Vec<_> = pools.names().collect();
+        names.sort();
+        assert_eq!(names, ["jp", "us"]);
+
+        assert!(pools.client("jp", "https://example.com/a.m3u8").is_ok());
+        assert!(matches!(
+            pools.client("eu", "https://example.com/a.m3u8"),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_pools() {
+        let config = config();
+
+        assert!(EgressPools::parse("jp=", &config).is_err());
+        assert!(EgressPools::parse("socks5://10.0.0.1:1080", &config).is_err());
+        assert!(EgressPools::parse("jp=ftp://10.0.0.1", &config).is_err());
+        assert!(EgressPools::parse("", &config).unwrap().is_empty());
+
+        // Proxies resolve hosts themselves, so private networks can't be refused
+        assert!(EgressPools::parse("jp=http://10.1.0.1:3128", &ClientConfig::default()).is_err());
+    }
+}
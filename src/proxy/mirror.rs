@@ -0,0 +1,122 @@
+use url::Url;
+
+/// Groups of interchangeable upstream hosts (e.g. CDNs serving the same
+/// origin).
+///
+/// A request to any host of a group can be retried on the others, with the
+/// path and query unchanged. Hosts may include a port (`cdn.example.com:8443`).
+#[derive(Debug, Clone, Default)]
+pub struct Mirrors {
+    groups: Vec<Vec<String>>,
+}
+
+impl Mirrors {
+    /// Parse groups separated by `;`, each a comma-separated list of hosts.
+    ///
+    /// Example: `cdn-a.example.com,cdn-b.example.com;img1.example.net,img2.example.net`
+    pub fn parse(spec: &str) -> Self {
+        let groups = spec
+            .split(';')
+            .map(|group| {
+                group
+                    .split(',')
+                    .map(|host| host.trim().to_ascii_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|group| group.len() > 1)
+            .collect();
+
+        Self { groups }
+    }
+
+    /// Create mirrors from the `SHIZU_MIRRORS` environment variable.
+    pub fn from_env() -> Self {
+        std::env::var("SHIZU_MIRRORS")
+            .map(|spec| Self::parse(&spec))
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// URLs to try for `url`: the URL itself, then the same URL on each of
+    /// its mirrors in the configured order.
+    pub fn candidates(&self, url: &str) -> Vec<String> {
+        let mut candidates = vec![url.to_string()];
+
+        let Ok(parsed) = Url::parse(url) else {
+            return candidates;
+        };
+        let Some(authority) = authority(&parsed) else {
+            return candidates;
+        };
+        let Some(group) = self.groups.iter().find(|g| g.contains(&authority)) else {
+            return candidates;
+        };
+
+        for mirror in group.iter().filter(|host| **host != authority) {
+            if let Some(candidate) = with_authority(&parsed, mirror) {
+                candidates.push(candidate.to_string());
+            }
+        }
+        candidates
+    }
+}
+
+/// Host and, if explicit, port of a URL.
+pub(crate) fn authority(url: &Url) -> Option<String> {
+    let host = url.host_str()?.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host,
+    })
+}
+
+/// Replace the host and port of a URL with `authority`.
+pub(crate) fn with_authority(url: &Url, authority: &str) -> Option<Url> {
+    let mut url = url.clone();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !host.ends_with(']') || authority.starts_with('[') => {
+            match port.parse::<u16>() {
+                Ok(port) => (host, Some(port)),
+                Err(_) => (authority, None),
+            }
+        }
+        _ => (authority, None),
+    };
+
+    url.set_host(Some(host)).ok()?;
+    url.set_port(port).ok()?;
+    Some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let mirrors = Mirrors::parse("cdn-a.example.com, cdn-b.example.com,cdn-c.example.com:8443");
+
+        assert_eq!(
+            mirrors.candidates("https://cdn-b.example.com/live/seg1.ts?token=abc"),
+            [
+                "https://cdn-b.example.com/live/seg1.ts?token=abc",
+                "https://cdn-a.example.com/live/seg1.ts?token=abc",
+                "https://cdn-c.example.com:8443/live/seg1.ts?token=abc",
+            ]
+        );
+        assert_eq!(
+            mirrors.candidates("https://other.example.com/seg1.ts"),
+            ["https://other.example.com/seg1.ts"]
+        );
+    }
+
+    #[test]
+    fn test_ignores_single_host_groups() {
+        assert!(Mirrors::parse("cdn-a.example.com;").is_empty());
+        assert!(Mirrors::parse("").is_empty());
+    }
+}
@@ -1,8 +1,12 @@
+pub mod client_ip;
 pub mod handlers;
+pub mod keyring;
 pub mod params;
+pub mod request_log;
 pub mod router;
 pub mod signature;
 pub mod state;
+pub mod token;
 
 pub use router::create_router;
 pub use signature::SigningKey;
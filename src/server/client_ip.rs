@@ -0,0 +1,156 @@
+//! Client addresses of requests that come through reverse proxies.
+
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+
+use crate::{Error, Result};
+
+/// Reverse proxies whose `X-Forwarded-For` entries are trusted.
+///
+/// Without trusted proxies the client address is the peer address, so
+/// clients cannot claim another address with a forwarded header.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    /// Networks as address and prefix length.
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse comma-separated addresses and CIDR networks, e.g.
+    /// `10.0.0.0/8,127.0.0.1,fd00::/8`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let networks = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let invalid =
+                    || Error::InvalidParameter(format!("Invalid trusted proxy: {}", entry));
+                let (addr, prefix) = entry.split_once('/').unwrap_or((entry, ""));
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                let max = if addr.is_ipv4() { 32 } else { 128 };
+                let prefix = match prefix {
+                    "" => max,
+                    prefix => prefix
+                        .parse()
+                        .ok()
+                        .filter(|p| *p <= max)
+                        .ok_or_else(invalid)?,
+                };
+                Ok((addr, prefix))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { networks })
+    }
+
+    /// Create the list from the `SHIZU_TRUSTED_PROXIES` environment variable.
+    ///
+    /// An invalid value is logged and leaves no proxy trusted.
+    pub fn from_env() -> Self {
+        let Ok(spec) = std::env::var("SHIZU_TRUSTED_PROXIES") else {
+            return Self::default();
+        };
+
+        Self::parse(&spec).unwrap_or_else(|e| {
+            tracing::error!("Ignoring SHIZU_TRUSTED_PROXIES: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Check if an address belongs to a trusted proxy.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks
+            .iter()
+            .any(|(network, prefix)| match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    prefix_matches(network.to_bits().into(), ip.to_bits().into(), *prefix, 32)
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    prefix_matches(network.to_bits(), ip.to_bits(), *prefix, 128)
+                }
+                _ => false,
+            })
+    }
+
+    /// Address of the client of a request from `peer`.
+    ///
+    /// `X-Forwarded-For` is followed from the right only while the hop that
+    /// added an entry is a trusted proxy; the first address not belonging
+    /// to one is the client.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        let mut client = peer?.to_canonical();
+
+        let forwarded: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+
+        for entry in forwarded.into_iter().rev() {
+            if !self.contains(client) {
+                break;
+            }
+            match entry.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip.to_canonical(),
+                Err(_) => break,
+            }
+        }
+
+        Some(client)
+    }
+}
+
+/// Check if the first `prefix` of `bits` bits of two addresses are equal.
+fn prefix_matches(network: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+    let shift = u32::from(bits - prefix);
+    shift >= u32::from(bits) || (network >> shift) == (ip >> shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_contains() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8, 192.168.1.1, fd00::/8").unwrap();
+
+        assert!(proxies.contains("10.20.30.40".parse().unwrap()));
+        assert!(proxies.contains("192.168.1.1".parse().unwrap()));
+        assert!(proxies.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!(proxies.contains("fd12::1".parse().unwrap()));
+        assert!(!proxies.contains("192.168.1.2".parse().unwrap()));
+        assert!(!proxies.contains("11.0.0.1".parse().unwrap()));
+
+        assert!(TrustedProxies::parse("10.0.0.0/33").is_err());
+        assert!(TrustedProxies::parse("proxy.local").is_err());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let headers = forwarded("198.51.100.1, 203.0.113.7, 10.0.0.2");
+        let peer = Some("10.0.0.1".parse().unwrap());
+
+        // Forwarded headers from untrusted peers are ignored
+        let none = TrustedProxies::default();
+        assert_eq!(none.client_ip(&headers, peer), peer);
+
+        // Entries added by trusted proxies are skipped, the rest is the
+        // client's own claim
+        let proxies = TrustedProxies::parse("10.0.0.0/8").unwrap();
+        assert_eq!(
+            proxies.client_ip(&headers, peer),
+            Some("203.0.113.7".parse().unwrap())
+        );
+
+        assert_eq!(proxies.client_ip(&headers, None), None);
+    }
+}
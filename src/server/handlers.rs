@@ -1,5 +1,13 @@
+pub mod key;
 pub mod manifest;
+pub mod metrics;
+pub mod proxy;
 pub mod segment;
+pub mod token;
 
+pub use key::handle_key;
 pub use manifest::handle_manifest;
+pub use metrics::handle_metrics;
+pub use proxy::handle_proxy;
 pub use segment::handle_segment;
+pub use token::handle_token;
@@ -0,0 +1,41 @@
+use axum::{
+    extract::{Query, RawQuery, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    Result,
+    proxy::HeaderCodec,
+    server::{params::KeyParams, signature::ClientIdentity, state::AppState},
+};
+
+/// Handle GET /key requests.
+pub async fn handle_key(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Query(params): Query<KeyParams>,
+    RawQuery(query): RawQuery,
+) -> Result<Response> {
+    tracing::info!("Key request: {}", params.url);
+
+    // Verify URL signature to prevent SSRF attacks
+    state.verify_request("key", query.as_deref(), &identity)?;
+
+    // Decode headers
+    let headers = HeaderCodec::decode_optional(params.h.as_deref())?;
+
+    // Fetch the key (cached per URI and headers)
+    let client = state.client_for(params.egress.as_deref(), &params.url)?;
+    let key = state
+        .key_fetcher
+        .get_or_fetch(&params.url, &headers, &client)
+        .await?;
+    let key = *key.require_single()?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        key.to_vec(),
+    )
+        .into_response())
+}
@@ -1,32 +1,44 @@
 use axum::{
-    extract::{Query, State},
-    http::header,
+    extract::{Query, RawQuery, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use sha2::{Digest, Sha256};
+use url::Url;
 
 use crate::{
-    Error, Result,
+    Result,
     decrypt::DecryptionKey,
     proxy::HeaderCodec,
-    server::{params::ManifestParams, state::AppState},
-    stream::{StreamProcessor, TransformContext, rules},
+    server::{
+        params::ManifestParams,
+        signature::{ClientIdentity, SignatureScope, unix_time},
+        state::AppState,
+    },
+    stream::{
+        ManifestKind, MpdProcessor, ProcessorState, RenditionFilter, StreamProcessor,
+        TransformContext, VariantFilter, rules,
+    },
 };
 
+/// Cache lifetime in seconds for playlists that no longer change.
+const VOD_MAX_AGE: u64 = 86400;
+
 /// Handle GET /manifest requests.
 pub async fn handle_manifest(
     State(state): State<AppState>,
+    identity: ClientIdentity,
+    request_headers: HeaderMap,
     Query(params): Query<ManifestParams>,
+    RawQuery(query): RawQuery,
 ) -> Result<Response> {
     tracing::info!("Manifest request: {}", params.url);
 
     // Verify URL signature to prevent SSRF attacks
-    if !state.verify_signature(&params.url, params.sig.as_deref()) {
-        tracing::warn!("Invalid signature for URL: {}", params.url);
-        return Err(Error::InvalidSignature);
-    }
+    let binding = state.verify_request("manifest", query.as_deref(), &identity)?;
 
     // Parse original URL
-    let original_url = url::Url::parse(&params.url)?;
+    let original_url = Url::parse(&params.url)?;
 
     // Decode headers
     let manifest_headers = HeaderCodec::decode_optional(params.h.as_deref())?;
@@ -41,11 +53,32 @@ pub async fn handle_manifest(
 
     let decrypt_enabled = params.decrypt.unwrap_or(false);
 
+    let variant_filter = VariantFilter::from_params(
+        params.max_bw,
+        params.max_res.as_deref(),
+        params.codecs.as_deref(),
+        params.sort.as_deref(),
+    )?;
+    let rendition_filter = RenditionFilter::from_params(
+        params.lang.as_deref(),
+        params.media_type.as_deref(),
+        params.group.as_deref(),
+        params.default_lang.as_deref(),
+    )?;
+
     // Fetch the manifest
-    let content = state
-        .client
-        .fetch_text(&params.url, Some(&manifest_headers))
+    let fetch_url = delivery_directive_url(&original_url, &params);
+    let response = state
+        .client_for(params.egress.as_deref(), &params.url)?
+        .fetch_text_response(fetch_url.as_str(), Some(&manifest_headers))
         .await?;
+    let (content, upstream_headers) = (response.text, response.headers);
+    let served_url = Url::parse(&response.url)?;
+    let content_type = upstream_headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let kind = ManifestKind::detect(&content, content_type);
+    let scope = state.signature_scope(binding, &identity);
 
     // Create transform context
     let context = TransformContext::new(
@@ -54,24 +87,645 @@ pub async fn handle_manifest(
         params.sh.clone(),
         manifest_headers,
         segment_headers,
-        decryption_key,
-        decrypt_enabled,
         state.signing_key.clone(),
+    )
+    .with_decryption_key(decryption_key)
+    .with_decrypt(decrypt_enabled)
+    .with_aes128_decrypt(params.aes.unwrap_or(false))
+    .with_proxy_all(params.proxy.unwrap_or(false))
+    .with_variant_filter(variant_filter)
+    .with_rendition_filter(rendition_filter)
+    .with_served_url(&served_url)
+    .with_egress(params.egress.clone())
+    .with_signature_scope(scope.clone())
+    .with_token_cipher(state.tokens.clone());
+
+    // Process the manifest
+    let (transformed, max_age) = match kind {
+        ManifestKind::Hls => {
+            // Create processor with default rules
+            let rules = rules::default_rules();
+            let mut processor = StreamProcessor::new(context, rules);
+            let transformed = processor.process(&content);
+            (transformed, max_age(processor.state()))
+        }
+        ManifestKind::Dash => (MpdProcessor::new(context).process(&content)?, None),
+    };
+
+    tracing::debug!("Transformed {:?} manifest:\n{}", kind, transformed);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(kind.content_type()),
     );
+    if let Some(last_modified) = upstream_headers.get(header::LAST_MODIFIED) {
+        headers.insert(header::LAST_MODIFIED, last_modified.clone());
+    }
 
-    // Create processor with default rules
-    let rules = rules::default_rules();
-    let mut processor = StreamProcessor::new(context, rules);
+    // The output only depends on the upstream body, our parameters and the
+    // signatures in it, so the upstream ETag extended with the signature
+    // scope identifies it up to semantic equivalence
+    let now = unix_time();
+    let mut expires = scope.as_ref().map(|scope| scope.expires);
+    let mut etag = upstream_headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(|etag| playlist_etag(etag, scope.as_ref()));
+    let cached = etag.as_deref().and_then(|etag| {
+        let candidates = request_headers.get(header::IF_NONE_MATCH)?.to_str().ok()?;
+        matching_etag(candidates, etag, scope.as_ref(), now)
+    });
 
-    // Process the manifest
-    let transformed = processor.process(&content);
+    // A revalidated copy keeps the signatures it was served with
+    if let Some((cached, cached_expires)) = &cached {
+        expires = *cached_expires;
+        etag = Some(cached.clone());
+    }
 
-    tracing::debug!("Transformed manifest:\n{}", transformed);
+    let bound = scope.as_ref().is_some_and(|scope| scope.binding.is_some());
+    if let Ok(value) = HeaderValue::from_str(&cache_control(max_age, expires, bound, now)) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+        headers.insert(header::ETAG, etag);
+    }
 
-    // Return response with appropriate content type
-    Ok((
-        [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
-        transformed,
-    )
-        .into_response())
+    if cached.is_some() {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    Ok((headers, transformed).into_response())
+}
+
+/// Cache lifetime in seconds of a transformed HLS playlist, or `None` to
+/// always revalidate.
+///
+/// Live media playlists are cached for half a target duration, so players
+/// reloading once per target duration still see every update. Finished
+/// playlists never change; master playlists are always revalidated.
+fn max_age(state: &ProcessorState) -> Option<u64> {
+    if !state.is_media_playlist() {
+        return None;
+    }
+    if !state.is_live() {
+        return Some(VOD_MAX_AGE);
+    }
+    state.target_duration.map(|duration| (duration / 2).max(1))
+}
+
+/// Cache-Control for a transformed playlist whose signatures expire at
+/// `expires`.
+///
+/// Cached copies must not outlive their signatures, and signatures bound
+/// to a client are useless to anyone else, so shared caches must not keep
+/// them.
+fn cache_control(max_age: Option<u64>, expires: Option<u64>, bound: bool, now: u64) -> String {
+    let max_age = match expires {
+        Some(expires) => max_age.map(|age| age.min(expires.saturating_sub(now))),
+        None => max_age,
+    };
+    let directive = match max_age {
+        Some(age) if age > 0 => format!("max-age={}", age),
+        _ => "no-cache".to_string(),
+    };
+
+    if bound {
+        format!("private, {}", directive)
+    } else {
+        directive
+    }
+}
+
+/// Weak ETag of a transformed playlist.
+///
+/// The body has been rewritten, so the tag is weak. With signatures, the
+/// tag also covers their binding and ends with their expiry.
+fn playlist_etag(upstream: &str, scope: Option<&SignatureScope>) -> String {
+    let opaque = upstream.trim().trim_start_matches("W/").trim_matches('"');
+    match scope {
+        None => format!("W/\"{}\"", opaque),
+        Some(scope) => {
+            let binding = scope
+                .binding
+                .as_ref()
+                .map(|b| {
+                    let digest = Sha256::digest(format!("{}:{}", b.kind.as_str(), b.value));
+                    hex::encode(&digest[..8])
+                })
+                .unwrap_or_default();
+            format!("W/\"{}:{}:{}\"", opaque, binding, scope.expires)
+        }
+    }
+}
+
+/// Expiry of the signatures in a playlist with a signed ETag.
+fn etag_expiry(etag: &str) -> Option<u64> {
+    let (_, expires) = etag.trim_end_matches('"').rsplit_once(':')?;
+    expires.parse().ok()
+}
+
+/// Find the tag in an If-None-Match header that still matches `etag`,
+/// using weak comparison, and the expiry of the signatures in the copy it
+/// stands for.
+///
+/// A cached copy with signatures is only revalidated while at least half
+/// of their lifetime is left, so players never keep URLs about to expire.
+/// The expiry in a tag comes from the client, so it is capped at the
+/// current scope. `*` matches any copy (RFC 9110, section 13.1.2) and
+/// revalidates it as the current one.
+fn matching_etag(
+    if_none_match: &str,
+    etag: &str,
+    scope: Option<&SignatureScope>,
+    now: u64,
+) -> Option<(String, Option<u64>)> {
+    if if_none_match.trim() == "*" {
+        return Some((etag.to_string(), scope.map(|scope| scope.expires)));
+    }
+
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .find_map(|candidate| match scope {
+            None => {
+                (candidate.trim_start_matches("W/") == etag).then(|| (candidate.to_string(), None))
+            }
+            Some(scope) => {
+                let (unsigned, _) = etag.rsplit_once(':')?;
+                let (candidate_unsigned, _) =
+                    candidate.trim_start_matches("W/").rsplit_once(':')?;
+                let expires = etag_expiry(candidate)?.min(scope.expires);
+                let lifetime = scope.expires.saturating_sub(now);
+                (candidate_unsigned == unsigned && expires >= now + lifetime / 2)
+                    .then(|| (candidate.to_string(), Some(expires)))
+            }
+        })
+}
+
+/// Add the LL-HLS delivery directives sent by the player to the upstream
+/// URL, so blocking playlist reloads reach the origin.
+///
+/// Delta updates (`_HLS_skip`) are not requested: segments are numbered
+/// and decrypted from the full playlist.
+fn delivery_directive_url(url: &Url, params: &ManifestParams) -> Url {
+    let directives = [
+        ("_HLS_msn", &params.hls_msn),
+        ("_HLS_part", &params.hls_part),
+    ];
+
+    let mut url = url.clone();
+    for (name, value) in directives {
+        if let Some(value) = value {
+            url.query_pairs_mut().append_pair(name, value);
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proxy::ProxyClient, server::SigningKey};
+    use axum::{Router, body::Body, extract::RawQuery, http::Request, routing::get};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    /// Serve `upstream` on a local port and return the URL of `path`.
+    async fn spawn_upstream(upstream: Router, path: &str) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+        url
+    }
+
+    async fn get_manifest_with(query: &str, request_headers: &[(&str, &str)]) -> Response {
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::local();
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .with_state(state);
+
+        let mut request = Request::builder().uri(format!("/manifest?{}", query));
+        for (name, value) in request_headers {
+            request = request.header(*name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn get_manifest(query: &str) -> (Response, String) {
+        let response = get_manifest_with(query, &[]).await;
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (
+            Response::from_parts(parts, Body::empty()),
+            String::from_utf8_lossy(&body).into_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_detects_dash_manifest() {
+        let upstream = Router::new().route(
+            "/manifest.mpd",
+            get(|| async {
+                (
+                    [(header::CONTENT_TYPE, "application/dash+xml")],
+                    r#"<MPD><Period><AdaptationSet><Representation id="a"><BaseURL>a.mp4</BaseURL></Representation></AdaptationSet></Period></MPD>"#,
+                )
+            }),
+        );
+        let url = spawn_upstream(upstream, "/manifest.mpd").await;
+
+        let (response, body) = get_manifest(&format!("url={}", urlencoding::encode(&url))).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/dash+xml"
+        );
+        let expected = format!(
+            "<BaseURL>{}</BaseURL>",
+            url.replace("manifest.mpd", "a.mp4")
+        );
+        assert!(body.contains(&expected));
+    }
+
+    #[tokio::test]
+    async fn test_forwards_blocking_reload_directives() {
+        let upstream = Router::new().route(
+            "/live.m3u8",
+            get(|RawQuery(query): RawQuery| async move {
+                format!("#EXTM3U\n#EXT-X-QUERY:{}", query.unwrap_or_default())
+            }),
+        );
+        let url = spawn_upstream(upstream, "/live.m3u8").await;
+
+        let (response, body) = get_manifest(&format!(
+            "url={}&_HLS_msn=273&_HLS_part=2",
+            urlencoding::encode(&format!("{}?token=abc", url))
+        ))
+        .await;
+
+        assert_eq!(response.status(), 200);
+        assert!(body.ends_with("#EXT-X-QUERY:token=abc&_HLS_msn=273&_HLS_part=2"));
+    }
+
+    #[tokio::test]
+    async fn test_filters_renditions() {
+        let upstream = Router::new().route(
+            "/master.m3u8",
+            get(|| async {
+                "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en\",URI=\"en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Japanese\",LANGUAGE=\"ja\",URI=\"ja.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\"
+360p.m3u8"
+            }),
+        );
+        let url = spawn_upstream(upstream, "/master.m3u8").await;
+
+        let (response, body) = get_manifest(&format!(
+            "url={}&lang=ja&default_lang=ja",
+            urlencoding::encode(&url)
+        ))
+        .await;
+
+        assert_eq!(response.status(), 200);
+        assert!(!body.contains("NAME=\"English\""));
+        assert!(body.contains("NAME=\"Japanese\""));
+        assert!(body.contains(",DEFAULT=YES,AUTOSELECT=YES"));
+        assert!(body.contains("&lang=ja&default_lang=ja&sig="));
+    }
+
+    #[tokio::test]
+    async fn test_cache_control_for_live_and_vod() {
+        let upstream = Router::new()
+            .route(
+                "/live.m3u8",
+                get(|| async { "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts" }),
+            )
+            .route(
+                "/vod.m3u8",
+                get(|| async {
+                    "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST"
+                }),
+            )
+            .route(
+                "/master.m3u8",
+                get(|| async { "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=800000\nlive.m3u8" }),
+            );
+        let url = spawn_upstream(upstream, "").await;
+
+        for (path, expected) in [
+            ("/live.m3u8", "max-age=3"),
+            ("/vod.m3u8", "max-age=86400"),
+            ("/master.m3u8", "no-cache"),
+        ] {
+            let query = format!("url={}", urlencoding::encode(&format!("{}{}", url, path)));
+            let (response, _) = get_manifest(&query).await;
+            assert_eq!(response.headers()[header::CACHE_CONTROL], expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_passes_through_etag() {
+        let upstream = Router::new().route(
+            "/vod.m3u8",
+            get(|| async {
+                (
+                    [
+                        (header::ETAG, "\"abc\""),
+                        (header::LAST_MODIFIED, "Wed, 21 Oct 2026 07:28:00 GMT"),
+                    ],
+                    "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST",
+                )
+            }),
+        );
+        let url = spawn_upstream(upstream, "/vod.m3u8").await;
+        let query = format!("url={}", urlencoding::encode(&url));
+
+        let response = get_manifest_with(&query, &[]).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[header::ETAG], "W/\"abc\"");
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Wed, 21 Oct 2026 07:28:00 GMT"
+        );
+
+        let response = get_manifest_with(&query, &[("if-none-match", "W/\"abc\"")]).await;
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "max-age=86400");
+
+        let response = get_manifest_with(&query, &[("if-none-match", "\"other\"")]).await;
+        assert_eq!(response.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_scopes_caching_to_signatures() {
+        use crate::server::signature::BindingKind;
+        use std::time::Duration;
+
+        let upstream = Router::new().route(
+            "/vod.m3u8",
+            get(|| async {
+                (
+                    [(header::ETAG, "\"abc\"")],
+                    "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST",
+                )
+            }),
+        );
+        let url = spawn_upstream(upstream, "/vod.m3u8").await;
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::test_key();
+        state.signatures.ttl = Duration::from_secs(600);
+        state.signatures.binding = Some(BindingKind::Session);
+        state.client = ProxyClient::local();
+        let exp = (unix_time() + 60).to_string();
+        let params =
+            [("url", url.as_str()), ("exp", &exp)].map(|(k, v)| (k.to_string(), v.to_string()));
+        let uri = format!(
+            "/manifest?url={}&exp={}&sig={}",
+            urlencoding::encode(&url),
+            exp,
+            state.signing_key.sign_v2("manifest", &params, None)
+        );
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .with_state(state);
+
+        let get = |session: &str, if_none_match: Option<&str>| {
+            let mut request = Request::builder()
+                .uri(&uri)
+                .header("x-shizu-session", session);
+            if let Some(etag) = if_none_match {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        // Cached privately, and no longer than the signatures are valid
+        let response = get("alice", None).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let cache_control = response.headers()[header::CACHE_CONTROL].to_str().unwrap();
+        let max_age: u64 = cache_control
+            .strip_prefix("private, max-age=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((590..=600).contains(&max_age));
+
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let expires = etag_expiry(&etag).unwrap();
+        assert!(etag.starts_with("W/\"abc:"));
+        assert!(expires > unix_time() + 590);
+
+        // Revalidation keeps the tag, and the signatures, of the cached copy
+        let response = get("alice", Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+
+        // Not for copies whose signatures are about to expire
+        let expiring = etag.replace(&expires.to_string(), &(unix_time() + 10).to_string());
+        assert_eq!(get("alice", Some(&expiring)).await.unwrap().status(), 200);
+
+        // Nor for copies bound to another client
+        assert_eq!(get("bob", Some(&etag)).await.unwrap().status(), 200);
+
+        // Claimed expiries past the current signatures are capped
+        let extended = etag.replace(&expires.to_string(), &(expires + 86400).to_string());
+        let response = get("alice", Some(&extended)).await.unwrap();
+        assert_eq!(response.status(), 304);
+        let cache_control = response.headers()[header::CACHE_CONTROL].to_str().unwrap();
+        let max_age: u64 = cache_control
+            .strip_prefix("private, max-age=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(max_age <= 600);
+
+        // Any copy matches `*`, and becomes the current one
+        let response = get("alice", Some("*")).await.unwrap();
+        assert_eq!(response.status(), 304);
+        let current = response.headers()[header::ETAG].to_str().unwrap();
+        assert!(current.starts_with("W/\"abc:"));
+        assert!(etag_expiry(current).unwrap() >= expires);
+    }
+
+    #[tokio::test]
+    async fn test_sticks_to_serving_mirror() {
+        use crate::proxy::{ClientConfig, DestinationPolicy, Mirrors, RetryPolicy};
+
+        let primary = Router::new().route(
+            "/live.m3u8",
+            get(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+        );
+        let mirror = Router::new().route(
+            "/live.m3u8",
+            get(|| async { "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts" }),
+        );
+        let primary_url = spawn_upstream(primary, "/live.m3u8").await;
+        let mirror_url = spawn_upstream(mirror, "/live.m3u8").await;
+        let host = |url: &str| {
+            Url::parse(url).unwrap()[url::Position::BeforeHost..url::Position::AfterPort]
+                .to_string()
+        };
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::from_config(ClientConfig {
+            retry: RetryPolicy::none(),
+            destinations: DestinationPolicy::allow_all(),
+            mirrors: Mirrors::parse(&format!("{},{}", host(&primary_url), host(&mirror_url))),
+            ..Default::default()
+        });
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .with_state(state);
+
+        let request = Request::builder()
+            .uri(format!(
+                "/manifest?url={}&proxy=true",
+                urlencoding::encode(&primary_url)
+            ))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+        let segment = mirror_url.replace("live.m3u8", "seg1.ts");
+        assert!(body.contains(&*urlencoding::encode(&segment)));
+    }
+
+    #[tokio::test]
+    async fn test_fetches_through_egress_pool() {
+        use crate::proxy::{ClientConfig, DestinationPolicy, EgressPools};
+
+        // A forward proxy receives the absolute URL and serves it itself
+        let proxy = Router::new().fallback(|request: Request<Body>| async move {
+            format!(
+                "#EXTM3U\n#EXT-X-PROXIED:{}\n#EXTINF:6.0,\nseg1.ts",
+                request.uri()
+            )
+        });
+        let proxy_url = spawn_upstream(proxy, "").await;
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::local();
+        state.egress = Arc::new(
+            EgressPools::parse(
+                &format!("jp={}", proxy_url),
+                &ClientConfig {
+                    destinations: DestinationPolicy::allow_all(),
+                    ..Default::default()
+                },
+            )
+            .unwrap(),
+        );
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .with_state(state);
+
+        let request = |query: &str| {
+            Request::builder()
+                .uri(format!("/manifest?{}", query))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(request(
+                "url=http%3A%2F%2Forigin.invalid%2Flive.m3u8&egress=jp&proxy=true",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("#EXT-X-PROXIED:http://origin.invalid/live.m3u8"));
+        assert!(body.contains("&egress=jp&sig="));
+
+        let response = app
+            .oneshot(request(
+                "url=http%3A%2F%2Forigin.invalid%2Flive.m3u8&egress=us",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_emits_v2_signatures() {
+        use crate::server::handlers::handle_segment;
+
+        let upstream = Router::new()
+            .route(
+                "/live.m3u8",
+                get(|| async { "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts" }),
+            )
+            .route("/seg1.ts", get(|| async { "segment" }));
+        let url = spawn_upstream(upstream, "/live.m3u8").await;
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::test_key();
+        state.signatures.accept_legacy = true;
+        state.client = ProxyClient::local();
+        let sig = state.signing_key.sign(&url);
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .route("/segment.{ext}", get(handle_segment))
+            .with_state(state);
+
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        // A legacy signature is accepted when opted in, and the manifest is
+        // rewritten with v2 signatures
+        let response = get(format!(
+            "/manifest?url={}&proxy=true&sig={}",
+            urlencoding::encode(&url),
+            sig
+        ))
+        .await;
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let segment = String::from_utf8_lossy(&body)
+            .lines()
+            .last()
+            .unwrap()
+            .to_string();
+        assert!(segment.contains("&exp=") && segment.contains("&sig=v2."));
+
+        assert_eq!(get(segment.clone()).await.status(), 200);
+
+        // Changing any signed parameter invalidates the signature
+        let tampered = segment.replacen(
+            "/segment.ts?",
+            "/segment.ts?k=00000000000000000000000000000000&",
+            1,
+        );
+        assert_eq!(get(tampered).await.status(), 403);
+    }
 }
@@ -0,0 +1,114 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+use crate::{cache::CacheStats, server::state::AppState};
+
+/// Handle GET /metrics requests with cache counters in the Prometheus text
+/// format.
+///
+/// Requires the configured bearer token; without one, there is no such
+/// endpoint.
+pub async fn handle_metrics(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let Some(ref token) = state.metrics_token else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // Compare digests so the comparison time says nothing about the token
+    if provided.map(Sha256::digest) != Some(Sha256::digest(token.as_bytes())) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+        )
+            .into_response();
+    }
+
+    let mut body = String::new();
+    write_cache_stats(&mut body, "init_cache", &state.init_cache.stats());
+    if let Some(ref cache) = state.segment_cache {
+        write_cache_stats(&mut body, "segment_cache", &cache.stats());
+    }
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+fn write_cache_stats(out: &mut String, cache: &str, stats: &CacheStats) {
+    let metrics = [
+        ("hits_total", "counter", stats.hits),
+        ("misses_total", "counter", stats.misses),
+        ("evictions_total", "counter", stats.evictions),
+        ("expirations_total", "counter", stats.expirations),
+        ("entries", "gauge", stats.entries as u64),
+        ("size_bytes", "gauge", stats.size_bytes as u64),
+    ];
+
+    for (name, kind, value) in metrics {
+        let _ = writeln!(out, "# TYPE shizu_{}_{} {}", cache, name, kind);
+        let _ = writeln!(out, "shizu_{}_{} {}", cache, name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request, routing::get};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_requires_token() {
+        let status = |state: AppState, authorization: Option<&str>| {
+            let app = Router::new()
+                .route("/metrics", get(handle_metrics))
+                .with_state(state);
+            let mut request = Request::builder().uri("/metrics");
+            if let Some(value) = authorization {
+                request = request.header(header::AUTHORIZATION, value);
+            }
+            async move {
+                app.oneshot(request.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+
+        let mut state = AppState::new();
+        state.metrics_token = None;
+        assert_eq!(status(state.clone(), None).await, StatusCode::NOT_FOUND);
+
+        state.metrics_token = Some(Arc::from("secret"));
+        assert_eq!(status(state.clone(), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(state.clone(), Some("Bearer wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(state, Some("Bearer secret")).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_write_cache_stats() {
+        let stats = CacheStats {
+            hits: 3,
+            misses: 1,
+            entries: 1,
+            size_bytes: 512,
+            ..Default::default()
+        };
+        let mut out = String::new();
+        write_cache_stats(&mut out, "init_cache", &stats);
+
+        assert!(out.starts_with(
+            "# TYPE shizu_init_cache_hits_total counter\nshizu_init_cache_hits_total 3\n"
+        ));
+        assert!(out.contains("shizu_init_cache_evictions_total 0\n"));
+        assert!(out.ends_with("shizu_init_cache_size_bytes 512\n"));
+    }
+}
@@ -0,0 +1,103 @@
+use axum::{
+    body::Body,
+    extract::{Query, RawQuery, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    Result,
+    proxy::HeaderCodec,
+    server::{params::ProxyParams, signature::ClientIdentity, state::AppState},
+};
+
+/// Handle GET /proxy requests.
+///
+/// Streams any upstream resource unchanged, adding the request headers and
+/// CORS. The upstream content type and length are forwarded.
+pub async fn handle_proxy(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Query(params): Query<ProxyParams>,
+    RawQuery(query): RawQuery,
+) -> Result<Response> {
+    tracing::info!("Proxy request: {}", params.url);
+
+    // Verify URL signature to prevent SSRF attacks
+    state.verify_request("proxy", query.as_deref(), &identity)?;
+
+    // Decode headers
+    let headers = HeaderCodec::decode_optional(params.h.as_deref())?;
+
+    let upstream = state
+        .client_for(params.egress.as_deref(), &params.url)?
+        .fetch_stream(&params.url, Some(&headers), None)
+        .await?;
+
+    let forwarded: Vec<_> = [header::CONTENT_TYPE, header::CONTENT_LENGTH]
+        .into_iter()
+        .filter_map(|name| {
+            let value = upstream.headers().get(&name)?.clone();
+            Some((name, value))
+        })
+        .collect();
+
+    let mut response = Body::from_stream(upstream.bytes_stream()).into_response();
+    response.headers_mut().extend(forwarded);
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proxy::ProxyClient, server::SigningKey};
+    use axum::{Router, http::Request, routing::get};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_proxies_with_headers() {
+        let upstream = Router::new().route(
+            "/sub.vtt",
+            get(|headers: axum::http::HeaderMap| async move {
+                let cookie = headers.get("cookie").cloned();
+                (
+                    [(header::CONTENT_TYPE, "text/vtt")],
+                    format!("{:?}", cookie),
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sub.vtt", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::local();
+        let app = Router::new()
+            .route("/proxy", get(handle_proxy))
+            .with_state(state);
+
+        let h = HeaderCodec::encode(&[("Cookie".to_string(), "a=b".to_string())].into()).unwrap();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri(format!(
+                        "/proxy?url={}&h={}",
+                        urlencoding::encode(&url),
+                        urlencoding::encode(&h)
+                    ))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/vtt");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), br#"Some("a=b")"#);
+    }
+}
@@ -1,40 +1,53 @@
 use axum::{
     body::Body,
-    extract::{Path, Query, State},
-    http::header,
+    extract::{Path, Query, RawQuery, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use std::{collections::HashMap, io, time::Instant};
 
 use crate::{
     Error, Result,
+    cache::SegmentCacheKey,
     decrypt::{DecryptionKey, SegmentDecryptMethod, SegmentDecryptor},
     hls::{ByteRange, SegmentFormat},
-    proxy::HeaderCodec,
-    server::{params::SegmentParams, state::AppState},
+    proxy::{HeaderCodec, ProxyClient},
+    server::{params::SegmentParams, signature::ClientIdentity, state::AppState},
 };
 
 /// Handle GET /segment requests.
 pub async fn handle_segment(
     State(state): State<AppState>,
+    identity: ClientIdentity,
     path: Path<String>,
     Query(params): Query<SegmentParams>,
+    RawQuery(query): RawQuery,
+    request_headers: HeaderMap,
 ) -> Result<Response> {
     tracing::info!("Segment request: {}", params.url);
 
-    // Verify URL signature to prevent SSRF attacks
-    if !state.verify_signature(&params.url, params.sig.as_deref()) {
-        tracing::warn!("Invalid signature for URL: {}", params.url);
-        return Err(Error::InvalidSignature);
-    }
+    // Client Range header, if any
+    let range = request_headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok());
 
-    // Parse decryption method
-    let method = SegmentDecryptMethod::parse(&params.m)?;
+    // Verify URL signature to prevent SSRF attacks
+    state.verify_request("segment", query.as_deref(), &identity)?;
 
-    // Parse decryption key
-    let key = DecryptionKey::parse(&params.k)?;
+    // Upstream fetches for this request, including retries, share a deadline
+    let client = state
+        .client_for(params.egress.as_deref(), &params.url)?
+        .with_deadline(Instant::now() + state.segment_deadline);
 
-    // Parse IV (default to zeros if not provided)
-    let iv = parse_iv(params.iv.as_deref())?;
+    // Fill in DASH template identifiers after verifying the signed template
+    let url = params.template.expand(&params.url)?;
+    let init_url = params
+        .init
+        .as_deref()
+        .map(|init| params.template.expand(init))
+        .transpose()?;
 
     // Decode headers
     let headers = HeaderCodec::decode_optional(params.h.as_deref())?;
@@ -46,32 +59,130 @@ pub async fn handle_segment(
         .map(|br| ByteRange::parse(br))
         .transpose()?;
 
+    // Without a method, pipe the segment through unchanged
+    let Some(ref method) = params.m else {
+        let format = SegmentFormat::parse(&path);
+        return passthrough(&client, &url, &headers, byterange.as_ref(), format, range).await;
+    };
+
+    // Parse decryption method
+    let method = SegmentDecryptMethod::parse(method)?;
+
+    // Parse IV (default to zeros if not provided)
+    let iv = parse_iv(params.iv.as_deref())?;
+
+    // Use the provided key, or fetch it from the key URI
+    let key = match (&params.k, &params.ku) {
+        (Some(k), _) => DecryptionKey::parse(k)?,
+        (None, Some(ku)) => {
+            state
+                .key_fetcher
+                .get_or_fetch(ku, &headers, &client)
+                .await?
+        }
+        (None, None) => return Err(Error::KeyRequired),
+    };
+
     // Determine segment format from path extension or URL
     let format = SegmentFormat::from_extension(&path)?;
 
+    let init_byterange = params
+        .init_br
+        .as_ref()
+        .map(|br| ByteRange::parse(br))
+        .transpose()?;
+    let init = init_url
+        .as_deref()
+        .filter(|_| method.uses_init_segment())
+        .map(|init_url| (init_url, init_byterange.as_ref()));
+
+    // Cached segments are shared between viewers, so they are always
+    // decrypted in full rather than streamed
+    if let Some(ref cache) = state.segment_cache {
+        let cache_key = SegmentCacheKey::new(&url, &headers, byterange.as_ref())
+            .with_decryption(method, format, &key, &iv, init);
+        let decryptor = SegmentDecryptor::new(method, key, iv);
+        let decrypted = cache
+            .get_or_fetch(cache_key, || {
+                fetch_and_decrypt(
+                    &state,
+                    &client,
+                    &url,
+                    &headers,
+                    byterange.as_ref(),
+                    init,
+                    &decryptor,
+                    format,
+                )
+            })
+            .await?;
+        return Ok(ranged_response(decrypted, format.content_type(), range));
+    }
+
+    let decryptor = SegmentDecryptor::new(method, key, iv);
+
+    // Packet-based formats are decrypted while the segment downloads.
+    // Range requests need the full output, so those are buffered.
+    if decryptor.supports_streaming(format) && range.is_none() {
+        let upstream = client
+            .fetch_stream(&url, Some(&headers), byterange.as_ref())
+            .await?;
+        let input = upstream.bytes_stream().map_err(io::Error::other).boxed();
+        let output = decryptor.decrypt_stream(input, format)?;
+
+        tracing::debug!("Streaming decrypted segment, format: {:?}", format);
+
+        return Ok((
+            [
+                (header::CONTENT_TYPE, format.content_type()),
+                (header::ACCEPT_RANGES, "bytes"),
+            ],
+            Body::from_stream(output),
+        )
+            .into_response());
+    }
+
+    let decrypted = fetch_and_decrypt(
+        &state,
+        &client,
+        &url,
+        &headers,
+        byterange.as_ref(),
+        init,
+        &decryptor,
+        format,
+    )
+    .await?;
+
+    // Return response with appropriate content type
+    Ok(ranged_response(decrypted, format.content_type(), range))
+}
+
+/// Fetch a segment, and its init segment if needed, and decrypt it in memory.
+#[allow(clippy::too_many_arguments)]
+async fn fetch_and_decrypt(
+    state: &AppState,
+    client: &ProxyClient,
+    url: &str,
+    headers: &HashMap<String, String>,
+    byterange: Option<&ByteRange>,
+    init: Option<(&str, Option<&ByteRange>)>,
+    decryptor: &SegmentDecryptor,
+    format: SegmentFormat,
+) -> Result<Bytes> {
     // Fetch init segment if needed (for fMP4)
-    let init_data = if let Some(ref init_url) = params.init {
-        let init_byterange = params
-            .init_br
-            .as_ref()
-            .map(|br| ByteRange::parse(br))
-            .transpose()?;
-
-        Some(
+    let init_data = match init {
+        Some((init_url, init_byterange)) => Some(
             state
                 .init_cache
-                .get_or_fetch(init_url, &headers, init_byterange.as_ref(), &state.client)
+                .get_or_fetch(init_url, headers, init_byterange, client)
                 .await?,
-        )
-    } else {
-        None
+        ),
+        None => None,
     };
 
     // Fetch segment
-    let segment_data = state
-        .client
-        .fetch(&params.url, Some(&headers), byterange.as_ref())
-        .await?;
+    let segment_data = client.fetch(url, Some(headers), byterange).await?;
 
     tracing::debug!(
         "Fetched segment: {} bytes, format: {:?}",
@@ -79,18 +190,118 @@ pub async fn handle_segment(
         format
     );
 
-    // Create decryptor and decrypt
-    let decryptor = SegmentDecryptor::new(method, key, iv);
+    // Decrypt
     let decrypted = decryptor.decrypt(segment_data, init_data, format).await?;
 
     tracing::debug!("Decrypted segment: {} bytes", decrypted.len());
 
-    // Return response with appropriate content type
-    Ok((
-        [(header::CONTENT_TYPE, format.content_type())],
-        Body::from(decrypted),
+    Ok(decrypted)
+}
+
+/// Serve a complete body, applying the client Range header if present.
+fn ranged_response(data: Bytes, content_type: &'static str, range: Option<&str>) -> Response {
+    let total = data.len() as u64;
+    let headers = [
+        (header::CONTENT_TYPE, content_type),
+        (header::ACCEPT_RANGES, "bytes"),
+    ];
+
+    // Malformed ranges are ignored and the full body is served
+    match range.map(|r| ByteRange::from_range_header(r, total)) {
+        Some(Ok(Some(br))) => {
+            let start = br.offset.unwrap_or(0) as usize;
+            let end = start + br.length as usize;
+            (
+                StatusCode::PARTIAL_CONTENT,
+                headers,
+                [(header::CONTENT_RANGE, br.to_content_range(total))],
+                Body::from(data.slice(start..end)),
+            )
+                .into_response()
+        }
+        Some(Ok(None)) => range_not_satisfiable(total),
+        _ => (headers, Body::from(data)).into_response(),
+    }
+}
+
+fn range_not_satisfiable(total: u64) -> Response {
+    (
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        [(header::CONTENT_RANGE, format!("bytes */{}", total))],
     )
-        .into_response())
+        .into_response()
+}
+
+/// Stream a segment from upstream to the client without buffering.
+///
+/// A client Range is applied within the segment's own byte range, or
+/// forwarded upstream as-is when the segment is a whole resource.
+async fn passthrough(
+    client: &ProxyClient,
+    url: &str,
+    headers: &HashMap<String, String>,
+    byterange: Option<&ByteRange>,
+    format: SegmentFormat,
+    range: Option<&str>,
+) -> Result<Response> {
+    let mut upstream_headers = headers.clone();
+    let mut upstream_range = byterange.cloned();
+    let mut content_range = None;
+
+    match (range, byterange) {
+        (Some(range), Some(br)) => match ByteRange::from_range_header(range, br.length) {
+            Ok(Some(sub)) => {
+                let offset = br.offset.unwrap_or(0) + sub.offset.unwrap_or(0);
+                upstream_range = Some(ByteRange::new(sub.length, Some(offset)));
+                content_range = Some(sub.to_content_range(br.length));
+            }
+            Ok(None) => return Ok(range_not_satisfiable(br.length)),
+            Err(_) => {}
+        },
+        (Some(range), None) => {
+            upstream_headers.insert("Range".to_string(), range.to_string());
+        }
+        (None, _) => {}
+    }
+
+    let upstream = client
+        .fetch_stream(url, Some(&upstream_headers), upstream_range.as_ref())
+        .await?;
+
+    // Relay upstream partial responses to a forwarded Range
+    if content_range.is_none()
+        && byterange.is_none()
+        && upstream.status() == StatusCode::PARTIAL_CONTENT
+    {
+        content_range = upstream
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+    }
+    let content_length = upstream.headers().get(header::CONTENT_LENGTH).cloned();
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, format.content_type()),
+            (header::ACCEPT_RANGES, "bytes"),
+        ],
+        Body::from_stream(upstream.bytes_stream()),
+    )
+        .into_response();
+    if let Some(length) = content_length {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, length);
+    }
+    if let Some(content_range) = content_range
+        && let Ok(value) = content_range.parse()
+    {
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+
+    Ok(response)
 }
 
 /// Parse IV from hex string or return default zeros.
@@ -119,6 +330,254 @@ fn parse_iv(iv_str: Option<&str>) -> Result<[u8; 16]> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::server::SigningKey;
+    use aes::cipher::{BlockEncryptMut, KeyIvInit, block_padding::Pkcs7};
+    use axum::{Router, http::Request, routing::get};
+    use tower::ServiceExt;
+
+    /// Serve `body` at `/seg.ts` on a local upstream and return its URL.
+    ///
+    /// The upstream honours single Range requests.
+    async fn spawn_upstream(body: Vec<u8>) -> String {
+        let app = Router::new().route(
+            "/seg.ts",
+            get(move |headers: HeaderMap| async move {
+                let range = headers
+                    .get(header::RANGE)
+                    .and_then(|v| {
+                        ByteRange::from_range_header(v.to_str().unwrap(), body.len() as u64).ok()
+                    })
+                    .flatten();
+                match range {
+                    Some(br) => {
+                        let start = br.offset.unwrap() as usize;
+                        (
+                            StatusCode::PARTIAL_CONTENT,
+                            [(
+                                header::CONTENT_RANGE,
+                                br.to_content_range(body.len() as u64),
+                            )],
+                            body[start..start + br.length as usize].to_vec(),
+                        )
+                            .into_response()
+                    }
+                    None => body.clone().into_response(),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/seg.ts", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    async fn get_segment(query: &str, range: Option<&str>) -> Response {
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::local();
+        let app = Router::new()
+            .route("/segment.{ext}", get(handle_segment))
+            .with_state(state);
+
+        let mut request = Request::builder().uri(format!("/segment.ts?{}", query));
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body_bytes(response: Response) -> Bytes {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_without_method() {
+        let url = spawn_upstream(b"clear segment".to_vec()).await;
+        let response = get_segment(&format!("url={}", urlencoding::encode(&url)), None).await;
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "video/mp2t");
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "13");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), b"clear segment");
+    }
+
+    #[tokio::test]
+    async fn test_streams_sample_aes_ts() {
+        let mut packet = [0xFFu8; 188];
+        packet[..4].copy_from_slice(&[0x47, 0x1F, 0xFF, 0x10]);
+        let data = packet.repeat(500);
+
+        let url = spawn_upstream(data.clone()).await;
+        let response = get_segment(
+            &format!(
+                "url={}&m=ssa&k=0123456789abcdef0123456789abcdef",
+                urlencoding::encode(&url)
+            ),
+            None,
+        )
+        .await;
+
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let expected = SegmentDecryptor::new(
+            SegmentDecryptMethod::SampleAes,
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+            [0u8; 16],
+        )
+        .decrypt(Bytes::from(data), None, SegmentFormat::MpegTS)
+        .await
+        .unwrap();
+        assert_eq!(body, expected);
+    }
+
+    #[tokio::test]
+    async fn test_range_on_decrypted_segment() {
+        let key = [0x11u8; 16];
+        let plaintext = (0..=255u8).collect::<Vec<_>>().repeat(4);
+        let mut encrypted = plaintext.clone();
+        encrypted.resize(plaintext.len() + 16, 0);
+        cbc::Encryptor::<aes::Aes128>::new(&key.into(), &[0u8; 16].into())
+            .encrypt_padded_mut::<Pkcs7>(&mut encrypted, plaintext.len())
+            .unwrap();
+
+        let url = spawn_upstream(encrypted).await;
+        let query = format!(
+            "url={}&m=aes&k={}",
+            urlencoding::encode(&url),
+            hex::encode(key)
+        );
+
+        let response = get_segment(&query, Some("bytes=100-199")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            "bytes 100-199/1024"
+        );
+        assert_eq!(body_bytes(response).await.as_ref(), &plaintext[100..200]);
+
+        let response = get_segment(&query, Some("bytes=2000-")).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */1024");
+
+        // Malformed ranges are ignored
+        let response = get_segment(&query, Some("bytes=oops")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_bytes(response).await.len(), 1024);
+    }
+
+    #[tokio::test]
+    async fn test_range_within_segment_byterange() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let url = spawn_upstream(data.clone()).await;
+        let query = format!("url={}&br=50@20", urlencoding::encode(&url));
+
+        let response = get_segment(&query, Some("bytes=10-19")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 10-19/50");
+        assert_eq!(body_bytes(response).await.as_ref(), &data[30..40]);
+    }
+
+    #[tokio::test]
+    async fn test_range_forwarded_upstream() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let url = spawn_upstream(data.clone()).await;
+        let query = format!("url={}", urlencoding::encode(&url));
+
+        let response = get_segment(&query, Some("bytes=90-")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 90-99/100");
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body_bytes(response).await.as_ref(), &data[90..]);
+    }
+
+    #[tokio::test]
+    async fn test_expands_dash_template() {
+        let url = spawn_upstream(b"dash segment".to_vec()).await;
+        let template = url.replace("/seg.ts", "/$RepresentationID$");
+        let query = format!("url={}&rid=seg.ts", urlencoding::encode(&template));
+
+        let response = get_segment(&query, None).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(body_bytes(response).await.as_ref(), b"dash segment");
+    }
+
+    #[tokio::test]
+    async fn test_serves_cached_segment() {
+        use crate::cache::{SegmentCache, SegmentCacheConfig};
+        use std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        };
+
+        let key = [0x11u8; 16];
+        let plaintext = b"decrypted segment".to_vec();
+        let mut encrypted = plaintext.clone();
+        encrypted.resize(32, 0);
+        cbc::Encryptor::<aes::Aes128>::new(&key.into(), &[0u8; 16].into())
+            .encrypt_padded_mut::<Pkcs7>(&mut encrypted, plaintext.len())
+            .unwrap();
+
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let upstream = Router::new().route(
+            "/seg.ts",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                encrypted
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/seg.ts", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let mut state = AppState::new().with_segment_cache(SegmentCache::new(SegmentCacheConfig {
+            memory_bytes: 1024,
+            memory_ttl: std::time::Duration::from_secs(60),
+            disk_dir: None,
+            disk_ttl: std::time::Duration::from_secs(60),
+            disk_bytes: 0,
+        }));
+        state.signing_key = SigningKey::disabled();
+        state.client = ProxyClient::local();
+        let app = Router::new()
+            .route("/segment.{ext}", get(handle_segment))
+            .with_state(state);
+
+        let uri = format!(
+            "/segment.ts?url={}&m=aes&k={}",
+            urlencoding::encode(&url),
+            hex::encode(key)
+        );
+        for range in [None, Some("bytes=0-8")] {
+            let mut request = Request::builder().uri(&uri);
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let expected = if range.is_some() {
+                &plaintext[..9]
+            } else {
+                &plaintext[..]
+            };
+            assert_eq!(body_bytes(response).await.as_ref(), expected);
+        }
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
 
     #[test]
     fn test_parse_iv_with_prefix() {
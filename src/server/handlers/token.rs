@@ -0,0 +1,184 @@
+use axum::{
+    extract::{Path, Query, RawQuery, State},
+    http::{HeaderMap, Uri},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+
+use super::{handle_key, handle_manifest, handle_proxy, handle_segment};
+use crate::{
+    Error, Result,
+    server::{
+        signature::{ClientIdentity, UNSIGNED_PARAMS},
+        state::AppState,
+    },
+};
+
+/// The request an opaque URL stands for.
+///
+/// Set on the responses of token requests, so the request log records the
+/// decrypted request rather than the token.
+#[derive(Debug, Clone)]
+pub struct TokenTarget(pub Uri);
+
+/// Handle GET /s/{token}.{ext} requests.
+///
+/// The token is decrypted into the URL it stands for, which is served by
+/// its endpoint as if it had been requested directly. Parameters players
+/// fill in (DASH template values, LL-HLS directives) are taken from the
+/// query of the request.
+pub async fn handle_token(
+    State(state): State<AppState>,
+    identity: ClientIdentity,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+    request_headers: HeaderMap,
+) -> Result<Response> {
+    let cipher = state.tokens.as_ref().ok_or(Error::InvalidToken)?;
+
+    // The extension is only there for players
+    let token = path
+        .rsplit_once('.')
+        .map_or(path.as_str(), |(token, _)| token);
+    let target: Uri = cipher
+        .open(token)?
+        .parse()
+        .map_err(|_| Error::InvalidToken)?;
+
+    let inner_query = merge_query(target.query(), query.as_deref());
+    let uri: Uri = format!("{}?{}", target.path(), inner_query)
+        .parse()
+        .map_err(|_| Error::InvalidToken)?;
+    let raw_query = RawQuery(Some(inner_query));
+
+    let result = match uri.path() {
+        "/manifest" => {
+            handle_manifest(
+                State(state),
+                identity,
+                request_headers,
+                parse_query(&uri)?,
+                raw_query,
+            )
+            .await
+        }
+        "/key" => handle_key(State(state), identity, parse_query(&uri)?, raw_query).await,
+        "/proxy" => handle_proxy(State(state), identity, parse_query(&uri)?, raw_query).await,
+        path => {
+            let ext = path.strip_prefix("/segment.").ok_or(Error::InvalidToken)?;
+            handle_segment(
+                State(state),
+                identity,
+                Path(ext.to_string()),
+                parse_query(&uri)?,
+                raw_query,
+                request_headers,
+            )
+            .await
+        }
+    };
+
+    let mut response = result.into_response();
+    response.extensions_mut().insert(TokenTarget(uri));
+    Ok(response)
+}
+
+/// Add the parameters players fill in from the query of the request to
+/// the query of the decrypted URL.
+fn merge_query(inner: Option<&str>, outer: Option<&str>) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(inner.unwrap_or_default().to_string());
+    for (name, value) in url::form_urlencoded::parse(outer.unwrap_or_default().as_bytes()) {
+        if name != "sig" && UNSIGNED_PARAMS.contains(&name.as_ref()) {
+            query.append_pair(&name, &value);
+        }
+    }
+    query.finish()
+}
+
+/// Deserialize the parameters of a decrypted URL.
+fn parse_query<T: DeserializeOwned>(uri: &Uri) -> Result<Query<T>> {
+    Query::try_from_uri(uri).map_err(|e| Error::InvalidParameter(e.body_text()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proxy::ProxyClient,
+        server::{SigningKey, signature::unix_time, token::TokenCipher},
+    };
+    use axum::{Router, body::Body, http::Request, routing::get};
+    use tower::ServiceExt;
+
+    async fn get_body(app: &Router, uri: &str) -> (u16, String) {
+        let response = app
+            .clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_serves_opaque_urls() {
+        let upstream = Router::new()
+            .route(
+                "/live/index.m3u8",
+                get(|| async {
+                    "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST\n"
+                }),
+            )
+            .route("/live/seg1.ts", get(|| async { "segment-data" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await.unwrap() });
+
+        let mut state = AppState::new();
+        state.signing_key = SigningKey::test_key();
+        state.client = ProxyClient::local();
+        state.tokens = Some(TokenCipher::new(b"token-secret"));
+        let app = Router::new()
+            .route("/manifest", get(handle_manifest))
+            .route("/segment.{ext}", get(handle_segment))
+            .route("/s/{token}", get(handle_token))
+            .with_state(state.clone());
+
+        let url = format!("http://{}/live/index.m3u8", addr);
+        let exp = (unix_time() + 60).to_string();
+        let params = [("url", url.as_str()), ("proxy", "true"), ("exp", &exp)]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let (status, playlist) = get_body(
+            &app,
+            &format!(
+                "/manifest?url={}&proxy=true&exp={}&sig={}",
+                urlencoding::encode(&url),
+                exp,
+                state.signing_key.sign_v2("manifest", &params, None)
+            ),
+        )
+        .await;
+        assert_eq!(status, 200);
+
+        let segment = playlist.lines().find(|line| line.starts_with('/')).unwrap();
+        assert!(segment.starts_with("/s/") && segment.ends_with(".ts"));
+        assert!(!segment.contains('?'));
+        assert!(!playlist.contains(&addr.to_string()));
+
+        let (status, body) = get_body(&app, segment).await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "segment-data");
+
+        // Tokens from another key, or altered ones, are refused
+        let forged = format!(
+            "/s/{}.ts",
+            TokenCipher::new(b"other").seal("/segment.ts?url=x")
+        );
+        assert_eq!(get_body(&app, &forged).await.0, 403);
+        let altered = segment.replacen("/s/", "/s/A", 1);
+        assert_eq!(get_body(&app, &altered).await.0, 403);
+    }
+}
@@ -0,0 +1,150 @@
+//! Signing keys with ids, for rotating keys without invalidating URLs
+//! already handed out.
+
+use std::{path::Path, sync::Arc};
+
+use crate::{Error, Result};
+
+/// A signing key and its id.
+#[derive(Clone)]
+pub struct KeyEntry {
+    /// Id embedded in v2 signatures made with this key. Keys without an id
+    /// (a single `SHIZU_SIGNING_KEY`) sign without one.
+    pub id: Option<String>,
+    pub key: Arc<[u8]>,
+}
+
+/// Verification keys, the first of which is the active signing key.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: Vec<KeyEntry>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("ids", &self.ids().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// A keyring with a single key without id.
+    pub fn single(key: impl Into<Vec<u8>>) -> Self {
+        Self {
+            keys: vec![KeyEntry {
+                id: None,
+                key: key.into().into(),
+            }],
+        }
+    }
+
+    /// Parse `id:key` entries separated by commas or newlines. Blank lines
+    /// and lines starting with `#` are skipped.
+    ///
+    /// Keys are hex-decoded when possible and used as raw bytes otherwise.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut keys: Vec<KeyEntry> = Vec::new();
+
+        for entry in spec
+            .split(['\n', ','])
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty() && !entry.starts_with('#'))
+        {
+            let (id, key) = entry
+                .split_once(':')
+                .map(|(id, key)| (id.trim(), key.trim()))
+                .filter(|(id, key)| !id.is_empty() && !key.is_empty())
+                .ok_or_else(|| {
+                    Error::InvalidKeyFormat("signing keys must be given as id:key".to_string())
+                })?;
+
+            if id.contains('.') {
+                return Err(Error::InvalidKeyFormat(format!(
+                    "signing key id must not contain '.': {}",
+                    id
+                )));
+            }
+            if keys.iter().any(|k| k.id.as_deref() == Some(id)) {
+                return Err(Error::InvalidKeyFormat(format!(
+                    "duplicate signing key id: {}",
+                    id
+                )));
+            }
+
+            keys.push(KeyEntry {
+                id: Some(id.to_string()),
+                key: decode_key(key).into(),
+            });
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// Load keys from a file in the format of [`Keyring::parse`].
+    pub fn load(path: &Path) -> Result<Self> {
+        let spec = std::fs::read_to_string(path)
+            .map_err(|e| Error::Internal(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::parse(&spec)
+    }
+
+    /// Key used to sign new URLs.
+    pub fn active(&self) -> Option<&KeyEntry> {
+        self.keys.first()
+    }
+
+    /// Key with the given id.
+    pub fn get(&self, id: &str) -> Option<&KeyEntry> {
+        self.keys.iter().find(|k| k.id.as_deref() == Some(id))
+    }
+
+    /// All keys, starting with the active one.
+    pub fn iter(&self) -> impl Iterator<Item = &KeyEntry> {
+        self.keys.iter()
+    }
+
+    /// Ids of the keys, for logging.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.keys
+            .iter()
+            .map(|k| k.id.as_deref().unwrap_or("<no id>"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+/// Decode a key from hex, falling back to the string's bytes.
+pub fn decode_key(key: &str) -> Vec<u8> {
+    hex::decode(key).unwrap_or_else(|_| key.as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let keyring = Keyring::parse(
+            "# rotated 2026-10\n2026b:00112233445566778899aabbccddeeff\n\n2026a: old-secret\n",
+        )
+        .unwrap();
+
+        assert_eq!(keyring.ids().collect::<Vec<_>>(), ["2026b", "2026a"]);
+        assert_eq!(keyring.active().unwrap().id.as_deref(), Some("2026b"));
+        assert_eq!(keyring.active().unwrap().key.len(), 16);
+        assert_eq!(&*keyring.get("2026a").unwrap().key, b"old-secret");
+
+        let keyring = Keyring::parse("a:one,b:two").unwrap();
+        assert_eq!(keyring.ids().collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[test]
+    fn test_rejects_invalid_entries() {
+        assert!(Keyring::parse("no-id").is_err());
+        assert!(Keyring::parse("a.b:key").is_err());
+        assert!(Keyring::parse("a:one,a:two").is_err());
+        assert!(Keyring::parse("").unwrap().is_empty());
+    }
+}
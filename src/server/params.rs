@@ -1,9 +1,11 @@
 use serde::Deserialize;
 
+use crate::dash::TemplateValues;
+
 /// Query parameters for the /manifest endpoint.
 #[derive(Debug, Deserialize)]
 pub struct ManifestParams {
-    /// URL of the M3U8 manifest.
+    /// URL of the M3U8 or MPD manifest.
     pub url: String,
 
     /// Base64url-encoded JSON headers for manifest fetch.
@@ -22,7 +24,99 @@ pub struct ManifestParams {
     #[serde(default)]
     pub decrypt: Option<bool>,
 
-    /// HMAC-SHA256 signature of the URL (hex encoded).
+    /// Whether to decrypt AES-128 segments on the server.
+    #[serde(default)]
+    pub aes: Option<bool>,
+
+    /// Whether to route every segment through the proxy.
+    #[serde(default)]
+    pub proxy: Option<bool>,
+
+    /// Maximum variant bandwidth in bits per second.
+    #[serde(default)]
+    pub max_bw: Option<u64>,
+
+    /// Maximum variant resolution: `WIDTHxHEIGHT` or a height (`720p`).
+    #[serde(default)]
+    pub max_res: Option<String>,
+
+    /// Comma-separated codec prefixes to keep (e.g. `avc1`).
+    #[serde(default)]
+    pub codecs: Option<String>,
+
+    /// Order variants by bandwidth: `asc` or `desc`.
+    #[serde(default)]
+    pub sort: Option<String>,
+
+    /// Comma-separated rendition languages to keep (e.g. `en,ja`).
+    #[serde(default)]
+    pub lang: Option<String>,
+
+    /// Comma-separated rendition types to keep (e.g. `AUDIO,SUBTITLES`).
+    #[serde(default, rename = "type")]
+    pub media_type: Option<String>,
+
+    /// Comma-separated rendition group IDs to keep.
+    #[serde(default)]
+    pub group: Option<String>,
+
+    /// Language to mark as the default rendition of each group.
+    #[serde(default)]
+    pub default_lang: Option<String>,
+
+    /// LL-HLS blocking reload: media sequence number to wait for.
+    #[serde(default, rename = "_HLS_msn")]
+    pub hls_msn: Option<String>,
+
+    /// LL-HLS blocking reload: part number to wait for.
+    #[serde(default, rename = "_HLS_part")]
+    pub hls_part: Option<String>,
+
+    /// Named outbound proxy pool to fetch through.
+    #[serde(default)]
+    pub egress: Option<String>,
+
+    /// Signature of the URL and egress (v1, hex) or of all parameters (`v2.<hex>`).
+    /// Required when SHIZU_SIGNING_KEY is set to prevent SSRF attacks.
+    #[serde(default)]
+    pub sig: Option<String>,
+}
+
+/// Query parameters for the /proxy endpoint.
+#[derive(Debug, Deserialize)]
+pub struct ProxyParams {
+    /// URL of the resource.
+    pub url: String,
+
+    /// Base64url-encoded JSON headers.
+    #[serde(default)]
+    pub h: Option<String>,
+
+    /// Named outbound proxy pool to fetch through.
+    #[serde(default)]
+    pub egress: Option<String>,
+
+    /// Signature of the URL and egress (v1, hex) or of all parameters (`v2.<hex>`).
+    /// Required when SHIZU_SIGNING_KEY is set to prevent SSRF attacks.
+    #[serde(default)]
+    pub sig: Option<String>,
+}
+
+/// Query parameters for the /key endpoint.
+#[derive(Debug, Deserialize)]
+pub struct KeyParams {
+    /// URL of the key.
+    pub url: String,
+
+    /// Base64url-encoded JSON headers.
+    #[serde(default)]
+    pub h: Option<String>,
+
+    /// Named outbound proxy pool to fetch through.
+    #[serde(default)]
+    pub egress: Option<String>,
+
+    /// Signature of the URL and egress (v1, hex) or of all parameters (`v2.<hex>`).
     /// Required when SHIZU_SIGNING_KEY is set to prevent SSRF attacks.
     #[serde(default)]
     pub sig: Option<String>,
@@ -39,14 +133,21 @@ pub struct SegmentParams {
     pub h: Option<String>,
 
     /// Decryption key in hex format.
-    pub k: String,
+    #[serde(default)]
+    pub k: Option<String>,
+
+    /// Key URI to fetch the key from when `k` is not given.
+    #[serde(default)]
+    pub ku: Option<String>,
 
     /// IV in hex format.
     #[serde(default)]
     pub iv: Option<String>,
 
-    /// Decryption method: ssa, ssa-ctr, cenc.
-    pub m: String,
+    /// Decryption method: ssa, ssa-ctr, cenc, aes.
+    /// Without a method the segment is passed through unchanged.
+    #[serde(default)]
+    pub m: Option<String>,
 
     /// Init segment URL (for fMP4).
     #[serde(default)]
@@ -60,7 +161,15 @@ pub struct SegmentParams {
     #[serde(default)]
     pub init_br: Option<String>,
 
-    /// HMAC-SHA256 signature of the URL (hex encoded).
+    /// Values for DASH `SegmentTemplate` identifiers in `url` and `init`.
+    #[serde(flatten)]
+    pub template: TemplateValues,
+
+    /// Named outbound proxy pool to fetch through.
+    #[serde(default)]
+    pub egress: Option<String>,
+
+    /// Signature of the URL and egress (v1, hex) or of all parameters (`v2.<hex>`).
     /// Required when SHIZU_SIGNING_KEY is set to prevent SSRF attacks.
     #[serde(default)]
     pub sig: Option<String>,
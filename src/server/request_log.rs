@@ -0,0 +1,259 @@
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Query, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, net::SocketAddr, time::Instant};
+
+use crate::{error::ErrorInfo, logging::RequestLogRecord};
+
+use super::{handlers::token::TokenTarget, state::AppState};
+
+/// Middleware that records a [`RequestLogRecord`] for every request it wraps
+/// and hands it to the configured log sink.
+///
+/// Token requests are recorded as the request their token stands for.
+pub async fn log_request(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let start = Instant::now();
+
+    let uri = request.uri().clone();
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client_ip = state
+        .trusted_proxies
+        .client_ip(request.headers(), peer)
+        .map(|ip| ip.to_string());
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+
+    let response = next.run(request).await;
+
+    let uri = match response.extensions().get::<TokenTarget>() {
+        Some(TokenTarget(target)) => target,
+        None => &uri,
+    };
+    let params = Query::<HashMap<String, String>>::try_from_uri(uri)
+        .map(|Query(params)| params)
+        .unwrap_or_default();
+
+    let record = build_record(uri.path(), &params, &response, start)
+        .with_client_info(client_ip.as_deref(), user_agent.as_deref());
+    state.logger.log(record);
+
+    response
+}
+
+fn build_record(
+    endpoint: &str,
+    params: &HashMap<String, String>,
+    response: &Response,
+    start: Instant,
+) -> RequestLogRecord {
+    let param = |name: &str| params.get(name).map(String::as_str);
+    let url = param("url").unwrap_or_default();
+
+    // Manifest requests carry their headers in `h` and `sh`, everything
+    // else fetches with the segment headers in `h`. They hold origin
+    // credentials, so only a fingerprint is logged.
+    let h = param("h").map(fingerprint);
+    let record = if !endpoint.starts_with("/manifest") {
+        RequestLogRecord::new(endpoint, url).with_headers(None, h.as_deref())
+    } else {
+        let sh = param("sh").map(fingerprint);
+        RequestLogRecord::new(endpoint, url).with_headers(h.as_deref(), sh.as_deref())
+    };
+
+    let key = param("k");
+    let key_type = match key {
+        Some(k) if k.contains(':') => Some("multi"),
+        Some(_) => Some("single"),
+        None => param("ku").map(|_| "uri"),
+    };
+    let decrypt_enabled =
+        param("m").is_some() || param("decrypt") == Some("true") || param("aes") == Some("true");
+
+    let mut record = record
+        .with_key_info(key_type.is_some(), key_type)
+        .with_decrypt(decrypt_enabled)
+        .with_response(
+            response.status().as_u16(),
+            start.elapsed().as_millis() as i64,
+            content_length(response),
+        );
+
+    if let Some(info) = response.extensions().get::<ErrorInfo>() {
+        record = record.with_error(info.code, &info.message);
+    }
+
+    record
+}
+
+/// Fingerprint of an encoded header set, so requests with the same headers
+/// can be grouped without logging the headers.
+fn fingerprint(headers: &str) -> String {
+    let digest = Sha256::digest(headers.as_bytes());
+    format!("sha256:{}", hex::encode(&digest[..8]))
+}
+
+fn content_length(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .or_else(|| response.body().size_hint().exact())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Error,
+        logging::RequestLogSink,
+        server::{
+            SigningKey, client_ip::TrustedProxies, handlers::token::handle_token,
+            token::TokenCipher,
+        },
+    };
+    use axum::{Router, body::Body, middleware, response::IntoResponse, routing::get};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct CollectingSink(Mutex<Vec<RequestLogRecord>>);
+
+    impl RequestLogSink for CollectingSink {
+        fn log(&self, record: RequestLogRecord) {
+            self.0.lock().unwrap().push(record);
+        }
+    }
+
+    async fn send(uri: &str, headers: &[(&str, &str)]) -> RequestLogRecord {
+        let sink = Arc::new(CollectingSink::default());
+        let mut state = AppState::new().with_logger(sink.clone());
+        state.trusted_proxies = Arc::new(TrustedProxies::parse("10.0.0.0/8").unwrap());
+
+        let app = Router::new()
+            .route("/manifest", get(|| async { "#EXTM3U\n" }))
+            .route(
+                "/segment.{ext}",
+                get(|| async { Error::InvalidSignature.into_response() }),
+            )
+            .route_layer(middleware::from_fn_with_state(state.clone(), log_request))
+            .with_state(state);
+
+        let mut request = Request::builder()
+            .uri(uri)
+            .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 40000))));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let mut records = sink.0.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        records.pop().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_logs_successful_manifest() {
+        let record = send(
+            "/manifest?url=https%3A%2F%2Fexample.com%2Fa.m3u8&k=kid:key&decrypt=true&sh=abc",
+            &[
+                ("user-agent", "test-player"),
+                ("x-forwarded-for", "203.0.113.7, 10.0.0.1"),
+            ],
+        )
+        .await;
+
+        assert_eq!(record.endpoint, "/manifest");
+        assert_eq!(record.original_url, "https://example.com/a.m3u8");
+        assert_eq!(record.response_status, 200);
+        assert_eq!(record.content_length, Some(8));
+        assert!(record.key_provided);
+        assert_eq!(record.key_type.as_deref(), Some("multi"));
+        assert!(record.decrypt_enabled);
+        assert_eq!(record.segment_headers, Some(fingerprint("abc")));
+        assert!(!record.segment_headers.unwrap().contains("abc"));
+        assert_eq!(record.client_ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(record.user_agent.as_deref(), Some("test-player"));
+        assert!(record.error_type.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_logs_error_code() {
+        let record = send(
+            "/segment.ts?url=https%3A%2F%2Fexample.com%2F1.ts&m=ssa&h=xyz",
+            &[],
+        )
+        .await;
+
+        assert_eq!(record.endpoint, "/segment.ts");
+        assert_eq!(record.response_status, 403);
+        assert_eq!(record.error_type.as_deref(), Some("INVALID_SIGNATURE"));
+        assert!(record.decrypt_enabled);
+        assert!(!record.key_provided);
+        assert_eq!(record.segment_headers, Some(fingerprint("xyz")));
+        assert!(record.manifest_headers.is_none());
+        assert_eq!(record.client_ip.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_logs_token_target() {
+        let sink = Arc::new(CollectingSink::default());
+        let mut state = AppState::new().with_logger(sink.clone());
+        state.signing_key = SigningKey::test_key();
+        state.tokens = Some(TokenCipher::new(b"token-secret"));
+        let app = Router::new()
+            .route("/s/{token}", get(handle_token))
+            .route_layer(middleware::from_fn_with_state(state.clone(), log_request))
+            .with_state(state);
+
+        let token = TokenCipher::new(b"token-secret")
+            .seal("/segment.ts?url=https%3A%2F%2Fexample.com%2F1.ts&m=ssa&h=xyz");
+        let request = Request::builder()
+            .uri(format!("/s/{}.ts", token))
+            .extension(ConnectInfo(SocketAddr::from(([198, 51, 100, 9], 40000))))
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let record = sink.0.lock().unwrap().pop().unwrap();
+        assert_eq!(record.endpoint, "/segment.ts");
+        assert_eq!(record.original_url, "https://example.com/1.ts");
+        assert_eq!(record.error_type.as_deref(), Some("INVALID_SIGNATURE"));
+        assert!(record.decrypt_enabled);
+        assert_eq!(record.segment_headers, Some(fingerprint("xyz")));
+    }
+
+    #[tokio::test]
+    async fn test_ignores_forwarded_for_from_untrusted_peer() {
+        let sink = Arc::new(CollectingSink::default());
+        let state = AppState::new().with_logger(sink.clone());
+        let app = Router::new()
+            .route("/manifest", get(|| async { "#EXTM3U\n" }))
+            .route_layer(middleware::from_fn_with_state(state.clone(), log_request))
+            .with_state(state);
+
+        let request = Request::builder()
+            .uri("/manifest?url=x")
+            .header("x-forwarded-for", "203.0.113.7")
+            .extension(ConnectInfo(SocketAddr::from(([198, 51, 100, 9], 40000))))
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let record = sink.0.lock().unwrap().pop().unwrap();
+        assert_eq!(record.client_ip.as_deref(), Some("198.51.100.9"));
+    }
+}
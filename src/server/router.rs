@@ -1,17 +1,30 @@
-use axum::{Json, Router, http::Method, routing::get};
+use axum::{Json, Router, http::Method, middleware, routing::get};
+use std::time::Duration;
 use tower_http::{
     cors::{Any, CorsLayer},
     trace::TraceLayer,
 };
 
+use crate::logging;
+
 use super::{
-    handlers::{handle_manifest, handle_segment},
+    handlers::{
+        handle_key, handle_manifest, handle_metrics, handle_proxy, handle_segment, handle_token,
+    },
+    request_log::log_request,
     state::AppState,
 };
 
 /// Create the application router.
 pub async fn create_router() -> anyhow::Result<Router> {
-    let state = AppState::new();
+    let state = AppState::new().with_logger(logging::sink_from_env().await);
+
+    // Pick up rotated signing keys without a restart
+    let reload_secs = std::env::var("SHIZU_SIGNING_KEYS_RELOAD_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(30);
+    state.signing_key.watch(Duration::from_secs(reload_secs));
 
     // Configure CORS
     let cors_origin = std::env::var("CORS_ALLOWED_ORIGIN").unwrap_or_else(|_| "*".to_string());
@@ -30,7 +43,12 @@ pub async fn create_router() -> anyhow::Result<Router> {
     let app = Router::new()
         .route("/manifest", get(handle_manifest))
         .route("/segment.{ext}", get(handle_segment))
+        .route("/key", get(handle_key))
+        .route("/proxy", get(handle_proxy))
+        .route("/s/{token}", get(handle_token))
+        .route_layer(middleware::from_fn_with_state(state.clone(), log_request))
         .route("/health", get(health_check))
+        .route("/metrics", get(handle_metrics))
         .layer(cors)
         .layer(TraceLayer::new_for_http())
         .with_state(state);
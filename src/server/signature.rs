@@ -3,20 +3,49 @@
 //! This module provides HMAC-SHA256 based URL signing and verification.
 //! Only URLs signed with the server's secret key can be fetched.
 //!
+//! Legacy (v1) signatures cover the target URL, the parameters that pick
+//! another upstream (`egress`, `ku`) and the request headers (`h`, `sh`).
+//! v2 signatures, written as `sig=v2.<kid>.<hex>`, cover the endpoint,
+//! every query parameter, an expiry (`exp`) and optionally the client IP or
+//! session (`bind`). The key id selects the verification key, so keys can
+//! be rotated.
+//!
 //! If no signing key is configured, signature validation is bypassed
 //! (with a warning logged at startup).
 
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use std::sync::Arc;
+use std::{
+    borrow::Cow,
+    convert::Infallible,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    keyring::{Keyring, decode_key},
+    state::AppState,
+};
+use crate::{Error, Result};
 
 type HmacSha256 = Hmac<Sha256>;
 
-/// Optional signing key for URL verification.
+/// Optional signing keys for URL verification.
 /// When `None`, signature validation is bypassed.
+///
+/// Clones share the keyring, so a reload applies to all of them.
 #[derive(Clone)]
 pub struct SigningKey {
-    key: Option<Arc<[u8]>>,
+    keyring: Option<Arc<RwLock<Arc<Keyring>>>>,
+
+    /// File the keyring is reloaded from.
+    file: Option<Arc<PathBuf>>,
 }
 
 impl std::fmt::Debug for SigningKey {
@@ -24,7 +53,7 @@ impl std::fmt::Debug for SigningKey {
         f.debug_struct("SigningKey")
             .field(
                 "key",
-                &if self.key.is_some() {
+                &if self.keyring.is_some() {
                     "[REDACTED]"
                 } else {
                     "[DISABLED]"
@@ -37,14 +66,24 @@ impl std::fmt::Debug for SigningKey {
 impl SigningKey {
     /// Create a new signing key from bytes.
     pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self::from_keyring(Keyring::single(key))
+    }
+
+    /// Create a signing key that signs with the keyring's active key and
+    /// verifies with all of its keys.
+    pub fn from_keyring(keyring: Keyring) -> Self {
         Self {
-            key: Some(key.into().into()),
+            keyring: Some(Arc::new(RwLock::new(Arc::new(keyring)))),
+            file: None,
         }
     }
 
     /// Create a disabled signing key (bypasses validation).
     pub fn disabled() -> Self {
-        Self { key: None }
+        Self {
+            keyring: None,
+            file: None,
+        }
     }
 
     /// Create a test signing key (for testing only).
@@ -53,9 +92,45 @@ impl SigningKey {
         Self::new(b"test-signing-key-for-tests".to_vec())
     }
 
-    /// Create a signing key from the SHIZU_SIGNING_KEY environment variable.
-    /// If not set, returns a disabled key and logs a warning.
+    /// Create signing keys from the environment.
+    ///
+    /// Keys are read from the file in `SHIZU_SIGNING_KEYS_FILE`, the
+    /// `id:key` list in `SHIZU_SIGNING_KEYS`, or the single key in
+    /// `SHIZU_SIGNING_KEY`, in that order. If none is set, returns a
+    /// disabled key and logs a warning.
     pub fn from_env() -> Self {
+        if let Ok(path) = std::env::var("SHIZU_SIGNING_KEYS_FILE") {
+            let path = PathBuf::from(path);
+            // Fail closed: an unreadable file leaves no valid keys until a
+            // reload succeeds
+            let keyring = Keyring::load(&path).unwrap_or_else(|e| {
+                tracing::error!("No signing keys loaded: {}", e);
+                Keyring::default()
+            });
+            tracing::info!(
+                "URL signature validation is enabled with keys {:?}",
+                keyring.ids().collect::<Vec<_>>()
+            );
+            return Self {
+                file: Some(Arc::new(path)),
+                ..Self::from_keyring(keyring)
+            };
+        }
+
+        if let Ok(keys) = std::env::var("SHIZU_SIGNING_KEYS")
+            && !keys.is_empty()
+        {
+            let keyring = Keyring::parse(&keys).unwrap_or_else(|e| {
+                tracing::error!("Invalid SHIZU_SIGNING_KEYS, no signing keys loaded: {}", e);
+                Keyring::default()
+            });
+            tracing::info!(
+                "URL signature validation is enabled with keys {:?}",
+                keyring.ids().collect::<Vec<_>>()
+            );
+            return Self::from_keyring(keyring);
+        }
+
         if let Ok(key) = std::env::var("SHIZU_SIGNING_KEY") {
             if key.is_empty() {
                 tracing::warn!("SHIZU_SIGNING_KEY is empty, signature validation is DISABLED");
@@ -63,7 +138,7 @@ impl SigningKey {
                 return Self::disabled();
             }
             // Try to decode as hex first, fall back to using the string as bytes
-            let key_bytes = hex::decode(&key).unwrap_or_else(|_| key.into_bytes());
+            let key_bytes = decode_key(&key);
             tracing::info!("URL signature validation is enabled");
             Self::new(key_bytes)
         } else {
@@ -78,26 +153,97 @@ impl SigningKey {
 
     /// Check if signature validation is enabled.
     pub fn is_enabled(&self) -> bool {
-        self.key.is_some()
+        self.keyring.is_some()
+    }
+
+    /// Current keys, if signing is enabled.
+    fn keyring(&self) -> Option<Arc<Keyring>> {
+        self.keyring
+            .as_ref()
+            .map(|keyring| keyring.read().unwrap().clone())
+    }
+
+    /// Replace the keys. Has no effect if signing is disabled.
+    pub fn set_keyring(&self, keyring: Keyring) {
+        if let Some(current) = &self.keyring {
+            *current.write().unwrap() = Arc::new(keyring);
+        }
+    }
+
+    /// Reload the keys from `SHIZU_SIGNING_KEYS_FILE`.
+    ///
+    /// The current keys are kept if the file cannot be read or parsed.
+    pub fn reload(&self) -> Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+
+        let keyring = Keyring::load(path)?;
+        tracing::info!(
+            "Reloaded signing keys {:?}",
+            keyring.ids().collect::<Vec<_>>()
+        );
+        self.set_keyring(keyring);
+        Ok(())
+    }
+
+    /// Reload the keys on `SIGHUP` and whenever the key file changes,
+    /// checking every `interval`.
+    pub fn watch(&self, interval: Duration) {
+        let Some(path) = self.file.clone() else {
+            return;
+        };
+        let key = self.clone();
+
+        tokio::spawn(async move {
+            let modified = || std::fs::metadata(&*path).and_then(|m| m.modified()).ok();
+            let mut last_modified = modified();
+
+            #[cfg(unix)]
+            let mut hangup =
+                tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok();
+
+            loop {
+                #[cfg(unix)]
+                let signaled = async {
+                    match &mut hangup {
+                        Some(hangup) => hangup.recv().await,
+                        None => std::future::pending().await,
+                    }
+                };
+                #[cfg(not(unix))]
+                let signaled = std::future::pending::<Option<()>>();
+
+                tokio::select! {
+                    _ = signaled => {}
+                    _ = tokio::time::sleep(interval) => {
+                        let current = modified();
+                        if current == last_modified {
+                            continue;
+                        }
+                        last_modified = current;
+                    }
+                }
+
+                if let Err(e) = key.reload() {
+                    tracing::error!("Keeping current signing keys: {}", e);
+                }
+            }
+        });
     }
 
     /// Sign a URL and return the signature as a hex string.
     /// Returns an empty string if signing is disabled.
     pub fn sign(&self, url: &str) -> String {
-        let Some(key) = &self.key else {
-            return String::new();
-        };
-
-        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
-        mac.update(url.as_bytes());
-        let result = mac.finalize();
-        hex::encode(result.into_bytes())
+        self.keyring()
+            .and_then(|keyring| keyring.active().map(|entry| mac(&entry.key, url)))
+            .unwrap_or_default()
     }
 
     /// Verify a URL signature.
     /// Returns `true` if signing is disabled (bypass mode).
     pub fn verify(&self, url: &str, signature: Option<&str>) -> bool {
-        let Some(key) = &self.key else {
+        let Some(keyring) = self.keyring() else {
             // Signing disabled, bypass validation
             return true;
         };
@@ -107,17 +253,345 @@ impl SigningKey {
             return false;
         };
 
-        let Ok(sig_bytes) = hex::decode(sig) else {
-            return false;
+        // Signatures without key id may come from any of the keys
+        keyring.iter().any(|entry| verify_mac(&entry.key, url, sig))
+    }
+
+    /// Sign the query parameters of a URL to `endpoint` with a v2
+    /// signature. Returns an empty string if signing is disabled.
+    ///
+    /// `params` must already include `exp` and, if bound, `bind`.
+    pub fn sign_v2(
+        &self,
+        endpoint: &str,
+        params: &[(String, String)],
+        binding: Option<&Binding>,
+    ) -> String {
+        let Some(entry) = self.keyring().and_then(|k| k.active().cloned()) else {
+            return String::new();
         };
 
-        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
-        mac.update(url.as_bytes());
+        let signature = mac(&entry.key, &canonical_message(endpoint, params, binding));
+        match &entry.id {
+            Some(id) => format!("{}{}.{}", V2_PREFIX, id, signature),
+            None => format!("{}{}", V2_PREFIX, signature),
+        }
+    }
+
+    /// Verify the signature of a request to `endpoint`.
+    ///
+    /// Returns the binding of a v2 signature, so URLs derived from the
+    /// request can be bound the same way.
+    pub fn verify_query(
+        &self,
+        endpoint: &str,
+        params: &[(String, String)],
+        client: &ClientIdentity,
+        accept_legacy: bool,
+    ) -> Result<Option<Binding>> {
+        let Some(keyring) = self.keyring() else {
+            return Ok(None);
+        };
 
-        mac.verify_slice(&sig_bytes).is_ok()
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let signature = param("sig").ok_or(Error::InvalidSignature)?;
+
+        let Some(signature) = signature.strip_prefix(V2_PREFIX) else {
+            let url = param("url").ok_or(Error::InvalidSignature)?;
+            let message = signed_message(url, params);
+            return match accept_legacy && self.verify(&message, Some(signature)) {
+                true => Ok(None),
+                false => Err(Error::InvalidSignature),
+            };
+        };
+
+        let expires = param("exp")
+            .and_then(|exp| exp.parse::<u64>().ok())
+            .ok_or(Error::InvalidSignature)?;
+        let binding = match param("bind") {
+            Some(kind) => Some(
+                BindingKind::parse(kind)
+                    .and_then(|kind| kind.bind(client))
+                    .ok_or(Error::InvalidSignature)?,
+            ),
+            None => None,
+        };
+
+        // Signatures name the key they were made with; older ones without
+        // a key id may come from any key
+        let message = canonical_message(endpoint, params, binding.as_ref());
+        let valid = match signature.split_once('.') {
+            Some((id, signature)) => keyring
+                .get(id)
+                .is_some_and(|entry| verify_mac(&entry.key, &message, signature)),
+            None => keyring
+                .iter()
+                .any(|entry| verify_mac(&entry.key, &message, signature)),
+        };
+        if !valid {
+            return Err(Error::InvalidSignature);
+        }
+        if expires < unix_time() {
+            return Err(Error::SignatureExpired);
+        }
+        Ok(binding)
+    }
+}
+
+/// HMAC-SHA256 of a message as a hex string.
+fn mac(key: &[u8], message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Check a hex HMAC-SHA256 of a message in constant time.
+fn verify_mac(key: &[u8], message: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Prefix of v2 signatures.
+const V2_PREFIX: &str = "v2.";
+
+/// Query parameters not covered by v2 signatures: the signature itself and
+/// the values players fill in (DASH template identifiers and LL-HLS
+/// delivery directives).
+pub(crate) const UNSIGNED_PARAMS: &[&str] = &[
+    "sig",
+    "rid",
+    "num",
+    "bw",
+    "time",
+    "sub",
+    "_HLS_msn",
+    "_HLS_part",
+    // Ignored, but players may still send it
+    "_HLS_skip",
+];
+
+/// Message signed by a v2 signature.
+///
+/// `v2`, the endpoint and the binding on their own lines, followed by the
+/// signed parameters sorted and percent-encoded as `name=value` joined
+/// with `&`.
+pub fn canonical_message(
+    endpoint: &str,
+    params: &[(String, String)],
+    binding: Option<&Binding>,
+) -> String {
+    let mut pairs: Vec<_> = params
+        .iter()
+        .filter(|(name, _)| !UNSIGNED_PARAMS.contains(&name.as_str()))
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                urlencoding::encode(name),
+                urlencoding::encode(value)
+            )
+        })
+        .collect();
+    pairs.sort();
+
+    let binding = binding
+        .map(|b| format!("{}:{}", b.kind.as_str(), b.value))
+        .unwrap_or_default();
+    format!("v2\n{}\n{}\n{}", endpoint, binding, pairs.join("&"))
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Which signature version proxied URLs are signed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureVersion {
+    V1,
+    V2,
+}
+
+/// How signatures are created and checked.
+#[derive(Debug, Clone)]
+pub struct SignatureConfig {
+    /// Version of the signatures in rewritten manifests.
+    pub version: SignatureVersion,
+
+    /// Lifetime of v2 signatures in rewritten manifests.
+    pub ttl: Duration,
+
+    /// Binding for v2 signatures of requests that were not bound.
+    pub binding: Option<BindingKind>,
+
+    /// Whether v1 signatures are still accepted while manifests are signed
+    /// with v2. Off by default, since v1 signatures never expire.
+    pub accept_legacy: bool,
+}
+
+impl SignatureConfig {
+    /// Create config from environment variables, falling back to defaults.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+
+        Self {
+            version: match var("SHIZU_SIGNATURE_VERSION").as_deref() {
+                Some("1") => SignatureVersion::V1,
+                Some("2") => SignatureVersion::V2,
+                _ => defaults.version,
+            },
+            ttl: var("SHIZU_SIGNATURE_TTL_SECS")
+                .and_then(|s| s.parse().ok())
+                .map_or(defaults.ttl, Duration::from_secs),
+            binding: var("SHIZU_SIGNATURE_BINDING")
+                .as_deref()
+                .and_then(BindingKind::parse),
+            accept_legacy: var("SHIZU_ACCEPT_LEGACY_SIGNATURES")
+                .map_or(defaults.accept_legacy, |v| v == "true" || v == "1"),
+        }
+    }
+}
+
+impl Default for SignatureConfig {
+    fn default() -> Self {
+        Self {
+            version: SignatureVersion::V2,
+            ttl: Duration::from_secs(86400),
+            binding: None,
+            accept_legacy: false,
+        }
     }
 }
 
+/// What a v2 signature can be bound to besides its parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// The client IP address.
+    Ip,
+
+    /// The `X-Shizu-Session` request header.
+    Session,
+}
+
+impl BindingKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ip" => Some(Self::Ip),
+            "session" => Some(Self::Session),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ip => "ip",
+            Self::Session => "session",
+        }
+    }
+
+    /// Bind to the given client, if it has the bound value.
+    pub fn bind(self, client: &ClientIdentity) -> Option<Binding> {
+        let value = match self {
+            Self::Ip => client.ip.clone(),
+            Self::Session => client.session.clone(),
+        };
+        value.map(|value| Binding { kind: self, value })
+    }
+}
+
+/// A client value a v2 signature is bound to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub kind: BindingKind,
+    pub value: String,
+}
+
+/// Expiry and binding of the v2 signatures in a rewritten manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureScope {
+    /// Unix time the signatures expire at.
+    pub expires: u64,
+
+    pub binding: Option<Binding>,
+}
+
+/// Values identifying the client of a request, for signature bindings.
+#[derive(Debug, Clone, Default)]
+pub struct ClientIdentity {
+    /// Peer address, or the address forwarded by trusted proxies.
+    pub ip: Option<String>,
+
+    /// Value of the `X-Shizu-Session` header.
+    pub session: Option<String>,
+}
+
+impl FromRequestParts<AppState> for ClientIdentity {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> std::result::Result<Self, Self::Rejection> {
+        // A forwarded address from anyone else would let a leaked IP-bound
+        // URL be replayed by claiming the bound address
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip = state
+            .trusted_proxies
+            .client_ip(&parts.headers, peer)
+            .map(|ip| ip.to_string());
+        let session = parts
+            .headers
+            .get("x-shizu-session")
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(String::from);
+
+        Ok(Self { ip, session })
+    }
+}
+
+/// Parameters covered by v1 signatures besides `url`: the egress pool,
+/// the other upstreams fetched (key and init segment), the headers sent to
+/// them, and how the fetched bytes are selected and decrypted.
+///
+/// Mode flags and filters (`proxy`, `decrypt`, `aes`, `max_bw`, ...) are
+/// left out; they cannot make the server fetch anything else.
+const LEGACY_SIGNED_PARAMS: &[&str] = &[
+    "egress", "ku", "init", "h", "sh", "br", "init_br", "k", "iv", "m",
+];
+
+/// Message signed by a v1 signature for a target URL.
+///
+/// The parameters in [`LEGACY_SIGNED_PARAMS`] are part of the signature, so
+/// a signed URL cannot be moved to another egress, made to fetch from
+/// another host, sent with other credentials, or given another key, IV or
+/// byte range.
+pub fn signed_message<'a>(url: &'a str, params: &[(String, String)]) -> Cow<'a, str> {
+    let mut message = Cow::Borrowed(url);
+    for name in LEGACY_SIGNED_PARAMS {
+        if let Some((_, value)) = params.iter().find(|(key, _)| key == name) {
+            message.to_mut().push_str(&format!("\n{}={}", name, value));
+        }
+    }
+    message
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -191,4 +665,308 @@ mod tests {
         assert!(enabled.is_enabled());
         assert!(!disabled.is_enabled());
     }
+
+    #[test]
+    fn test_signature_binds_egress() {
+        let key = SigningKey::new(b"test-secret-key".to_vec());
+        let url = "https://example.com/manifest.m3u8";
+
+        let jp = params(&[("egress", "jp")]);
+        let signature = key.sign(&signed_message(url, &jp));
+        assert!(key.verify(&signed_message(url, &jp), Some(&signature)));
+        assert!(!key.verify(
+            &signed_message(url, &params(&[("egress", "us")])),
+            Some(&signature)
+        ));
+        assert!(!key.verify(&signed_message(url, &[]), Some(&signature)));
+    }
+
+    #[test]
+    fn test_legacy_covers_headers() {
+        let key = SigningKey::test_key();
+        let url = "https://example.com/key";
+        let signature = key.sign(url);
+        let client = ClientIdentity::default();
+
+        // Headers added to a URL signed without them
+        let params = params(&[("url", url), ("h", "e30"), ("sig", &signature)]);
+        assert!(matches!(
+            key.verify_query("key", &params, &client, true),
+            Err(Error::InvalidSignature)
+        ));
+
+        let h = [("h".to_string(), "e30".to_string())];
+        let mut params = params;
+        params[2].1 = key.sign(&signed_message(url, &h));
+        assert!(key.verify_query("key", &params, &client, true).is_ok());
+    }
+
+    #[test]
+    fn test_legacy_covers_key_uri() {
+        let key = SigningKey::test_key();
+        let url = "https://example.com/a.ts";
+        let ku = params(&[("ku", "https://example.com/key")]);
+        let mut params = params(&[
+            ("url", url),
+            ("ku", "https://example.com/key"),
+            ("sig", &key.sign(&signed_message(url, &ku))),
+        ]);
+        let client = ClientIdentity::default();
+
+        assert!(key.verify_query("segment", &params, &client, true).is_ok());
+
+        // The key URI is another fetch target, so it cannot be swapped
+        params[1].1 = "http://169.254.169.254/latest".to_string();
+        assert!(matches!(
+            key.verify_query("segment", &params, &client, true),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_legacy_covers_init_segment() {
+        let key = SigningKey::test_key();
+        let url = "https://example.com/a.m4s";
+        let signed = params(&[
+            ("init", "https://example.com/init.mp4"),
+            ("k", "00112233445566778899aabbccddeeff"),
+            ("iv", "00000000000000000000000000000001"),
+            ("m", "cenc"),
+        ]);
+        let mut params = params(&[
+            ("url", url),
+            ("init", "https://example.com/init.mp4"),
+            ("k", "00112233445566778899aabbccddeeff"),
+            ("iv", "00000000000000000000000000000001"),
+            ("m", "cenc"),
+            ("sig", &key.sign(&signed_message(url, &signed))),
+        ]);
+        let client = ClientIdentity::default();
+
+        assert!(key.verify_query("segment", &params, &client, true).is_ok());
+
+        // The init segment is another fetch target, so it cannot be swapped
+        let mut swapped = params.clone();
+        swapped[1].1 = "http://169.254.169.254/latest".to_string();
+        assert!(matches!(
+            key.verify_query("segment", &swapped, &client, true),
+            Err(Error::InvalidSignature)
+        ));
+
+        // Nor added to a URL signed without one
+        params.remove(1);
+        params.push(("init".to_string(), "http://10.0.0.1/".to_string()));
+        assert!(matches!(
+            key.verify_query("segment", &params, &client, true),
+            Err(Error::InvalidSignature)
+        ));
+
+        // Nor can the IV be changed
+        let mut params = swapped;
+        params[1].1 = "https://example.com/init.mp4".to_string();
+        params[3].1 = "00000000000000000000000000000002".to_string();
+        assert!(matches!(
+            key.verify_query("segment", &params, &client, true),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn signed_params(
+        key: &SigningKey,
+        pairs: &[(&str, &str)],
+        binding: Option<&Binding>,
+    ) -> Vec<(String, String)> {
+        let mut params = params(pairs);
+        let signature = key.sign_v2("segment", &params, binding);
+        params.push(("sig".to_string(), signature));
+        params
+    }
+
+    #[test]
+    fn test_v2_covers_all_params() {
+        let key = SigningKey::test_key();
+        let client = ClientIdentity::default();
+        let exp = (unix_time() + 60).to_string();
+        let pairs = [
+            ("url", "https://example.com/a.ts"),
+            ("m", "aes"),
+            ("exp", exp.as_str()),
+        ];
+
+        let mut params = signed_params(&key, &pairs, None);
+        assert_eq!(
+            key.verify_query("segment", &params, &client, false)
+                .unwrap(),
+            None
+        );
+        assert!(
+            key.verify_query("manifest", &params, &client, false)
+                .is_err()
+        );
+
+        // Values filled in by players are not signed
+        params.push(("num".to_string(), "42".to_string()));
+        assert!(key.verify_query("segment", &params, &client, false).is_ok());
+
+        params.push(("k".to_string(), "00".repeat(16)));
+        assert!(matches!(
+            key.verify_query("segment", &params, &client, false),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_v2_expires() {
+        let key = SigningKey::test_key();
+        let exp = (unix_time() - 1).to_string();
+        let params = signed_params(
+            &key,
+            &[("url", "https://example.com/a.ts"), ("exp", &exp)],
+            None,
+        );
+
+        assert!(matches!(
+            key.verify_query("segment", &params, &ClientIdentity::default(), false),
+            Err(Error::SignatureExpired)
+        ));
+    }
+
+    #[test]
+    fn test_v2_binds_client_ip() {
+        let key = SigningKey::test_key();
+        let exp = (unix_time() + 60).to_string();
+        let client = ClientIdentity {
+            ip: Some("203.0.113.7".to_string()),
+            session: None,
+        };
+        let binding = BindingKind::Ip.bind(&client);
+        let params = signed_params(
+            &key,
+            &[
+                ("url", "https://example.com/a.ts"),
+                ("exp", &exp),
+                ("bind", "ip"),
+            ],
+            binding.as_ref(),
+        );
+
+        assert_eq!(
+            key.verify_query("segment", &params, &client, false)
+                .unwrap(),
+            binding
+        );
+
+        let other = ClientIdentity {
+            ip: Some("198.51.100.1".to_string()),
+            session: None,
+        };
+        assert!(key.verify_query("segment", &params, &other, false).is_err());
+        assert!(
+            key.verify_query("segment", &params, &ClientIdentity::default(), false)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_ip_ignores_untrusted_forwarded_for() {
+        use crate::server::client_ip::TrustedProxies;
+        use axum::http::Request;
+
+        let parts = |peer: [u8; 4]| {
+            Request::builder()
+                .header("x-forwarded-for", "203.0.113.7")
+                .extension(ConnectInfo(SocketAddr::from((peer, 40000))))
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0
+        };
+        let mut state = AppState::new();
+
+        // Claiming the bound address does not help a replayed URL
+        let identity = ClientIdentity::from_request_parts(&mut parts([198, 51, 100, 9]), &state)
+            .await
+            .unwrap();
+        assert_eq!(identity.ip.as_deref(), Some("198.51.100.9"));
+
+        state.trusted_proxies = Arc::new(TrustedProxies::parse("10.0.0.1").unwrap());
+        let identity = ClientIdentity::from_request_parts(&mut parts([10, 0, 0, 1]), &state)
+            .await
+            .unwrap();
+        assert_eq!(identity.ip.as_deref(), Some("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_legacy_behind_switch() {
+        let key = SigningKey::test_key();
+        let url = "https://example.com/a.m3u8";
+        let params = params(&[("url", url), ("sig", &key.sign(url))]);
+        let client = ClientIdentity::default();
+
+        assert!(key.verify_query("manifest", &params, &client, true).is_ok());
+        assert!(matches!(
+            key.verify_query("manifest", &params, &client, false),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_signatures_valid() {
+        let old = Keyring::parse("k1:old-secret").unwrap();
+        let key = SigningKey::from_keyring(old);
+        let client = ClientIdentity::default();
+        let exp = (unix_time() + 60).to_string();
+        let pairs = [("url", "https://example.com/a.ts"), ("exp", exp.as_str())];
+
+        let before = signed_params(&key, &pairs, None);
+        assert!(before.last().unwrap().1.starts_with("v2.k1."));
+
+        // The new key signs, the old one still verifies
+        key.set_keyring(Keyring::parse("k2:new-secret\nk1:old-secret").unwrap());
+        let after = signed_params(&key, &pairs, None);
+        assert!(after.last().unwrap().1.starts_with("v2.k2."));
+        assert!(key.verify_query("segment", &before, &client, false).is_ok());
+        assert!(key.verify_query("segment", &after, &client, false).is_ok());
+
+        // Retired keys no longer verify
+        key.set_keyring(Keyring::parse("k2:new-secret").unwrap());
+        assert!(
+            key.verify_query("segment", &before, &client, false)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_reloads_from_file() {
+        let path = std::env::temp_dir().join(format!("shizu-keys-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "k1:old-secret\n").unwrap();
+        let key = SigningKey {
+            file: Some(Arc::new(path.clone())),
+            ..SigningKey::from_keyring(Keyring::load(&path).unwrap())
+        };
+        let clone = key.clone();
+
+        std::fs::write(&path, "k2:new-secret\nk1:old-secret\n").unwrap();
+        key.reload().unwrap();
+        let params = signed_params(&clone, &[("exp", "99999999999")], None);
+        assert!(params.last().unwrap().1.starts_with("v2.k2."));
+
+        // A broken file keeps the current keys
+        std::fs::write(&path, "broken").unwrap();
+        assert!(key.reload().is_err());
+        assert!(
+            clone
+                .verify_query("segment", &params, &ClientIdentity::default(), false)
+                .is_ok()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -1,28 +1,140 @@
-use crate::{cache::InitSegmentCache, proxy::ProxyClient};
-use std::sync::Arc;
+use crate::{
+    Result,
+    cache::{InitSegmentCache, InitSegmentCacheConfig, SegmentCache, SegmentCacheConfig},
+    decrypt::KeyFetcher,
+    logging::{NoOpLogger, RequestLogSink},
+    proxy::{ClientConfig, EgressPools, ProxyClient},
+};
+use std::{sync::Arc, time::Duration};
 
-use super::signature::SigningKey;
+use super::{
+    client_ip::TrustedProxies,
+    signature::{
+        Binding, ClientIdentity, SignatureConfig, SignatureScope, SignatureVersion, SigningKey,
+        unix_time,
+    },
+    token::TokenCipher,
+};
 
 /// Shared application state.
 #[derive(Clone)]
 pub struct AppState {
     pub client: ProxyClient,
+
+    /// Named outbound proxy pools selectable with `egress`.
+    pub egress: Arc<EgressPools>,
+
     pub init_cache: Arc<InitSegmentCache>,
+    pub segment_cache: Option<Arc<SegmentCache>>,
+    pub key_fetcher: Arc<KeyFetcher>,
     pub signing_key: SigningKey,
+    pub signatures: SignatureConfig,
+
+    /// Cipher for opaque `/s/{token}` URLs; plaintext query URLs without.
+    pub tokens: Option<TokenCipher>,
+
+    pub logger: Arc<dyn RequestLogSink>,
+
+    /// Reverse proxies allowed to forward the client address.
+    pub trusted_proxies: Arc<TrustedProxies>,
+
+    /// Time a /segment request may spend on upstream fetches.
+    pub segment_deadline: Duration,
+
+    /// Bearer token for /metrics; the endpoint is disabled without one.
+    pub metrics_token: Option<Arc<str>>,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let client_config = ClientConfig::from_env();
+
         Self {
-            client: ProxyClient::new(),
-            init_cache: Arc::new(InitSegmentCache::new(100)),
+            client: ProxyClient::from_config(client_config.clone()),
+            egress: Arc::new(EgressPools::from_env(&client_config)),
+            init_cache: Arc::new(InitSegmentCache::new(InitSegmentCacheConfig::from_env())),
+            segment_cache: SegmentCacheConfig::from_env()
+                .map(|config| Arc::new(SegmentCache::new(config))),
+            key_fetcher: Arc::new(KeyFetcher::new(100)),
             signing_key: SigningKey::from_env(),
+            signatures: SignatureConfig::from_env(),
+            tokens: TokenCipher::from_env(),
+            logger: Arc::new(NoOpLogger),
+            trusted_proxies: Arc::new(TrustedProxies::from_env()),
+            segment_deadline: Duration::from_secs(
+                std::env::var("SHIZU_SEGMENT_DEADLINE_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30),
+            ),
+            metrics_token: std::env::var("SHIZU_METRICS_TOKEN")
+                .ok()
+                .filter(|s| !s.is_empty())
+                .map(Arc::from),
         }
     }
 
-    /// Verify that a URL has a valid signature.
-    pub fn verify_signature(&self, url: &str, signature: Option<&str>) -> bool {
-        self.signing_key.verify(url, signature)
+    /// Use the given sink for request logs.
+    pub fn with_logger(mut self, logger: Arc<dyn RequestLogSink>) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Cache decrypted segments.
+    pub fn with_segment_cache(mut self, cache: SegmentCache) -> Self {
+        self.segment_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Verify the signature of a request to `endpoint` with the given raw
+    /// query string.
+    ///
+    /// Returns the binding of a v2 signature.
+    pub fn verify_request(
+        &self,
+        endpoint: &str,
+        query: Option<&str>,
+        client: &ClientIdentity,
+    ) -> Result<Option<Binding>> {
+        let params: Vec<(String, String)> =
+            url::form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect();
+
+        // Manifests signed with v1 hand out v1 URLs, which must keep working
+        let accept_legacy =
+            self.signatures.accept_legacy || self.signatures.version == SignatureVersion::V1;
+
+        self.signing_key
+            .verify_query(endpoint, &params, client, accept_legacy)
+            .inspect_err(|e| tracing::warn!("Rejected /{} request: {}", endpoint, e))
+    }
+
+    /// Expiry and binding for the v2 signatures of a manifest rewritten for
+    /// `client`, or `None` if manifests are signed with v1 signatures.
+    ///
+    /// URLs stay bound like the manifest request was.
+    pub fn signature_scope(
+        &self,
+        binding: Option<Binding>,
+        client: &ClientIdentity,
+    ) -> Option<SignatureScope> {
+        if !self.signing_key.is_enabled() || self.signatures.version != SignatureVersion::V2 {
+            return None;
+        }
+
+        Some(SignatureScope {
+            expires: unix_time() + self.signatures.ttl.as_secs(),
+            binding: binding.or_else(|| self.signatures.binding?.bind(client)),
+        })
+    }
+
+    /// Client to fetch `url` with: one of the `egress` pool, or the default.
+    pub fn client_for(&self, egress: Option<&str>, url: &str) -> Result<ProxyClient> {
+        match egress {
+            Some(name) => self.egress.client(name, url).cloned(),
+            None => Ok(self.client.clone()),
+        }
     }
 
     /// Sign a URL and return the signature.
@@ -0,0 +1,146 @@
+//! Opaque URL tokens.
+//!
+//! Rewritten URLs carry the origin URL, request headers and content keys in
+//! their query. With a token key configured, each URL is instead encrypted
+//! with AES-256-GCM into a base64url token served at `/s/{token}.{ext}`,
+//! so none of it shows up in player logs, referrers or CDN logs.
+
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, Payload},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+
+use super::keyring::decode_key;
+use crate::{Error, Result};
+
+/// Length of the random nonce prepended to each token.
+const NONCE_LEN: usize = 12;
+
+/// Associated data of tokens, so ciphertexts made with the same key for
+/// another purpose are not accepted.
+const TOKEN_AAD: &[u8] = b"shizu-token-v1";
+
+/// Encrypts rewritten URLs into opaque tokens and back.
+#[derive(Clone)]
+pub struct TokenCipher {
+    cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for TokenCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenCipher")
+            .field("key", &"[REDACTED]")
+            .finish()
+    }
+}
+
+impl TokenCipher {
+    /// Create a cipher from a secret of any length, hashed into the
+    /// AES-256 key.
+    pub fn new(secret: &[u8]) -> Self {
+        let key = Sha256::digest(secret);
+        Self {
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    /// Create a cipher from `SHIZU_TOKEN_KEY` (hex or raw string), or `None`
+    /// to keep plaintext URLs.
+    pub fn from_env() -> Option<Self> {
+        let secret = std::env::var("SHIZU_TOKEN_KEY").ok()?;
+        if secret.is_empty() {
+            return None;
+        }
+        tracing::info!("Rewritten URLs are encrypted into opaque tokens");
+        Some(Self::new(&decode_key(&secret)))
+    }
+
+    /// Encrypt a relative URL (path and query) into a token.
+    pub fn seal(&self, url: &str) -> String {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: url.as_bytes(),
+                    aad: TOKEN_AAD,
+                },
+            )
+            .expect("AES-GCM encryption of a URL cannot fail");
+
+        let mut token = nonce.to_vec();
+        token.extend(ciphertext);
+        URL_SAFE_NO_PAD.encode(token)
+    }
+
+    /// Decrypt and authenticate a token into the URL it was made from.
+    pub fn open(&self, token: &str) -> Result<String> {
+        let token = URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| Error::InvalidToken)?;
+        if token.len() < NONCE_LEN {
+            return Err(Error::InvalidToken);
+        }
+
+        let (nonce, ciphertext) = token.split_at(NONCE_LEN);
+        let url = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: TOKEN_AAD,
+                },
+            )
+            .map_err(|_| Error::InvalidToken)?;
+
+        String::from_utf8(url).map_err(|_| Error::InvalidToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let cipher = TokenCipher::new(b"token-secret");
+        let url = "/segment.ts?url=https%3A%2F%2Fexample.com%2Fa.ts&k=00112233&m=ssa";
+
+        let token = cipher.seal(url);
+        assert!(!token.contains("example.com"));
+        assert!(
+            token
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        );
+        assert_eq!(cipher.open(&token).unwrap(), url);
+
+        // Fresh nonce for every token
+        assert_ne!(cipher.seal(url), token);
+    }
+
+    #[test]
+    fn test_rejects_tampered_tokens() {
+        let cipher = TokenCipher::new(b"token-secret");
+        let token = cipher.seal("/key?url=https%3A%2F%2Fexample.com%2Fkey");
+
+        let mut bytes = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = URL_SAFE_NO_PAD.encode(bytes);
+
+        assert!(matches!(cipher.open(&tampered), Err(Error::InvalidToken)));
+        assert!(matches!(
+            TokenCipher::new(b"other-secret").open(&token),
+            Err(Error::InvalidToken)
+        ));
+        assert!(matches!(cipher.open("short"), Err(Error::InvalidToken)));
+        assert!(matches!(
+            cipher.open("not base64!"),
+            Err(Error::InvalidToken)
+        ));
+    }
+}
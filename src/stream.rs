@@ -1,10 +1,16 @@
 pub mod classifier;
 pub mod context;
+pub mod filter;
+pub mod kind;
+pub mod mpd;
 pub mod processor;
 pub mod rules;
 pub mod state;
 
 pub use classifier::{LineClassifier, LineType};
 pub use context::TransformContext;
+pub use filter::{RenditionFilter, VariantFilter};
+pub use kind::ManifestKind;
+pub use mpd::MpdProcessor;
 pub use processor::StreamProcessor;
 pub use state::ProcessorState;
@@ -6,17 +6,24 @@ pub enum LineType {
     ExtXStreamInf,
     ExtXMedia,
     ExtXKey,
+    ExtXSessionKey,
+    ExtXSessionData,
     ExtXMap,
     ExtXMediaSequence,
     ExtInf,
     ExtXByteRange,
     ExtXIFrameStreamInf,
+    ExtXIFramesOnly,
     ExtXDiscontinuity,
     ExtXDiscontinuitySequence,
     ExtXEndList,
     ExtXTargetDuration,
     ExtXPlaylistType,
     ExtXVersion,
+    ExtXPart,
+    ExtXPreloadHint,
+    ExtXRenditionReport,
+    ExtXServerControl,
     UnknownExtTag,
     Comment,
     Uri,
@@ -79,6 +86,10 @@ impl LineClassifier {
             LineType::ExtXMedia
         } else if line.starts_with("#EXT-X-KEY:") {
             LineType::ExtXKey
+        } else if line.starts_with("#EXT-X-SESSION-KEY:") {
+            LineType::ExtXSessionKey
+        } else if line.starts_with("#EXT-X-SESSION-DATA:") {
+            LineType::ExtXSessionData
         } else if line.starts_with("#EXT-X-MAP:") {
             LineType::ExtXMap
         } else if line.starts_with("#EXT-X-MEDIA-SEQUENCE:") {
@@ -89,6 +100,8 @@ impl LineClassifier {
             LineType::ExtXByteRange
         } else if line.starts_with("#EXT-X-I-FRAME-STREAM-INF:") {
             LineType::ExtXIFrameStreamInf
+        } else if line.starts_with("#EXT-X-I-FRAMES-ONLY") {
+            LineType::ExtXIFramesOnly
         } else if line.starts_with("#EXT-X-DISCONTINUITY-SEQUENCE:") {
             LineType::ExtXDiscontinuitySequence
         } else if line.starts_with("#EXT-X-DISCONTINUITY") {
@@ -101,6 +114,14 @@ impl LineClassifier {
             LineType::ExtXPlaylistType
         } else if line.starts_with("#EXT-X-VERSION:") {
             LineType::ExtXVersion
+        } else if line.starts_with("#EXT-X-PART:") {
+            LineType::ExtXPart
+        } else if line.starts_with("#EXT-X-PRELOAD-HINT:") {
+            LineType::ExtXPreloadHint
+        } else if line.starts_with("#EXT-X-RENDITION-REPORT:") {
+            LineType::ExtXRenditionReport
+        } else if line.starts_with("#EXT-X-SERVER-CONTROL:") {
+            LineType::ExtXServerControl
         } else if line.starts_with("#EXT") {
             LineType::UnknownExtTag
         } else {
@@ -151,6 +172,56 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_classify_session_tags() {
+        assert_eq!(
+            LineClassifier::classify("#EXT-X-SESSION-KEY:METHOD=SAMPLE-AES,URI=\"skd://a\""),
+            LineType::ExtXSessionKey
+        );
+        assert_eq!(
+            LineClassifier::classify(
+                "#EXT-X-SESSION-DATA:DATA-ID=\"com.example.title\",VALUE=\"x\""
+            ),
+            LineType::ExtXSessionData
+        );
+    }
+
+    #[test]
+    fn test_classify_iframe_tags() {
+        assert_eq!(
+            LineClassifier::classify(r#"#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=1,URI="i.m3u8""#),
+            LineType::ExtXIFrameStreamInf
+        );
+        assert_eq!(
+            LineClassifier::classify("#EXT-X-I-FRAMES-ONLY"),
+            LineType::ExtXIFramesOnly
+        );
+    }
+
+    #[test]
+    fn test_classify_low_latency_tags() {
+        assert_eq!(
+            LineClassifier::classify(r#"#EXT-X-PART:DURATION=0.5,URI="part1.mp4""#),
+            LineType::ExtXPart
+        );
+        assert_eq!(
+            LineClassifier::classify("#EXT-X-PART-INF:PART-TARGET=0.5"),
+            LineType::UnknownExtTag
+        );
+        assert_eq!(
+            LineClassifier::classify(r#"#EXT-X-PRELOAD-HINT:TYPE=PART,URI="part2.mp4""#),
+            LineType::ExtXPreloadHint
+        );
+        assert_eq!(
+            LineClassifier::classify(r#"#EXT-X-RENDITION-REPORT:URI="../1M/live.m3u8""#),
+            LineType::ExtXRenditionReport
+        );
+        assert_eq!(
+            LineClassifier::classify("#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES"),
+            LineType::ExtXServerControl
+        );
+    }
+
     #[test]
     fn test_classify_empty() {
         assert_eq!(LineClassifier::classify(""), LineType::Empty);
@@ -1,4 +1,15 @@
-use crate::{decrypt::DecryptionKey, server::SigningKey, Result};
+use super::filter::{RenditionFilter, VariantFilter};
+use crate::{
+    decrypt::DecryptionKey,
+    hls::{KeyInfo, KeyMethod},
+    proxy::mirror::{authority, with_authority},
+    server::{
+        signature::{signed_message, SignatureScope},
+        token::TokenCipher,
+        SigningKey,
+    },
+    Result,
+};
 use std::collections::HashMap;
 use url::Url;
 
@@ -26,6 +37,37 @@ pub struct TransformContext {
     /// Whether to decrypt DRM segments.
     pub decrypt_enabled: bool,
 
+    /// Whether to decrypt AES-128 segments on the server instead of
+    /// leaving them to the client.
+    pub aes128_decrypt: bool,
+
+    /// Whether to route every segment through the proxy, even when it is
+    /// not decrypted (to add headers and CORS).
+    pub proxy_all: bool,
+
+    /// Variant filtering and ordering for master playlists.
+    pub variant_filter: VariantFilter,
+
+    /// Audio/subtitle rendition filtering for master playlists.
+    pub rendition_filter: RenditionFilter,
+
+    /// Extension for /segment URLs whose target has none.
+    pub default_extension: &'static str,
+
+    /// Requested host and the mirror host that served the manifest.
+    /// URLs on the requested host are moved to the mirror, so the session
+    /// stays on one CDN.
+    pub mirror: Option<(String, String)>,
+
+    /// Outbound proxy pool that rewritten URLs are fetched through.
+    pub egress: Option<String>,
+
+    /// Expiry and binding of v2 signatures; v1 signatures without.
+    pub signature_scope: Option<SignatureScope>,
+
+    /// Cipher sealing rewritten URLs into opaque `/s/{token}` URLs.
+    pub token_cipher: Option<TokenCipher>,
+
     /// Signing key for generating signed URLs.
     signing_key: SigningKey,
 }
@@ -37,8 +79,6 @@ impl TransformContext {
         segment_headers: Option<String>,
         manifest_headers_map: HashMap<String, String>,
         segment_headers_map: HashMap<String, String>,
-        decryption_key: Option<DecryptionKey>,
-        decrypt_enabled: bool,
         signing_key: SigningKey,
     ) -> Self {
         Self {
@@ -47,15 +87,106 @@ impl TransformContext {
             segment_headers,
             manifest_headers_map,
             segment_headers_map,
-            decryption_key,
-            decrypt_enabled,
+            decryption_key: None,
+            decrypt_enabled: false,
+            aes128_decrypt: false,
+            proxy_all: false,
+            variant_filter: VariantFilter::default(),
+            rendition_filter: RenditionFilter::default(),
+            default_extension: "ts",
+            mirror: None,
+            egress: None,
+            signature_scope: None,
+            token_cipher: None,
             signing_key,
         }
     }
 
-    /// Resolve a relative URL against the original manifest URL.
+    /// Set the decryption key(s) given with the request.
+    pub fn with_decryption_key(mut self, key: Option<DecryptionKey>) -> Self {
+        self.decryption_key = key;
+        self
+    }
+
+    /// Enable decryption of DRM segments.
+    pub fn with_decrypt(mut self, enabled: bool) -> Self {
+        self.decrypt_enabled = enabled;
+        self
+    }
+
+    /// Enable server-side decryption of AES-128 segments.
+    pub fn with_aes128_decrypt(mut self, enabled: bool) -> Self {
+        self.aes128_decrypt = enabled;
+        self
+    }
+
+    /// Route all segments through the proxy, not only decrypted ones.
+    pub fn with_proxy_all(mut self, enabled: bool) -> Self {
+        self.proxy_all = enabled;
+        self
+    }
+
+    /// Filter and reorder the variants of master playlists.
+    pub fn with_variant_filter(mut self, filter: VariantFilter) -> Self {
+        self.variant_filter = filter;
+        self
+    }
+
+    /// Filter the `#EXT-X-MEDIA` renditions of master playlists.
+    pub fn with_rendition_filter(mut self, filter: RenditionFilter) -> Self {
+        self.rendition_filter = filter;
+        self
+    }
+
+    /// Set the extension used for segments without one (e.g. `mp4` for DASH).
+    pub fn with_default_extension(mut self, ext: &'static str) -> Self {
+        self.default_extension = ext;
+        self
+    }
+
+    /// Fetch the rewritten URLs through the named outbound proxy pool.
+    pub fn with_egress(mut self, egress: Option<String>) -> Self {
+        self.egress = egress;
+        self
+    }
+
+    /// Sign rewritten URLs with v2 signatures of the given scope.
+    pub fn with_signature_scope(mut self, scope: Option<SignatureScope>) -> Self {
+        self.signature_scope = scope;
+        self
+    }
+
+    /// Encrypt rewritten URLs into opaque tokens instead of query strings.
+    pub fn with_token_cipher(mut self, cipher: Option<TokenCipher>) -> Self {
+        self.token_cipher = cipher;
+        self
+    }
+
+    /// Stick to the mirror that served the manifest instead of the host
+    /// of the original URL.
+    pub fn with_served_url(mut self, served: &Url) -> Self {
+        self.mirror = authority(&self.original_url)
+            .zip(authority(served))
+            .filter(|(from, to)| from != to);
+        self
+    }
+
+    /// Resolve a relative URL against the original manifest URL, moved to
+    /// the mirror that served the manifest.
     pub fn resolve_url(&self, relative: &str) -> Result<Url> {
-        self.original_url.join(relative).map_err(Into::into)
+        let url = self.original_url.join(relative)?;
+        Ok(self.pin_to_mirror(url))
+    }
+
+    /// Move a URL on the requested host to the mirror that served the
+    /// manifest.
+    pub fn pin_to_mirror(&self, url: Url) -> Url {
+        match &self.mirror {
+            Some((from, to)) if authority(&url).as_ref() == Some(from) => {
+                with_authority(&url, to).unwrap_or(url)
+            }
+            _ => url,
+        }
     }
 
     /// Build a relative URL for the /manifest endpoint.
@@ -75,33 +206,69 @@ impl TransformContext {
         if self.decrypt_enabled {
             params.push("decrypt=true".to_string());
         }
+        if self.aes128_decrypt {
+            params.push("aes=true".to_string());
+        }
+        if self.proxy_all {
+            params.push("proxy=true".to_string());
+        }
+        params.extend(self.variant_filter.query_params());
+        params.extend(self.rendition_filter.query_params());
 
         // Sign the target URL to prevent SSRF attacks
-        let signature = self.signing_key.sign(target_str);
-        params.push(format!("sig={}", signature));
+        self.sign("manifest", target_str, &mut params);
 
-        format!("/manifest?{}", params.join("&"))
+        let ext = target_extension(target, "m3u8");
+        self.finish_url("/manifest", &params, ext)
+    }
+
+    /// Build a relative URL for the /key endpoint.
+    pub fn build_key_url(&self, target: &Url) -> String {
+        let target_str = target.as_str();
+        let mut params = vec![format!("url={}", urlencoding::encode(target_str))];
+
+        if let Some(sh) = &self.segment_headers {
+            params.push(format!("h={}", urlencoding::encode(sh)));
+        }
+
+        // Sign the target URL to prevent SSRF attacks
+        self.sign("key", target_str, &mut params);
+
+        self.finish_url("/key", &params, "key")
+    }
+
+    /// Build a relative URL for the /proxy endpoint.
+    ///
+    /// `headers` is the encoded header set to fetch the resource with.
+    pub fn build_proxy_url(&self, target: &Url, headers: Option<&str>) -> String {
+        let target_str = target.as_str();
+        let mut params = vec![format!("url={}", urlencoding::encode(target_str))];
+
+        if let Some(h) = headers {
+            params.push(format!("h={}", urlencoding::encode(h)));
+        }
+
+        // Sign the target URL to prevent SSRF attacks
+        self.sign("proxy", target_str, &mut params);
+
+        let ext = target_extension(target, "bin");
+        self.finish_url("/proxy", &params, ext)
     }
 
     /// Build a relative URL for the /segment endpoint.
+    ///
+    /// `key_uri` is passed along as `ku` when no key was provided, so the
+    /// segment endpoint can fetch the key itself.
     pub fn build_segment_url(
         &self,
         target: &Url,
         method: &str,
         iv: &[u8; 16],
         byterange: Option<&crate::hls::ByteRange>,
-        init_url: Option<&Url>,
-        init_byterange: Option<&crate::hls::ByteRange>,
+        init: Option<(&Url, Option<&crate::hls::ByteRange>)>,
+        key_uri: Option<&Url>,
     ) -> String {
-        // Extract extension from target URL path for player compatibility (e.g., ffplay requires .ts)
-        let ext = target
-            .path()
-            .rsplit_once('/')
-            .map(|(_, filename)| filename)
-            .unwrap_or(target.path())
-            .rsplit_once('.')
-            .map(|(_, ext)| ext)
-            .unwrap_or("ts");
+        let ext = self.segment_extension(target);
 
         let target_str = target.as_str();
         let mut params = vec![format!("url={}", urlencoding::encode(target_str))];
@@ -111,6 +278,8 @@ impl TransformContext {
         }
         if let Some(k) = &self.decryption_key {
             params.push(format!("k={}", urlencoding::encode(&k.to_string())));
+        } else if let Some(ku) = key_uri {
+            params.push(format!("ku={}", urlencoding::encode(ku.as_str())));
         }
 
         params.push(format!("iv={}", hex::encode(iv)));
@@ -119,10 +288,10 @@ impl TransformContext {
         if let Some(br) = byterange {
             params.push(format!("br={}", urlencoding::encode(&br.to_query_param())));
         }
-        if let Some(init) = init_url {
-            params.push(format!("init={}", urlencoding::encode(init.as_str())));
+        if let Some((init_url, _)) = init {
+            params.push(format!("init={}", urlencoding::encode(init_url.as_str())));
         }
-        if let Some(init_br) = init_byterange {
+        if let Some(init_br) = init.and_then(|(_, br)| br) {
             params.push(format!(
                 "init_br={}",
                 urlencoding::encode(&init_br.to_query_param())
@@ -130,14 +299,135 @@ impl TransformContext {
         }
 
         // Sign the target URL to prevent SSRF attacks
-        let signature = self.signing_key.sign(target_str);
+        self.sign("segment", target_str, &mut params);
+
+        self.finish_url(&format!("/segment.{}", ext), &params, ext)
+    }
+
+    /// Build a relative URL for the /segment endpoint that passes the
+    /// segment through without decryption.
+    pub fn build_passthrough_url(
+        &self,
+        target: &Url,
+        byterange: Option<&crate::hls::ByteRange>,
+    ) -> String {
+        let ext = self.segment_extension(target);
+
+        let target_str = target.as_str();
+        let mut params = vec![format!("url={}", urlencoding::encode(target_str))];
+
+        if let Some(sh) = &self.segment_headers {
+            params.push(format!("h={}", urlencoding::encode(sh)));
+        }
+        if let Some(br) = byterange {
+            params.push(format!("br={}", urlencoding::encode(&br.to_query_param())));
+        }
+
+        // Sign the target URL to prevent SSRF attacks
+        self.sign("segment", target_str, &mut params);
+
+        self.finish_url(&format!("/segment.{}", ext), &params, ext)
+    }
+
+    /// Add the egress and signature parameters to the encoded `params` of
+    /// a URL to `endpoint`.
+    fn sign(&self, endpoint: &str, target: &str, params: &mut Vec<String>) {
+        if let Some(egress) = &self.egress {
+            params.push(format!("egress={}", urlencoding::encode(egress)));
+        }
+
+        let signature = match &self.signature_scope {
+            Some(scope) => {
+                params.push(format!("exp={}", scope.expires));
+                if let Some(binding) = &scope.binding {
+                    params.push(format!("bind={}", binding.kind.as_str()));
+                }
+                self.signing_key
+                    .sign_v2(endpoint, &decode_params(params), scope.binding.as_ref())
+            }
+            None => self
+                .signing_key
+                .sign(&signed_message(target, &decode_params(params))),
+        };
         params.push(format!("sig={}", signature));
+    }
+
+    /// Join a path and its encoded `params`, sealed into an opaque
+    /// `/s/{token}.{ext}` URL when a token cipher is set.
+    fn finish_url(&self, path: &str, params: &[String], ext: &str) -> String {
+        let url = format!("{}?{}", path, params.join("&"));
+        match &self.token_cipher {
+            Some(cipher) => format!("/s/{}.{}", cipher.seal(&url), ext),
+            None => url,
+        }
+    }
 
-        format!("/segment.{}?{}", ext, params.join("&"))
+    /// Extract extension from target URL path for player compatibility (e.g., ffplay requires .ts)
+    fn segment_extension<'a>(&'a self, target: &'a Url) -> &'a str {
+        target_extension(target, self.default_extension)
     }
 
     /// Check if we should intercept and decrypt segments with this key method.
     pub fn should_intercept(&self, requires_server_decrypt: bool) -> bool {
         self.decrypt_enabled && requires_server_decrypt && self.decryption_key.is_some()
     }
+
+    /// Check if segments encrypted with this key should go through /segment.
+    ///
+    /// DRM methods are intercepted when `decrypt` is enabled, AES-128 only
+    /// when server-side AES-128 decryption was opted into. Without a provided
+    /// key, methods whose key can be fetched from the URI are still intercepted.
+    pub fn should_intercept_key(&self, key: &KeyInfo) -> bool {
+        let enabled = match key.method {
+            KeyMethod::Aes128 => self.aes128_decrypt,
+            _ => self.decrypt_enabled && key.requires_server_decrypt(),
+        };
+        if !enabled {
+            return false;
+        }
+        if self.decryption_key.is_some() || self.key_uri(key).is_some() {
+            return true;
+        }
+
+        if let Some(uri) = key.uri.as_deref().filter(|uri| uri.starts_with("skd://")) {
+            tracing::debug!(
+                "Leaving segments of {} to the player: skd:// keys must be provided with k",
+                uri
+            );
+        }
+        false
+    }
+
+    /// Resolved key URI to fetch the key from, if the key can be fetched.
+    ///
+    /// FairPlay `skd://` URIs name a key for the license server, not a
+    /// location, so their keys must be provided.
+    pub fn key_uri(&self, key: &KeyInfo) -> Option<Url> {
+        if !key.method.supports_key_fetch() {
+            return None;
+        }
+        key.uri
+            .as_deref()
+            .and_then(|uri| self.resolve_url(uri).ok())
+            .filter(|url| matches!(url.scheme(), "http" | "https" | "data"))
+    }
+}
+
+/// Decode the encoded `name=value` params of a URL.
+fn decode_params(params: &[String]) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(params.join("&").as_bytes())
+        .into_owned()
+        .collect()
+}
+
+/// Extension of the last path segment of `target`, or `default`.
+fn target_extension<'a>(target: &'a Url, default: &'a str) -> &'a str {
+    target
+        .path()
+        .rsplit_once('/')
+        .map(|(_, filename)| filename)
+        .unwrap_or(target.path())
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or(default)
 }
@@ -0,0 +1,749 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use super::classifier::{LineClassifier, LineType};
+use crate::{
+    Error, Result,
+    hls::{StreamInfo, attributes},
+};
+
+/// Order of variants by bandwidth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err(Error::InvalidParameter(format!("sort={}", s))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Asc => "asc",
+            Self::Desc => "desc",
+        }
+    }
+}
+
+/// Maximum resolution: `WIDTHxHEIGHT`, or a height alone (`720` / `720p`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxResolution {
+    pub width: Option<u32>,
+    pub height: u32,
+}
+
+impl MaxResolution {
+    pub fn parse(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidParameter(format!("max_res={}", s));
+        let value = s.trim();
+
+        match value.split_once(['x', 'X']) {
+            Some((w, h)) => Ok(Self {
+                width: Some(w.parse().map_err(|_| invalid())?),
+                height: h.parse().map_err(|_| invalid())?,
+            }),
+            None => Ok(Self {
+                width: None,
+                height: value
+                    .trim_end_matches(['p', 'P'])
+                    .parse()
+                    .map_err(|_| invalid())?,
+            }),
+        }
+    }
+
+    fn allows(&self, (width, height): (u32, u32)) -> bool {
+        height <= self.height && self.width.is_none_or(|max| width <= max)
+    }
+
+    fn to_query_param(self) -> String {
+        match self.width {
+            Some(width) => format!("{}x{}", width, self.height),
+            None => self.height.to_string(),
+        }
+    }
+}
+
+/// Filter and order for the variants of a master playlist.
+///
+/// Variants lacking the attribute a limit applies to are kept. If every
+/// variant would be dropped, the lowest-bandwidth one is kept so the
+/// playlist stays playable.
+#[derive(Debug, Clone, Default)]
+pub struct VariantFilter {
+    /// Maximum `BANDWIDTH` in bits per second.
+    pub max_bandwidth: Option<u64>,
+
+    /// Maximum `RESOLUTION`.
+    pub max_resolution: Option<MaxResolution>,
+
+    /// Codec prefixes (e.g. `avc1`); a variant is kept if any of its
+    /// `CODECS` starts with one of them.
+    pub codecs: Vec<String>,
+
+    /// Reorder variants by bandwidth.
+    pub sort: Option<SortOrder>,
+}
+
+impl VariantFilter {
+    /// Build a filter from the /manifest query parameters.
+    pub fn from_params(
+        max_bw: Option<u64>,
+        max_res: Option<&str>,
+        codecs: Option<&str>,
+        sort: Option<&str>,
+    ) -> Result<Self> {
+        Ok(Self {
+            max_bandwidth: max_bw,
+            max_resolution: max_res.map(MaxResolution::parse).transpose()?,
+            codecs: codecs
+                .map(|c| {
+                    c.split(',')
+                        .map(|codec| codec.trim().to_lowercase())
+                        .filter(|codec| !codec.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            sort: sort.map(SortOrder::parse).transpose()?,
+        })
+    }
+
+    /// Check if the filter changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.max_bandwidth.is_none()
+            && self.max_resolution.is_none()
+            && self.codecs.is_empty()
+            && self.sort.is_none()
+    }
+
+    /// Query parameters that carry this filter to child manifest URLs.
+    pub fn query_params(&self) -> Vec<String> {
+        let mut params = Vec::new();
+
+        if let Some(max_bw) = self.max_bandwidth {
+            params.push(format!("max_bw={}", max_bw));
+        }
+        if let Some(max_res) = self.max_resolution {
+            params.push(format!("max_res={}", max_res.to_query_param()));
+        }
+        if !self.codecs.is_empty() {
+            params.push(format!(
+                "codecs={}",
+                urlencoding::encode(&self.codecs.join(","))
+            ));
+        }
+        if let Some(sort) = self.sort {
+            params.push(format!("sort={}", sort.as_str()));
+        }
+
+        params
+    }
+
+    /// Check if a variant passes the limits.
+    pub fn accepts(&self, info: &StreamInfo) -> bool {
+        if let (Some(max), Some(bandwidth)) = (self.max_bandwidth, info.bandwidth)
+            && bandwidth > max
+        {
+            return false;
+        }
+        if let (Some(max), Some(resolution)) = (self.max_resolution, info.resolution)
+            && !max.allows(resolution)
+        {
+            return false;
+        }
+        if !self.codecs.is_empty()
+            && let Some(ref codecs) = info.codecs
+        {
+            let matches = codecs.split(',').any(|codec| {
+                let codec = codec.trim().to_lowercase();
+                self.codecs.iter().any(|prefix| codec.starts_with(prefix))
+            });
+            if !matches {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Drop and reorder `#EXT-X-STREAM-INF` + URI pairs in a playlist.
+    ///
+    /// Filtered variants keep their position; sorted variants are emitted
+    /// together where the first variant was.
+    pub fn apply<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        if self.is_empty() || !input.contains("#EXT-X-STREAM-INF:") {
+            return Cow::Borrowed(input);
+        }
+
+        enum Item<'a> {
+            Line(&'a str),
+            Variant(usize),
+        }
+
+        let mut items = Vec::new();
+        let mut variants: Vec<(StreamInfo, Vec<&str>)> = Vec::new();
+        let mut pending: Option<(StreamInfo, Vec<&str>)> = None;
+
+        for line in input.lines() {
+            let line_type = LineClassifier::classify(line);
+
+            // A variant is its tag plus everything up to its URI
+            if let Some((_, ref mut lines)) = pending {
+                lines.push(line);
+                if line_type == LineType::Uri
+                    && let Some(variant) = pending.take()
+                {
+                    items.push(Item::Variant(variants.len()));
+                    variants.push(variant);
+                }
+                continue;
+            }
+
+            if line_type == LineType::ExtXStreamInf {
+                pending = Some((StreamInfo::parse(line.trim()), vec![line]));
+            } else {
+                items.push(Item::Line(line));
+            }
+        }
+        if let Some((_, lines)) = pending {
+            items.extend(lines.into_iter().map(Item::Line));
+        }
+
+        let mut kept: Vec<usize> = (0..variants.len())
+            .filter(|&i| self.accepts(&variants[i].0))
+            .collect();
+        if kept.is_empty()
+            && let Some(lowest) =
+                (0..variants.len()).min_by_key(|&i| variants[i].0.bandwidth.unwrap_or(u64::MAX))
+        {
+            kept.push(lowest);
+        }
+
+        if let Some(order) = self.sort {
+            kept.sort_by(|&a, &b| {
+                let (a, b) = (variants[a].0.bandwidth, variants[b].0.bandwidth);
+                match order {
+                    SortOrder::Asc => a.cmp(&b),
+                    SortOrder::Desc => b.cmp(&a),
+                }
+            });
+        }
+
+        let mut output = Vec::new();
+        let mut sorted_emitted = false;
+        for item in items {
+            match item {
+                Item::Line(line) => output.push(line),
+                Item::Variant(_) if self.sort.is_some() => {
+                    if !sorted_emitted {
+                        for &i in &kept {
+                            output.extend(&variants[i].1);
+                        }
+                        sorted_emitted = true;
+                    }
+                }
+                Item::Variant(i) => {
+                    if kept.contains(&i) {
+                        output.extend(&variants[i].1);
+                    }
+                }
+            }
+        }
+
+        Cow::Owned(output.join("\n"))
+    }
+}
+
+/// Filter for the `#EXT-X-MEDIA` renditions of a master playlist.
+///
+/// Renditions lacking `LANGUAGE` are kept by the language filter. When a
+/// group loses all of its renditions, the variants referencing it drop the
+/// group attribute (`CLOSED-CAPTIONS` becomes `NONE`).
+#[derive(Debug, Clone, Default)]
+pub struct RenditionFilter {
+    /// Languages to keep (e.g. `en`); `en` also matches `en-US`.
+    pub languages: Vec<String>,
+
+    /// Rendition `TYPE`s to keep (e.g. `AUDIO`).
+    pub types: Vec<String>,
+
+    /// `GROUP-ID`s to keep.
+    pub groups: Vec<String>,
+
+    /// Language to mark `DEFAULT=YES` in every group that has it.
+    pub default_language: Option<String>,
+}
+
+/// Attributes of an `#EXT-X-MEDIA` tag the filter looks at.
+struct Rendition<'a> {
+    media_type: String,
+    group: &'a str,
+    language: Option<&'a str>,
+    default: bool,
+}
+
+impl RenditionFilter {
+    /// Build a filter from the /manifest query parameters.
+    pub fn from_params(
+        lang: Option<&str>,
+        media_type: Option<&str>,
+        group: Option<&str>,
+        default_lang: Option<&str>,
+    ) -> Result<Self> {
+        let types = split_list(media_type, |t| t.to_uppercase());
+        if let Some(invalid) = types.iter().find(|t| {
+            !matches!(
+                t.as_str(),
+                "AUDIO" | "VIDEO" | "SUBTITLES" | "CLOSED-CAPTIONS"
+            )
+        }) {
+            return Err(Error::InvalidParameter(format!("type={}", invalid)));
+        }
+
+        Ok(Self {
+            languages: split_list(lang, |l| l.to_lowercase()),
+            types,
+            groups: split_list(group, str::to_string),
+            default_language: default_lang
+                .map(|l| l.trim().to_lowercase())
+                .filter(|l| !l.is_empty()),
+        })
+    }
+
+    /// Check if the filter changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty()
+            && self.types.is_empty()
+            && self.groups.is_empty()
+            && self.default_language.is_none()
+    }
+
+    /// Query parameters that carry this filter to child manifest URLs.
+    pub fn query_params(&self) -> Vec<String> {
+        let mut params = Vec::new();
+
+        if !self.languages.is_empty() {
+            params.push(format!(
+                "lang={}",
+                urlencoding::encode(&self.languages.join(","))
+            ));
+        }
+        if !self.types.is_empty() {
+            params.push(format!(
+                "type={}",
+                urlencoding::encode(&self.types.join(","))
+            ));
+        }
+        if !self.groups.is_empty() {
+            params.push(format!(
+                "group={}",
+                urlencoding::encode(&self.groups.join(","))
+            ));
+        }
+        if let Some(ref lang) = self.default_language {
+            params.push(format!("default_lang={}", urlencoding::encode(lang)));
+        }
+
+        params
+    }
+
+    /// Drop renditions and fix up `DEFAULT` and variant group references.
+    pub fn apply<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        if self.is_empty() || !input.contains("#EXT-X-MEDIA:") {
+            return Cow::Borrowed(input);
+        }
+
+        let lines: Vec<&str> = input.lines().collect();
+        let renditions: Vec<Option<Rendition>> = lines
+            .iter()
+            .map(|line| {
+                line.trim()
+                    .strip_prefix("#EXT-X-MEDIA:")
+                    .map(Rendition::parse)
+            })
+            .collect();
+
+        let kept: Vec<bool> = renditions
+            .iter()
+            .map(|r| r.as_ref().is_some_and(|r| self.accepts(r)))
+            .collect();
+
+        // Per group: whether anything is left, and which rendition is the default
+        let mut groups: HashMap<(&str, &str), GroupState> = HashMap::new();
+        for (i, rendition) in renditions.iter().enumerate() {
+            let Some(rendition) = rendition else { continue };
+            let group = groups
+                .entry((rendition.media_type.as_str(), rendition.group))
+                .or_default();
+            if !kept[i] {
+                group.had_default |= rendition.default;
+                continue;
+            }
+            group.kept.push(i);
+            let is_preferred = rendition.language.is_some_and(|lang| {
+                self.default_language
+                    .as_deref()
+                    .is_some_and(|preferred| language_matches(lang, preferred))
+            });
+            if is_preferred && group.preferred.is_none() {
+                group.preferred = Some(i);
+            }
+            if rendition.default && group.current_default.is_none() {
+                group.current_default = Some(i);
+            }
+        }
+
+        // The new default of each group, if it changes
+        let mut defaults: HashMap<usize, bool> = HashMap::new();
+        for group in groups.values() {
+            let chosen = group.preferred.or(match group.current_default {
+                None if group.had_default => group.kept.first().copied(),
+                _ => None,
+            });
+            if let Some(chosen) = chosen {
+                for &i in &group.kept {
+                    defaults.insert(i, i == chosen);
+                }
+            }
+        }
+
+        let mut output = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            if renditions[i].is_some() {
+                if !kept[i] {
+                    continue;
+                }
+                match defaults.get(&i) {
+                    Some(&default) => output.push(Self::set_default(line, default)),
+                    None => output.push(line.to_string()),
+                }
+                continue;
+            }
+
+            match LineClassifier::classify(line) {
+                LineType::ExtXStreamInf | LineType::ExtXIFrameStreamInf => {
+                    output.push(Self::fix_group_references(line, &groups));
+                }
+                _ => output.push(line.to_string()),
+            }
+        }
+
+        Cow::Owned(output.join("\n"))
+    }
+
+    fn accepts(&self, rendition: &Rendition) -> bool {
+        if !self.types.is_empty() && !self.types.contains(&rendition.media_type) {
+            return false;
+        }
+        if !self.groups.is_empty() && !self.groups.iter().any(|g| g == rendition.group) {
+            return false;
+        }
+        if !self.languages.is_empty()
+            && let Some(lang) = rendition.language
+            && !self.languages.iter().any(|l| language_matches(lang, l))
+        {
+            return false;
+        }
+        true
+    }
+
+    /// Set `DEFAULT` on a rendition; a default rendition is also `AUTOSELECT`.
+    fn set_default(line: &str, default: bool) -> String {
+        let line = line.trim();
+        let content = &line["#EXT-X-MEDIA:".len()..];
+
+        let mut attrs: Vec<String> = attributes::split(content)
+            .into_iter()
+            .filter(|attr| {
+                let name = attr_name(attr);
+                name != "DEFAULT" && !(default && name == "AUTOSELECT")
+            })
+            .map(str::to_string)
+            .collect();
+        if default {
+            attrs.push("DEFAULT=YES".to_string());
+            attrs.push("AUTOSELECT=YES".to_string());
+        } else {
+            attrs.push("DEFAULT=NO".to_string());
+        }
+
+        format!("#EXT-X-MEDIA:{}", attrs.join(","))
+    }
+
+    /// Drop variant references to groups that no longer have renditions.
+    fn fix_group_references(line: &str, groups: &HashMap<(&str, &str), GroupState>) -> String {
+        let line = line.trim();
+        let Some((tag, content)) = line.split_once(':') else {
+            return line.to_string();
+        };
+
+        let mut changed = false;
+        let mut attrs = Vec::new();
+        for attr in attributes::split(content) {
+            let name = attr_name(attr);
+            let emptied = matches!(
+                name.as_str(),
+                "AUDIO" | "VIDEO" | "SUBTITLES" | "CLOSED-CAPTIONS"
+            ) && attr.split_once('=').is_some_and(|(_, value)| {
+                groups
+                    .get(&(name.as_str(), value.trim().trim_matches('"')))
+                    .is_some_and(|group| group.kept.is_empty())
+            });
+
+            if !emptied {
+                attrs.push(attr.to_string());
+                continue;
+            }
+            changed = true;
+            if name == "CLOSED-CAPTIONS" {
+                attrs.push("CLOSED-CAPTIONS=NONE".to_string());
+            }
+        }
+
+        if !changed {
+            return line.to_string();
+        }
+        format!("{}:{}", tag, attrs.join(","))
+    }
+}
+
+impl<'a> Rendition<'a> {
+    fn parse(content: &'a str) -> Self {
+        let mut rendition = Self {
+            media_type: String::new(),
+            group: "",
+            language: None,
+            default: false,
+        };
+
+        for attr in attributes::split(content) {
+            let Some((key, value)) = attr.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match key.trim().to_uppercase().as_str() {
+                "TYPE" => rendition.media_type = value.to_uppercase(),
+                "GROUP-ID" => rendition.group = value,
+                "LANGUAGE" => rendition.language = Some(value),
+                "DEFAULT" => rendition.default = value.eq_ignore_ascii_case("YES"),
+                _ => {}
+            }
+        }
+
+        rendition
+    }
+}
+
+/// Renditions of one `TYPE` + `GROUP-ID` during filtering.
+#[derive(Default)]
+struct GroupState {
+    kept: Vec<usize>,
+    preferred: Option<usize>,
+    current_default: Option<usize>,
+    had_default: bool,
+}
+
+/// Match a language tag against a filter value by its primary subtags.
+fn language_matches(tag: &str, filter: &str) -> bool {
+    let tag = tag.to_lowercase();
+    tag == filter
+        || tag
+            .strip_prefix(filter)
+            .is_some_and(|rest| rest.starts_with('-'))
+}
+
+fn split_list(value: Option<&str>, normalize: impl Fn(&str) -> String) -> Vec<String> {
+    value
+        .map(|v| {
+            v.split(',')
+                .map(|item| normalize(item.trim()))
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn attr_name(attr: &str) -> String {
+    attr.split_once('=')
+        .map(|(key, _)| key.trim().to_uppercase())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"en\",URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+360p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS=\"avc1.640028,mp4a.40.2\"
+1080p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,CODECS=\"hvc1.1.6.L93.B0,mp4a.40.2\"
+720p-hevc.m3u8";
+
+    fn uris(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| LineClassifier::classify(line) == LineType::Uri)
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_filter_is_noop() {
+        let filter = VariantFilter::default();
+        assert!(matches!(filter.apply(MASTER), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_max_bandwidth_and_resolution() {
+        let filter = VariantFilter::from_params(Some(3_000_000), None, None, None).unwrap();
+        assert_eq!(uris(&filter.apply(MASTER)), ["360p.m3u8", "720p-hevc.m3u8"]);
+
+        let filter = VariantFilter::from_params(None, Some("720p"), None, None).unwrap();
+        let output = filter.apply(MASTER);
+        assert_eq!(uris(&output), ["360p.m3u8", "720p-hevc.m3u8"]);
+        // Tag and URI are removed together
+        assert!(!output.contains("BANDWIDTH=5000000"));
+        assert!(output.contains("#EXT-X-MEDIA:"));
+    }
+
+    #[test]
+    fn test_codecs() {
+        let filter = VariantFilter::from_params(None, None, Some("avc1"), None).unwrap();
+        assert_eq!(uris(&filter.apply(MASTER)), ["360p.m3u8", "1080p.m3u8"]);
+    }
+
+    #[test]
+    fn test_sort_desc() {
+        let filter = VariantFilter::from_params(None, None, None, Some("desc")).unwrap();
+        let output = filter.apply(MASTER);
+        assert_eq!(uris(&output), ["1080p.m3u8", "720p-hevc.m3u8", "360p.m3u8"]);
+        assert!(output.starts_with("#EXTM3U\n#EXT-X-MEDIA:"));
+    }
+
+    #[test]
+    fn test_keeps_lowest_when_all_filtered() {
+        let filter = VariantFilter::from_params(Some(1000), None, None, None).unwrap();
+        assert_eq!(uris(&filter.apply(MASTER)), ["360p.m3u8"]);
+    }
+
+    #[test]
+    fn test_query_params_roundtrip() {
+        let filter = VariantFilter::from_params(
+            Some(2_000_000),
+            Some("1280x720"),
+            Some("avc1,mp4a"),
+            Some("asc"),
+        )
+        .unwrap();
+        assert_eq!(
+            filter.query_params(),
+            [
+                "max_bw=2000000",
+                "max_res=1280x720",
+                "codecs=avc1%2Cmp4a",
+                "sort=asc"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(VariantFilter::from_params(None, Some("big"), None, None).is_err());
+        assert!(VariantFilter::from_params(None, None, None, Some("random")).is_err());
+    }
+
+    const RENDITIONS: &str = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en-US\",DEFAULT=YES,AUTOSELECT=YES,URI=\"en.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"Japanese\",LANGUAGE=\"ja\",AUTOSELECT=NO,URI=\"ja.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"French\",LANGUAGE=\"fr\",URI=\"fr.m3u8\"
+#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"subs\",NAME=\"French\",LANGUAGE=\"fr\",URI=\"fr.vtt.m3u8\"
+#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"CC1\",LANGUAGE=\"fr\",INSTREAM-ID=\"CC1\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\",SUBTITLES=\"subs\",CLOSED-CAPTIONS=\"cc\"
+360p.m3u8";
+
+    fn media_lines(output: &str) -> Vec<&str> {
+        output
+            .lines()
+            .filter(|line| line.starts_with("#EXT-X-MEDIA:"))
+            .collect()
+    }
+
+    #[test]
+    fn test_empty_rendition_filter_is_noop() {
+        let filter = RenditionFilter::default();
+        assert!(matches!(filter.apply(RENDITIONS), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_filters_languages_and_fixes_variants() {
+        let filter = RenditionFilter::from_params(Some("en,ja"), None, None, None).unwrap();
+        let output = filter.apply(RENDITIONS);
+
+        let media = media_lines(&output);
+        assert_eq!(media.len(), 2);
+        assert!(media[0].contains("LANGUAGE=\"en-US\",DEFAULT=YES"));
+        assert!(media[1].contains("LANGUAGE=\"ja\""));
+
+        // Emptied groups are no longer referenced
+        assert!(output.contains(
+            "#EXT-X-STREAM-INF:BANDWIDTH=800000,AUDIO=\"aud\",CLOSED-CAPTIONS=NONE\n360p.m3u8"
+        ));
+    }
+
+    #[test]
+    fn test_filters_type_and_group() {
+        let filter = RenditionFilter::from_params(None, Some("audio"), None, None).unwrap();
+        assert_eq!(media_lines(&filter.apply(RENDITIONS)).len(), 3);
+
+        let filter = RenditionFilter::from_params(None, None, Some("subs"), None).unwrap();
+        let output = filter.apply(RENDITIONS);
+        assert_eq!(media_lines(&output).len(), 1);
+        assert!(output.contains("BANDWIDTH=800000,SUBTITLES=\"subs\",CLOSED-CAPTIONS=NONE"));
+    }
+
+    #[test]
+    fn test_default_language() {
+        let filter = RenditionFilter::from_params(None, None, None, Some("ja")).unwrap();
+        let media = media_lines(&filter.apply(RENDITIONS))
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        assert!(media[0].ends_with("AUTOSELECT=YES,URI=\"en.m3u8\",DEFAULT=NO"));
+        assert!(media[1].ends_with("URI=\"ja.m3u8\",DEFAULT=YES,AUTOSELECT=YES"));
+        assert!(!media[1].contains("AUTOSELECT=NO"));
+        assert!(media[2].ends_with("DEFAULT=NO"));
+        // Groups without the language are untouched
+        assert!(!media[3].contains("DEFAULT"));
+    }
+
+    #[test]
+    fn test_promotes_default_when_removed() {
+        let filter = RenditionFilter::from_params(Some("ja,fr"), None, None, None).unwrap();
+        let media = media_lines(&filter.apply(RENDITIONS))
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        assert!(media[0].contains("LANGUAGE=\"ja\""));
+        assert!(media[0].ends_with("DEFAULT=YES,AUTOSELECT=YES"));
+        assert!(media[1].ends_with("DEFAULT=NO"));
+    }
+
+    #[test]
+    fn test_rendition_query_params() {
+        let filter =
+            RenditionFilter::from_params(Some("en,ja"), Some("audio"), None, Some("ja")).unwrap();
+        assert_eq!(
+            filter.query_params(),
+            ["lang=en%2Cja", "type=AUDIO", "default_lang=ja"]
+        );
+        assert!(RenditionFilter::from_params(None, Some("music"), None, None).is_err());
+    }
+}
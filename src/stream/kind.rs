@@ -0,0 +1,67 @@
+/// Type of a streaming manifest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestKind {
+    /// HLS playlist (M3U8).
+    Hls,
+    /// MPEG-DASH manifest (MPD).
+    Dash,
+}
+
+impl ManifestKind {
+    /// Detect the manifest type from its content, falling back to the
+    /// upstream Content-Type and then to HLS.
+    pub fn detect(content: &str, content_type: Option<&str>) -> Self {
+        let content = content.trim_start_matches('\u{feff}').trim_start();
+        if content.starts_with("#EXTM3U") {
+            return Self::Hls;
+        }
+        if content.starts_with('<') && content.contains("MPD") {
+            return Self::Dash;
+        }
+
+        let mime = content_type
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_ascii_lowercase());
+        match mime.as_deref() {
+            Some("application/dash+xml") => Self::Dash,
+            _ => Self::Hls,
+        }
+    }
+
+    /// Content type of the transformed manifest.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Hls => "application/vnd.apple.mpegurl",
+            Self::Dash => "application/dash+xml",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_from_content() {
+        assert_eq!(
+            ManifestKind::detect("#EXTM3U\n#EXT-X-VERSION:3", None),
+            ManifestKind::Hls
+        );
+        assert_eq!(
+            ManifestKind::detect(
+                "<?xml version=\"1.0\"?>\n<MPD xmlns=\"urn:mpeg:dash:schema:mpd:2011\"/>",
+                Some("text/plain")
+            ),
+            ManifestKind::Dash
+        );
+    }
+
+    #[test]
+    fn test_detect_from_content_type() {
+        assert_eq!(
+            ManifestKind::detect("", Some("application/dash+xml; charset=utf-8")),
+            ManifestKind::Dash
+        );
+        assert_eq!(ManifestKind::detect("", None), ManifestKind::Hls);
+    }
+}
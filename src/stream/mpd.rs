@@ -0,0 +1,637 @@
+use quick_xml::{
+    Reader, Writer,
+    events::{BytesEnd, BytesStart, BytesText, Event},
+};
+use url::Url;
+
+use super::context::TransformContext;
+use crate::{Error, Result, dash::TemplateValues, hls::ByteRange};
+
+/// Decryption method for protected DASH segments.
+const DASH_METHOD: &str = "cenc";
+
+/// Elements that can carry `BaseURL`s and segment addressing.
+const LEVELS: &[&str] = &["MPD", "Period", "AdaptationSet", "Representation"];
+
+/// URL attributes of `SegmentTemplate`.
+const TEMPLATE_ATTRS: &[&str] = &["initialization", "media", "index"];
+
+/// MPEG-DASH manifest (MPD) processor.
+///
+/// Resolves `BaseURL`s and rewrites segment addressing on each
+/// `Representation`:
+/// - `SegmentTemplate` `initialization`, `media` and `index` URLs
+/// - `SegmentList` `SegmentURL`s and `Initialization` / `RepresentationIndex`
+/// - the `BaseURL` itself for single-file representations
+///
+/// Protected representations are routed through /segment for CENC
+/// decryption when `decrypt` is enabled and a key is provided, and their
+/// `ContentProtection` elements are removed. Other segments go through
+/// /segment when proxying everything, and are made absolute otherwise.
+pub struct MpdProcessor {
+    context: TransformContext,
+}
+
+/// Inherited state while walking the MPD hierarchy.
+#[derive(Clone)]
+struct Scope {
+    base: Url,
+    template: TemplateUrls,
+    has_list: bool,
+    protected: bool,
+}
+
+/// `SegmentTemplate` URLs in effect, as written in the manifest.
+#[derive(Clone, Default)]
+struct TemplateUrls {
+    initialization: Option<String>,
+    media: Option<String>,
+    index: Option<String>,
+}
+
+impl MpdProcessor {
+    pub fn new(context: TransformContext) -> Self {
+        Self {
+            context: context.with_default_extension("mp4"),
+        }
+    }
+
+    /// Process an MPD and return the transformed document.
+    pub fn process(&self, input: &str) -> Result<String> {
+        let mut nodes = parse(input)?;
+
+        let scope = Scope {
+            base: self
+                .context
+                .pin_to_mirror(self.context.original_url.clone()),
+            template: TemplateUrls::default(),
+            has_list: false,
+            protected: false,
+        };
+        for node in &mut nodes {
+            if let Node::Element(el) = node
+                && el.is("MPD")
+            {
+                self.process_level(el, &scope);
+            }
+        }
+
+        serialize(&nodes)
+    }
+
+    /// Process an MPD, Period, AdaptationSet or Representation element.
+    fn process_level(&self, el: &mut Element, parent: &Scope) {
+        let mut scope = parent.clone();
+
+        // Resolve the first BaseURL; the rewritten URLs are all absolute
+        if let Some(base) = el.child("BaseURL").map(Element::text)
+            && let Ok(url) = scope.base.join(base.trim())
+        {
+            scope.base = self.context.pin_to_mirror(url);
+        }
+        let base_index = el.remove_children("BaseURL");
+
+        scope.protected |= el.child("ContentProtection").is_some();
+        let intercept = self.context.should_intercept(scope.protected);
+        if intercept {
+            el.remove_children("ContentProtection");
+        }
+
+        // Template URLs are written on each Representation, so they
+        // resolve against the Representation's own base
+        let is_representation = el.is("Representation");
+        if let Some(template) = el.child_mut("SegmentTemplate") {
+            let slots = [
+                &mut scope.template.initialization,
+                &mut scope.template.media,
+                &mut scope.template.index,
+            ];
+            for (attr, slot) in TEMPLATE_ATTRS.iter().zip(slots) {
+                if let Some(value) = template.attr(attr) {
+                    *slot = Some(value.to_string());
+                }
+                if !is_representation {
+                    template.remove_attr(attr);
+                }
+            }
+        }
+
+        for child in el.elements_mut() {
+            if child.is("SegmentList") || child.is("SegmentBase") {
+                scope.has_list |= child.is("SegmentList");
+                self.rewrite_segment_info(child, &scope.base, intercept);
+            } else if child.is("Location")
+                && let Ok(target) = self.context.resolve_url(child.text().trim())
+            {
+                child.set_text(&self.context.build_manifest_url(&target));
+            }
+        }
+
+        if is_representation {
+            self.rewrite_representation(el, &scope, intercept, base_index);
+        }
+
+        for child in el.elements_mut() {
+            if LEVELS.iter().any(|level| child.is(level)) {
+                self.process_level(child, &scope);
+            }
+        }
+    }
+
+    /// Write the effective segment addressing onto a Representation.
+    fn rewrite_representation(
+        &self,
+        el: &mut Element,
+        scope: &Scope,
+        intercept: bool,
+        base_index: Option<usize>,
+    ) {
+        let resolve = |t: &Option<String>| t.as_deref().and_then(|t| scope.base.join(t).ok());
+        let init = resolve(&scope.template.initialization);
+        let media = resolve(&scope.template.media);
+        let index = resolve(&scope.template.index);
+
+        if let Some(media) = media {
+            let mut attrs = Vec::new();
+            if let Some(ref init) = init {
+                attrs.push((
+                    "initialization",
+                    self.media_url(init, None, None, intercept),
+                ));
+            }
+            attrs.push((
+                "media",
+                self.media_url(&media, None, init.as_ref().map(|u| (u, None)), intercept),
+            ));
+            if let Some(index) = index {
+                attrs.push(("index", self.media_url(&index, None, None, false)));
+            }
+
+            let template = match el.child_mut("SegmentTemplate") {
+                Some(template) => template,
+                None => el.insert_child(0, "SegmentTemplate"),
+            };
+            for (name, value) in attrs {
+                template.set_attr(name, value);
+            }
+        } else if !scope.has_list {
+            // Single-file representation addressed by its BaseURL
+            let url = self.media_url(&scope.base, None, None, intercept);
+            el.insert_child(base_index.unwrap_or(0), "BaseURL")
+                .set_text(&url);
+        }
+    }
+
+    /// Rewrite the URLs of a SegmentList or SegmentBase.
+    fn rewrite_segment_info(&self, el: &mut Element, base: &Url, intercept: bool) {
+        let proxied = intercept || self.context.proxy_all;
+        let is_list = el.is("SegmentList");
+
+        // Media segments need the init segment for CENC decryption
+        let init = el
+            .child("Initialization")
+            .and_then(|i| Self::source(i, "sourceURL", "range", base));
+
+        for child in el.elements_mut() {
+            let (url_attr, range_attr) = match child.local_name() {
+                "Initialization" | "RepresentationIndex" => ("sourceURL", "range"),
+                "SegmentURL" => ("media", "mediaRange"),
+                _ => continue,
+            };
+            // Without a URL the range refers to the BaseURL. Lists get the
+            // range folded into a proxied URL, SegmentBase ranges are
+            // requested from the rewritten BaseURL by the player.
+            if child.attr(url_attr).is_none() && !(proxied && is_list) {
+                continue;
+            }
+            let Some((target, byterange)) = Self::source(child, url_attr, range_attr, base) else {
+                continue;
+            };
+
+            let url = match child.local_name() {
+                "SegmentURL" => self.media_url(
+                    &target,
+                    byterange.as_ref(),
+                    init.as_ref().map(|(url, br)| (url, br.as_ref())),
+                    intercept,
+                ),
+                "Initialization" => self.media_url(&target, byterange.as_ref(), None, intercept),
+                _ => self.media_url(&target, byterange.as_ref(), None, false),
+            };
+            child.set_attr(url_attr, url);
+            if proxied {
+                child.remove_attr(range_attr);
+            }
+        }
+    }
+
+    /// Resolve the URL and byte range of a segment element.
+    fn source(
+        el: &Element,
+        url_attr: &str,
+        range_attr: &str,
+        base: &Url,
+    ) -> Option<(Url, Option<ByteRange>)> {
+        let url = match el.attr(url_attr) {
+            Some(url) => base.join(url).ok()?,
+            None => base.clone(),
+        };
+        let byterange = el.attr(range_attr).and_then(Self::parse_range);
+        Some((url, byterange))
+    }
+
+    /// Parse a DASH `first-last` byte range.
+    fn parse_range(s: &str) -> Option<ByteRange> {
+        let (first, last) = s.trim().split_once('-')?;
+        let first: u64 = first.parse().ok()?;
+        let last: u64 = last.parse().ok()?;
+        (last >= first).then(|| ByteRange::new(last - first + 1, Some(first)))
+    }
+
+    /// Build the URL a segment is served from.
+    ///
+    /// Template identifiers are kept in the signed URL and forwarded as
+    /// query parameters for the player to fill in.
+    fn media_url(
+        &self,
+        target: &Url,
+        byterange: Option<&ByteRange>,
+        init: Option<(&Url, Option<&ByteRange>)>,
+        intercept: bool,
+    ) -> String {
+        let url = if intercept {
+            self.context
+                .build_segment_url(target, DASH_METHOD, &[0u8; 16], byterange, init, None)
+        } else if self.context.proxy_all {
+            self.context.build_passthrough_url(target, byterange)
+        } else {
+            return target.to_string();
+        };
+
+        let mut templates = vec![target.as_str()];
+        if let Some((init, _)) = init {
+            templates.push(init.as_str());
+        }
+        // Opaque token URLs have no query of their own yet
+        let params = TemplateValues::query_params(&templates);
+        match params.strip_prefix('&') {
+            Some(params) if !url.contains('?') => format!("{}?{}", url, params),
+            _ => url + &params,
+        }
+    }
+}
+
+/// Minimal XML tree, enough to rewrite an MPD while keeping everything else.
+enum Node {
+    Element(Element),
+    Other(Event<'static>),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn from_start(start: &BytesStart) -> Result<Self> {
+        let mut attrs = Vec::new();
+        for attr in start.attributes() {
+            let attr = attr.map_err(quick_xml::Error::from)?;
+            attrs.push((
+                String::from_utf8_lossy(attr.key.as_ref()).into_owned(),
+                attr.unescape_value()?.into_owned(),
+            ));
+        }
+
+        Ok(Self {
+            name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+            attrs,
+            children: Vec::new(),
+        })
+    }
+
+    /// Element name without namespace prefix.
+    fn local_name(&self) -> &str {
+        self.name.rsplit(':').next().unwrap_or(&self.name)
+    }
+
+    fn is(&self, name: &str) -> bool {
+        self.local_name() == name
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn set_attr(&mut self, name: &str, value: String) {
+        match self.attrs.iter_mut().find(|(key, _)| key == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.attrs.push((name.to_string(), value)),
+        }
+    }
+
+    fn remove_attr(&mut self, name: &str) {
+        self.attrs.retain(|(key, _)| key != name);
+    }
+
+    fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|node| match node {
+            Node::Element(el) => Some(el),
+            Node::Other(_) => None,
+        })
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|node| match node {
+            Node::Element(el) if el.is(name) => Some(el),
+            _ => None,
+        })
+    }
+
+    fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.elements_mut().find(|el| el.is(name))
+    }
+
+    /// Insert an empty child element, using this element's namespace prefix.
+    fn insert_child(&mut self, index: usize, local_name: &str) -> &mut Element {
+        let name = match self.name.split_once(':') {
+            Some((prefix, _)) => format!("{}:{}", prefix, local_name),
+            None => local_name.to_string(),
+        };
+        let index = index.min(self.children.len());
+        self.children.insert(
+            index,
+            Node::Element(Element {
+                name,
+                attrs: Vec::new(),
+                children: Vec::new(),
+            }),
+        );
+        match &mut self.children[index] {
+            Node::Element(el) => el,
+            Node::Other(_) => unreachable!(),
+        }
+    }
+
+    /// Remove all children with this name and the indentation before them.
+    ///
+    /// Returns the position of the first removed child.
+    fn remove_children(&mut self, name: &str) -> Option<usize> {
+        let mut first = None;
+        let mut kept = Vec::with_capacity(self.children.len());
+
+        for node in self.children.drain(..) {
+            if matches!(&node, Node::Element(el) if el.is(name)) {
+                if matches!(kept.last(), Some(Node::Other(Event::Text(t))) if t.iter().all(u8::is_ascii_whitespace))
+                {
+                    kept.pop();
+                }
+                first.get_or_insert(kept.len());
+                continue;
+            }
+            kept.push(node);
+        }
+
+        self.children = kept;
+        first
+    }
+
+    /// Text content of the element.
+    fn text(&self) -> String {
+        let mut text = String::new();
+        for node in &self.children {
+            match node {
+                Node::Other(Event::Text(t)) => {
+                    if let Ok(s) = t.unescape() {
+                        text.push_str(&s);
+                    }
+                }
+                Node::Other(Event::CData(c)) => text.push_str(&String::from_utf8_lossy(c)),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.children = vec![Node::Other(Event::Text(BytesText::new(text).into_owned()))];
+    }
+
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        let mut start = BytesStart::new(self.name.as_str());
+        for (key, value) in &self.attrs {
+            start.push_attribute((key.as_str(), value.as_str()));
+        }
+
+        if self.children.is_empty() {
+            return writer.write_event(Event::Empty(start));
+        }
+
+        writer.write_event(Event::Start(start))?;
+        for node in &self.children {
+            node.write(writer)?;
+        }
+        writer.write_event(Event::End(BytesEnd::new(self.name.as_str())))
+    }
+}
+
+impl Node {
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        match self {
+            Node::Element(el) => el.write(writer),
+            Node::Other(event) => writer.write_event(event.borrow()),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Vec<Node>> {
+    let mut reader = Reader::from_str(input);
+    let mut root = Vec::new();
+    let mut stack: Vec<Element> = Vec::new();
+
+    loop {
+        let node = match reader.read_event()? {
+            Event::Start(start) => {
+                stack.push(Element::from_start(&start)?);
+                continue;
+            }
+            Event::End(_) => {
+                let el = stack
+                    .pop()
+                    .ok_or_else(|| Error::InvalidManifest("Unexpected end tag".to_string()))?;
+                Node::Element(el)
+            }
+            Event::Empty(start) => Node::Element(Element::from_start(&start)?),
+            Event::Eof => break,
+            event => Node::Other(event.into_owned()),
+        };
+
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => root.push(node),
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(Error::InvalidManifest("Unclosed element".to_string()));
+    }
+    if !root
+        .iter()
+        .any(|node| matches!(node, Node::Element(el) if el.is("MPD")))
+    {
+        return Err(Error::InvalidManifest("Missing MPD element".to_string()));
+    }
+
+    Ok(root)
+}
+
+fn serialize(nodes: &[Node]) -> Result<String> {
+    let mut writer = Writer::new(Vec::new());
+    for node in nodes {
+        node.write(&mut writer)
+            .map_err(|e| Error::Internal(e.to_string()))?;
+    }
+    String::from_utf8(writer.into_inner()).map_err(|e| Error::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decrypt::DecryptionKey, server::SigningKey};
+    use std::collections::HashMap;
+
+    const TEMPLATE_MPD: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
+  <BaseURL>https://cdn.example.com/dash/</BaseURL>
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011" value="cenc"/>
+      <SegmentTemplate timescale="1000" duration="4000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/seg-$Number%05d$.m4s"/>
+      <Representation id="v1" bandwidth="800000"/>
+      <Representation id="v2" bandwidth="1600000">
+        <BaseURL>hd/</BaseURL>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+    fn create_test_context(key: Option<&str>, decrypt: bool) -> TransformContext {
+        TransformContext::new(
+            Url::parse("https://origin.example.com/live/manifest.mpd").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_decryption_key(key.map(|k| DecryptionKey::parse(k).unwrap()))
+        .with_decrypt(decrypt)
+    }
+
+    #[test]
+    fn test_decrypt_rewrites_templates() {
+        let context = create_test_context(Some("0123456789abcdef0123456789abcdef"), true);
+        let output = MpdProcessor::new(context).process(TEMPLATE_MPD).unwrap();
+
+        assert!(output.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<MPD"));
+        assert!(!output.contains("ContentProtection"));
+        assert!(!output.contains("<BaseURL>"));
+        // Shared template keeps its timing but not its URLs
+        assert!(output.contains(r#"<SegmentTemplate timescale="1000" duration="4000"/>"#));
+
+        assert!(output.contains(
+            "media=\"/segment.m4s?url=https%3A%2F%2Fcdn.example.com%2Fdash%2F%24RepresentationID%24%2Fseg-%24Number%2505d%24.m4s&amp;k="
+        ));
+        assert!(output.contains("&amp;m=cenc&amp;init=https%3A%2F%2Fcdn.example.com%2Fdash%2F%24RepresentationID%24%2Finit.mp4&amp;sig="));
+        assert!(output.contains("&amp;rid=$RepresentationID$&amp;num=$Number$\""));
+        assert!(output.contains(
+            "initialization=\"/segment.mp4?url=https%3A%2F%2Fcdn.example.com%2Fdash%2F%24RepresentationID%24%2Finit.mp4&amp;"
+        ));
+        // The Representation BaseURL applies to its own template URLs
+        assert!(
+            output.contains(
+                "https%3A%2F%2Fcdn.example.com%2Fdash%2Fhd%2F%24RepresentationID%24%2Fseg-"
+            )
+        );
+    }
+
+    #[test]
+    fn test_without_decrypt_makes_urls_absolute() {
+        let context = create_test_context(None, false);
+        let output = MpdProcessor::new(context).process(TEMPLATE_MPD).unwrap();
+
+        assert!(output.contains("ContentProtection"));
+        assert!(output.contains(
+            r#"media="https://cdn.example.com/dash/$RepresentationID$/seg-$Number%05d$.m4s""#
+        ));
+        assert!(output.contains(
+            r#"initialization="https://cdn.example.com/dash/hd/$RepresentationID$/init.mp4""#
+        ));
+    }
+
+    #[test]
+    fn test_single_file_base_url_proxied() {
+        let input = r#"<MPD><Period><AdaptationSet>
+<Representation id="a" bandwidth="128000"><BaseURL>audio.mp4</BaseURL><SegmentBase indexRange="800-1200"><Initialization range="0-799"/></SegmentBase></Representation>
+</AdaptationSet></Period></MPD>"#;
+        let context = create_test_context(None, false).with_proxy_all(true);
+        let output = MpdProcessor::new(context).process(input).unwrap();
+
+        assert!(output.contains(
+            "<BaseURL>/segment.mp4?url=https%3A%2F%2Forigin.example.com%2Flive%2Faudio.mp4&amp;sig="
+        ));
+        // Ranges within the file are left for the player to request
+        assert!(
+            output
+                .contains(r#"<SegmentBase indexRange="800-1200"><Initialization range="0-799"/>"#)
+        );
+    }
+
+    #[test]
+    fn test_segment_list_ranges_proxied() {
+        let input = r#"<MPD><Period><AdaptationSet><Representation id="v">
+<BaseURL>https://cdn.example.com/v.mp4</BaseURL>
+<SegmentList duration="4"><Initialization range="0-999"/><SegmentURL mediaRange="1000-4999"/></SegmentList>
+</Representation></AdaptationSet></Period></MPD>"#;
+        let context = create_test_context(None, false).with_proxy_all(true);
+        let output = MpdProcessor::new(context).process(input).unwrap();
+
+        assert!(output.contains(
+            "<Initialization sourceURL=\"/segment.mp4?url=https%3A%2F%2Fcdn.example.com%2Fv.mp4&amp;br=1000%400&amp;sig="
+        ));
+        assert!(output.contains(
+            "<SegmentURL media=\"/segment.mp4?url=https%3A%2F%2Fcdn.example.com%2Fv.mp4&amp;br=4000%401000&amp;sig="
+        ));
+        assert!(!output.contains("mediaRange"));
+        assert!(!output.contains("<BaseURL>"));
+    }
+
+    #[test]
+    fn test_location_rewritten() {
+        let input = r#"<MPD type="dynamic"><Location>https://origin.example.com/live/next.mpd</Location></MPD>"#;
+        let output = MpdProcessor::new(create_test_context(None, false))
+            .process(input)
+            .unwrap();
+
+        assert!(output.contains(
+            "<Location>/manifest?url=https%3A%2F%2Forigin.example.com%2Flive%2Fnext.mpd&amp;sig="
+        ));
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let processor = MpdProcessor::new(create_test_context(None, false));
+        assert!(matches!(
+            processor.process("<MPD><Period></MPD>"),
+            Err(Error::InvalidManifest(_))
+        ));
+        assert!(matches!(
+            processor.process("#EXTM3U"),
+            Err(Error::InvalidManifest(_))
+        ));
+    }
+}
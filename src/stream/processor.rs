@@ -2,7 +2,7 @@ use super::{
     classifier::{LineClassifier, LineType},
     context::TransformContext,
     rules::TransformRule,
-    state::{MapInfo, ProcessorState},
+    state::{MapInfo, MediaPlaylistType, ProcessorState},
 };
 use crate::hls::{ByteRange, KeyInfo, StreamInfo};
 
@@ -26,6 +26,12 @@ impl StreamProcessor {
     pub fn process(&mut self, input: &str) -> String {
         let mut output = Vec::new();
 
+        // Drop and reorder variants and renditions before the rules see them
+        let variant_filter = self.context.variant_filter.clone();
+        let rendition_filter = self.context.rendition_filter.clone();
+        let input = variant_filter.apply(input);
+        let input = rendition_filter.apply(&input);
+
         for line in input.lines() {
             let transformed = self.process_line(line);
             output.extend(transformed);
@@ -77,11 +83,34 @@ impl StreamProcessor {
                     self.state.update_key(key);
                 }
             }
+            LineType::ExtXSessionKey => {
+                if let Some(key) = KeyInfo::parse(line) {
+                    self.state.update_session_key(key);
+                }
+            }
             LineType::ExtXMap => {
                 if let Some(map) = MapInfo::parse(line) {
                     self.state.update_map(map);
                 }
             }
+            LineType::ExtXTargetDuration => {
+                match Self::parse_target_duration(line) {
+                    Some(duration) => self.state.set_target_duration(duration),
+                    // Media playlists may omit #EXT-X-MEDIA-SEQUENCE
+                    None => self.state.mark_media_playlist(),
+                }
+            }
+            LineType::ExtXIFramesOnly => {
+                self.state.mark_media_playlist();
+            }
+            LineType::ExtXPlaylistType => {
+                if let Some(playlist_type) = MediaPlaylistType::parse(line.trim()) {
+                    self.state.set_media_playlist_type(playlist_type);
+                }
+            }
+            LineType::ExtXEndList => {
+                self.state.mark_end_list();
+            }
             LineType::ExtInf => {
                 self.state.set_pending_segment();
             }
@@ -120,6 +149,11 @@ impl StreamProcessor {
             .and_then(|s| s.trim().parse().ok())
     }
 
+    fn parse_target_duration(line: &str) -> Option<u64> {
+        line.strip_prefix("#EXT-X-TARGETDURATION:")
+            .and_then(|s| s.trim().parse().ok())
+    }
+
     /// Get current state (for inspection/testing).
     pub fn state(&self) -> &ProcessorState {
         &self.state
@@ -134,7 +168,7 @@ impl StreamProcessor {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::server::SigningKey;
+    use crate::{server::SigningKey, stream::rules};
     use std::collections::HashMap;
     use url::Url;
 
@@ -145,8 +179,6 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            None,
-            false,
             SigningKey::test_key(),
         )
     }
@@ -161,4 +193,50 @@ mod tests {
 
         assert_eq!(output, input);
     }
+
+    #[test]
+    fn test_iframe_playlist_byteranges() {
+        let context = create_test_context().with_proxy_all(true);
+        let mut processor = StreamProcessor::new(context, rules::default_rules());
+
+        let input = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-I-FRAMES-ONLY\n\
+#EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"\n\
+#EXTINF:2.0,\n#EXT-X-BYTERANGE:1000@720\nmain.mp4\n\
+#EXTINF:2.0,\n#EXT-X-BYTERANGE:1200\nmain.mp4";
+        let output = processor.process(input);
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert!(lines[3].starts_with("#EXT-X-MAP:URI=\"/segment.mp4?"));
+        assert!(lines[3].contains("br=720%400"));
+        // Ranges move into the proxied URLs, so no BYTERANGE tags are left
+        // for players to apply on top of them
+        assert!(!output.contains("BYTERANGE"));
+        assert_eq!(lines[4], "#EXTINF:2.0,");
+        assert!(lines[5].starts_with("/segment.mp4?"));
+        assert!(lines[5].contains("br=1000%40720"));
+        assert_eq!(lines[6], "#EXTINF:2.0,");
+        // The second range continues where the first ended
+        assert!(lines[7].contains("br=1200%401720"));
+        assert_eq!(lines.len(), 8);
+        assert_eq!(processor.state().segment_index, 2);
+    }
+
+    #[test]
+    fn test_tracks_live_state() {
+        let mut processor = StreamProcessor::new(create_test_context(), rules::default_rules());
+        processor.process("#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,\nseg1.ts");
+        assert_eq!(processor.state().target_duration, Some(6));
+        assert!(processor.state().is_live());
+
+        let mut processor = StreamProcessor::new(create_test_context(), rules::default_rules());
+        processor.process(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-PLAYLIST-TYPE:EVENT\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST",
+        );
+        assert_eq!(
+            processor.state().media_playlist_type,
+            Some(MediaPlaylistType::Event)
+        );
+        assert!(processor.state().end_list);
+        assert!(!processor.state().is_live());
+    }
 }
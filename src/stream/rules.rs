@@ -1,15 +1,29 @@
+pub mod iframe_proxy;
+pub mod key_proxy;
 pub mod key_rewrite;
 pub mod map_rewrite;
 pub mod media_proxy;
+pub mod part_proxy;
+pub mod rendition_report;
 pub mod segment_proxy;
+pub mod server_control;
+pub mod session_data;
+pub mod session_key;
 pub mod variant_proxy;
 
 use super::{classifier::LineType, context::TransformContext, state::ProcessorState};
 
+pub use iframe_proxy::IFrameStreamProxyRule;
+pub use key_proxy::KeyUriProxyRule;
 pub use key_rewrite::KeyTagRewriteRule;
 pub use map_rewrite::MapTagRewriteRule;
 pub use media_proxy::MediaTagProxyRule;
+pub use part_proxy::PartialSegmentProxyRule;
+pub use rendition_report::RenditionReportProxyRule;
 pub use segment_proxy::SegmentUrlProxyRule;
+pub use server_control::ServerControlRule;
+pub use session_data::SessionDataProxyRule;
+pub use session_key::SessionKeyRewriteRule;
 pub use variant_proxy::VariantUrlProxyRule;
 
 /// Trait for transform rules.
@@ -31,9 +45,16 @@ pub trait TransformRule: Send + Sync {
 pub fn default_rules() -> Vec<Box<dyn TransformRule>> {
     vec![
         Box::new(VariantUrlProxyRule),
+        Box::new(IFrameStreamProxyRule),
         Box::new(MediaTagProxyRule),
         Box::new(KeyTagRewriteRule),
+        Box::new(SessionKeyRewriteRule),
+        Box::new(SessionDataProxyRule),
+        Box::new(KeyUriProxyRule),
         Box::new(MapTagRewriteRule),
         Box::new(SegmentUrlProxyRule),
+        Box::new(PartialSegmentProxyRule),
+        Box::new(RenditionReportProxyRule),
+        Box::new(ServerControlRule),
     ]
 }
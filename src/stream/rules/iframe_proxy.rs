@@ -0,0 +1,67 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::attributes;
+
+/// Rule for rewriting #EXT-X-I-FRAME-STREAM-INF URIs to go through /manifest.
+pub struct IFrameStreamProxyRule;
+
+impl TransformRule for IFrameStreamProxyRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        _state: &ProcessorState,
+        _context: &TransformContext,
+    ) -> bool {
+        *line_type == LineType::ExtXIFrameStreamInf
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        _state: &mut ProcessorState,
+        context: &TransformContext,
+    ) -> Vec<String> {
+        let Some(content) = line.trim().strip_prefix("#EXT-X-I-FRAME-STREAM-INF:") else {
+            return vec![line.to_string()];
+        };
+
+        // Rebuild the tag, rewriting only the URI attribute
+        let result = attributes::rewrite_uri("#EXT-X-I-FRAME-STREAM-INF:", content, |uri| {
+            let resolved = context.resolve_url(uri).ok()?;
+            Some(context.build_manifest_url(&resolved))
+        });
+
+        vec![result]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    #[test]
+    fn test_rewrites_iframe_uri() {
+        let rule = IFrameStreamProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/master.m3u8").unwrap(),
+            Some("aGVhZGVycw".to_string()),
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        );
+        let mut state = ProcessorState::new();
+
+        assert!(rule.matches(&LineType::ExtXIFrameStreamInf, &state, &context));
+
+        let line = r#"#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,CODECS="avc1.4d001f,mp4a.40.2",URI="720p/iframe.m3u8""#;
+        let result = rule.transform(line, &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with(
+            "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,CODECS=\"avc1.4d001f,mp4a.40.2\",URI=\"/manifest?url=https%3A%2F%2Fcdn.example.com%2F720p%2Fiframe.m3u8&h=aGVhZGVycw&sig="
+        ));
+    }
+}
@@ -0,0 +1,126 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::{KeyMethod, attributes};
+
+/// Rule for rewriting AES-128 key URIs to go through /key.
+///
+/// Applies to keys that are passed through to the client, so players can
+/// fetch them without the upstream auth headers.
+pub struct KeyUriProxyRule;
+
+impl TransformRule for KeyUriProxyRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        state: &ProcessorState,
+        context: &TransformContext,
+    ) -> bool {
+        if *line_type != LineType::ExtXKey {
+            return false;
+        }
+
+        // Only AES-128 keys left for the client, fetched over HTTP(S)
+        match state.current_key {
+            Some(ref key) => {
+                key.method == KeyMethod::Aes128
+                    && !context.should_intercept_key(key)
+                    && key
+                        .uri
+                        .as_deref()
+                        .is_some_and(|uri| !uri.starts_with("data:") && !uri.starts_with("skd://"))
+            }
+            None => false,
+        }
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        _state: &mut ProcessorState,
+        context: &TransformContext,
+    ) -> Vec<String> {
+        let Some(content) = line.strip_prefix("#EXT-X-KEY:") else {
+            return vec![line.to_string()];
+        };
+
+        // Rebuild the tag, rewriting only the URI attribute
+        let result = attributes::rewrite_uri("#EXT-X-KEY:", content, |uri| {
+            let resolved = context.resolve_url(uri).ok()?;
+            Some(context.build_key_url(&resolved))
+        });
+
+        vec![result]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hls::KeyInfo;
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    fn create_test_context() -> TransformContext {
+        TransformContext::new(
+            Url::parse("https://cdn.example.com/video/playlist.m3u8").unwrap(),
+            None,
+            Some("eyJhIjoiYiJ9".to_string()),
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+    }
+
+    fn state_with_key(method: KeyMethod, uri: &str) -> ProcessorState {
+        let mut state = ProcessorState::new();
+        state.update_key(KeyInfo {
+            method,
+            uri: Some(uri.to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+        state
+    }
+
+    #[test]
+    fn test_matches_aes128_passthrough() {
+        let rule = KeyUriProxyRule;
+        let context = create_test_context();
+
+        let state = state_with_key(KeyMethod::Aes128, "key.bin");
+        assert!(rule.matches(&LineType::ExtXKey, &state, &context));
+
+        let state = state_with_key(KeyMethod::Aes128, "data:;base64,AAAA");
+        assert!(!rule.matches(&LineType::ExtXKey, &state, &context));
+
+        let state = state_with_key(KeyMethod::SampleAes, "key.bin");
+        assert!(!rule.matches(&LineType::ExtXKey, &state, &context));
+    }
+
+    #[test]
+    fn test_does_not_match_intercepted_aes128() {
+        let rule = KeyUriProxyRule;
+        let context = create_test_context().with_aes128_decrypt(true);
+
+        let state = state_with_key(KeyMethod::Aes128, "key.bin");
+        assert!(!rule.matches(&LineType::ExtXKey, &state, &context));
+    }
+
+    #[test]
+    fn test_rewrites_key_uri() {
+        let rule = KeyUriProxyRule;
+        let context = create_test_context();
+        let mut state = state_with_key(KeyMethod::Aes128, "key.bin");
+
+        let line =
+            r#"#EXT-X-KEY:METHOD=AES-128,URI="key.bin",IV=0x00000000000000000000000000000001"#;
+        let result = rule.transform(line, &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with(
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"/key?url=https%3A%2F%2Fcdn.example.com%2Fvideo%2Fkey.bin&h=eyJhIjoiYiJ9&sig="
+        ));
+        assert!(result[0].ends_with("\",IV=0x00000000000000000000000000000001"));
+    }
+}
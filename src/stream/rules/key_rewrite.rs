@@ -16,7 +16,7 @@ impl TransformRule for KeyTagRewriteRule {
 
         // Only handle if we're intercepting DRM
         if let Some(ref key) = state.current_key {
-            context.should_intercept(key.requires_server_decrypt())
+            context.should_intercept_key(key)
         } else {
             false
         }
@@ -26,20 +26,20 @@ impl TransformRule for KeyTagRewriteRule {
         &self,
         _line: &str,
         state: &mut ProcessorState,
-        _context: &TransformContext,
+        context: &TransformContext,
     ) -> Vec<String> {
-        // When we intercept DRM, we remove the KEY tag from output
-        // because the /segment endpoint will handle decryption
+        // When we intercept DRM (or opted-in AES-128), we remove the KEY tag
+        // from output because the /segment endpoint will handle decryption
 
-        // Check if it's a DRM method we're handling
+        // Check if it's a method we're handling
         if let Some(ref key) = state.current_key
-            && key.requires_server_decrypt()
+            && context.should_intercept_key(key)
         {
             // Remove the KEY tag - segments will be decrypted by server
             return vec![];
         }
 
-        // For AES-128 or other methods, this rule shouldn't match
+        // For other methods, this rule shouldn't match
         // but if it does, passthrough
         vec![]
     }
@@ -61,10 +61,12 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            Some(DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap()),
-            true,
             SigningKey::test_key(),
         )
+        .with_decryption_key(Some(
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+        ))
+        .with_decrypt(true)
     }
 
     #[test]
@@ -102,6 +104,31 @@ mod tests {
         assert!(!rule.matches(&LineType::ExtXKey, &state, &context));
     }
 
+    #[test]
+    fn test_matches_aes128_when_opted_in() {
+        let rule = KeyTagRewriteRule;
+        let context = create_context_with_decrypt().with_aes128_decrypt(true);
+
+        let mut state = ProcessorState::new();
+        state.update_key(KeyInfo {
+            method: KeyMethod::Aes128,
+            uri: Some("https://key.server/key".to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+
+        assert!(rule.matches(&LineType::ExtXKey, &state, &context));
+        assert!(
+            rule.transform(
+                r#"#EXT-X-KEY:METHOD=AES-128,URI="https://key.server/key""#,
+                &mut state,
+                &context,
+            )
+            .is_empty()
+        );
+    }
+
     #[test]
     fn test_removes_drm_key_tag() {
         let rule = KeyTagRewriteRule;
@@ -14,11 +14,10 @@ impl TransformRule for MapTagRewriteRule {
             return false;
         }
 
-        // Only rewrite if we're intercepting DRM
-        if let Some(ref key) = state.current_key {
-            context.should_intercept(key.requires_server_decrypt())
-        } else {
-            false
+        // Rewrite if we're intercepting DRM, or proxying everything
+        match state.current_key {
+            Some(ref key) if context.should_intercept_key(key) => true,
+            _ => context.proxy_all,
         }
     }
 
@@ -32,31 +31,37 @@ impl TransformRule for MapTagRewriteRule {
             return vec![line.to_string()];
         };
 
-        let Some(ref key) = state.current_key else {
-            return vec![line.to_string()];
-        };
-
-        let Some(method) = key.method.to_segment_param() else {
-            return vec![line.to_string()];
-        };
-
         // Resolve the init segment URL
         let resolved = match context.resolve_url(&map_info.uri) {
             Ok(url) => url,
             Err(_) => return vec![line.to_string()],
         };
 
-        let iv = state.current_iv();
-
-        // Build segment URL for init segment
-        let proxied = context.build_segment_url(
-            &resolved,
-            method,
-            &iv,
-            map_info.byterange.as_ref(),
-            None, // No nested init
-            None,
-        );
+        let proxied = match state
+            .current_key
+            .as_ref()
+            .filter(|key| context.should_intercept_key(key))
+        {
+            Some(key) => {
+                let Some(method) = key.method.to_segment_param() else {
+                    return vec![line.to_string()];
+                };
+
+                let iv = state.current_iv();
+
+                // Build segment URL for init segment
+                context.build_segment_url(
+                    &resolved,
+                    method,
+                    &iv,
+                    map_info.byterange.as_ref(),
+                    None, // No nested init
+                    context.key_uri(key).as_ref(),
+                )
+            }
+            // Init segments we don't decrypt are only proxied
+            None => context.build_passthrough_url(&resolved, map_info.byterange.as_ref()),
+        };
 
         // Rebuild the #EXT-X-MAP tag with proxied URI
         let mut result = String::from("#EXT-X-MAP:URI=\"");
@@ -86,10 +91,12 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            Some(DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap()),
-            true,
             SigningKey::test_key(),
         )
+        .with_decryption_key(Some(
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+        ))
+        .with_decrypt(true)
     }
 
     #[test]
@@ -115,4 +122,31 @@ mod tests {
         assert_eq!(result.len(), 1);
         assert!(result[0].starts_with("#EXT-X-MAP:URI=\"/segment.mp4?"));
     }
+
+    #[test]
+    fn test_proxies_clear_map_in_proxy_mode() {
+        let rule = MapTagRewriteRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_proxy_all(true);
+
+        let mut state = ProcessorState::new();
+        state.update_map(MapInfo {
+            uri: "init.mp4".to_string(),
+            byterange: None,
+        });
+
+        assert!(rule.matches(&LineType::ExtXMap, &state, &context));
+
+        let result = rule.transform(r#"#EXT-X-MAP:URI="init.mp4""#, &mut state, &context);
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with("#EXT-X-MAP:URI=\"/segment.mp4?url="));
+        assert!(!result[0].contains("m="));
+    }
 }
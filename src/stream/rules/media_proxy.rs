@@ -1,4 +1,5 @@
 use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::attributes;
 
 /// Rule for rewriting #EXT-X-MEDIA tags with URI attributes.
 pub struct MediaTagProxyRule;
@@ -24,60 +25,16 @@ impl TransformRule for MediaTagProxyRule {
             return vec![line.to_string()];
         };
 
-        // Find URI attribute and rewrite it
-        let mut result = String::from("#EXT-X-MEDIA:");
-        let mut first = true;
-
-        for attr in Self::parse_attributes(content) {
-            if !first {
-                result.push(',');
-            }
-            first = false;
-
-            if let Some((key, value)) = attr.split_once('=') {
-                let key_upper = key.trim().to_uppercase();
-                if key_upper == "URI" {
-                    // Rewrite URI
-                    let uri = value.trim().trim_matches('"');
-                    if let Ok(resolved) = context.resolve_url(uri) {
-                        let proxied = context.build_manifest_url(&resolved);
-                        result.push_str(&format!("URI=\"{}\"", proxied));
-                        continue;
-                    }
-                }
-            }
-            result.push_str(attr);
-        }
+        // Rebuild the tag, rewriting only the URI attribute
+        let result = attributes::rewrite_uri("#EXT-X-MEDIA:", content, |uri| {
+            let resolved = context.resolve_url(uri).ok()?;
+            Some(context.build_manifest_url(&resolved))
+        });
 
         vec![result]
     }
 }
 
-impl MediaTagProxyRule {
-    fn parse_attributes(s: &str) -> Vec<&str> {
-        let mut attrs = Vec::new();
-        let mut start = 0;
-        let mut in_quotes = false;
-
-        for (i, c) in s.char_indices() {
-            match c {
-                '"' => in_quotes = !in_quotes,
-                ',' if !in_quotes => {
-                    attrs.push(s[start..i].trim());
-                    start = i + 1;
-                }
-                _ => {}
-            }
-        }
-
-        if start < s.len() {
-            attrs.push(s[start..].trim());
-        }
-
-        attrs
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,8 +49,6 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            None,
-            false,
             SigningKey::test_key(),
         )
     }
@@ -0,0 +1,268 @@
+use url::Url;
+
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::{ByteRange, KeyMethod, attributes};
+
+/// Rule for rewriting LL-HLS partial segment URIs (#EXT-X-PART and
+/// #EXT-X-PRELOAD-HINT) to go through /segment.
+///
+/// Parts are handled like the segment they belong to: decrypted with the
+/// current key and map when intercepting, or only proxied in proxy-only mode.
+pub struct PartialSegmentProxyRule;
+
+impl TransformRule for PartialSegmentProxyRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        state: &ProcessorState,
+        context: &TransformContext,
+    ) -> bool {
+        if !matches!(line_type, LineType::ExtXPart | LineType::ExtXPreloadHint) {
+            return false;
+        }
+
+        // Rewrite if we're intercepting DRM, or proxying everything
+        match state.current_key {
+            Some(ref key) if context.should_intercept_key(key) => true,
+            _ => context.proxy_all,
+        }
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        state: &mut ProcessorState,
+        context: &TransformContext,
+    ) -> Vec<String> {
+        let line = line.trim();
+        let Some((tag, content)) = line.split_once(':') else {
+            return vec![line.to_string()];
+        };
+        let attrs = attributes::split(content);
+
+        let Some(resolved) =
+            Self::attribute(&attrs, "URI").and_then(|uri| context.resolve_url(uri).ok())
+        else {
+            return vec![line.to_string()];
+        };
+
+        // Byte ranges are encoded in the proxied URL when fully known;
+        // open-ended ones are left for the player to request
+        let is_hint = tag == "#EXT-X-PRELOAD-HINT";
+        let byterange = if is_hint {
+            Self::attribute(&attrs, "BYTERANGE-START")
+                .zip(Self::attribute(&attrs, "BYTERANGE-LENGTH"))
+                .and_then(|(start, length)| {
+                    Some(ByteRange::new(
+                        length.parse().ok()?,
+                        Some(start.parse().ok()?),
+                    ))
+                })
+        } else {
+            Self::attribute(&attrs, "BYTERANGE")
+                .and_then(|br| ByteRange::parse(br).ok())
+                .filter(|br| br.offset.is_some())
+        };
+        let is_map = is_hint && Self::attribute(&attrs, "TYPE") == Some("MAP");
+
+        let Some(proxied) = Self::build_url(&resolved, byterange.as_ref(), is_map, state, context)
+        else {
+            return vec![line.to_string()];
+        };
+
+        // Rebuild the tag, replacing the URI and any encoded byte range
+        let mut result = format!("{}:", tag);
+        let mut first = true;
+
+        for attr in attrs {
+            let name = attr
+                .split_once('=')
+                .map(|(key, _)| key.trim().to_uppercase());
+            let value = match name.as_deref() {
+                Some("URI") => format!("URI=\"{}\"", proxied),
+                Some("BYTERANGE" | "BYTERANGE-START" | "BYTERANGE-LENGTH")
+                    if byterange.is_some() =>
+                {
+                    continue;
+                }
+                _ => attr.to_string(),
+            };
+
+            if !first {
+                result.push(',');
+            }
+            first = false;
+            result.push_str(&value);
+        }
+
+        vec![result]
+    }
+}
+
+impl PartialSegmentProxyRule {
+    /// Build the /segment URL for a part or hinted resource.
+    fn build_url(
+        target: &Url,
+        byterange: Option<&ByteRange>,
+        is_map: bool,
+        state: &ProcessorState,
+        context: &TransformContext,
+    ) -> Option<String> {
+        // Parts we don't decrypt are only proxied
+        let Some(key) = state
+            .current_key
+            .as_ref()
+            .filter(|key| context.should_intercept_key(key))
+        else {
+            return Some(context.build_passthrough_url(target, byterange));
+        };
+
+        let method = key.method.to_segment_param()?;
+
+        // Parts belong to the next segment, so they share its IV and map
+        let iv = state.current_iv();
+        let (init_url, init_byterange) = match state.current_map {
+            Some(ref map) if !is_map && key.method != KeyMethod::Aes128 => {
+                (context.resolve_url(&map.uri).ok(), map.byterange.as_ref())
+            }
+            _ => (None, None),
+        };
+
+        Some(context.build_segment_url(
+            target,
+            method,
+            &iv,
+            byterange,
+            init_url.as_ref().map(|url| (url, init_byterange)),
+            context.key_uri(key).as_ref(),
+        ))
+    }
+
+    fn attribute<'a>(attrs: &[&'a str], name: &str) -> Option<&'a str> {
+        attrs.iter().find_map(|attr| {
+            let (key, value) = attr.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().trim_matches('"'))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt::DecryptionKey;
+    use crate::hls::KeyInfo;
+    use crate::server::SigningKey;
+    use crate::stream::state::MapInfo;
+    use std::collections::HashMap;
+
+    fn create_context_with_decrypt() -> TransformContext {
+        TransformContext::new(
+            Url::parse("https://cdn.example.com/live/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_decryption_key(Some(
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+        ))
+        .with_decrypt(true)
+    }
+
+    fn drm_state() -> ProcessorState {
+        let mut state = ProcessorState::new();
+        state.update_media_sequence(10);
+        state.update_key(KeyInfo {
+            method: KeyMethod::SampleAesCtr,
+            uri: Some("skd://key".to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+        state.update_map(MapInfo {
+            uri: "init.mp4".to_string(),
+            byterange: None,
+        });
+        state
+    }
+
+    #[test]
+    fn test_matches_parts_when_intercepting() {
+        let rule = PartialSegmentProxyRule;
+        let context = create_context_with_decrypt();
+        let state = drm_state();
+
+        assert!(rule.matches(&LineType::ExtXPart, &state, &context));
+        assert!(rule.matches(&LineType::ExtXPreloadHint, &state, &context));
+        assert!(!rule.matches(&LineType::ExtXPart, &ProcessorState::new(), &context));
+    }
+
+    #[test]
+    fn test_rewrites_part_with_key_and_map() {
+        let rule = PartialSegmentProxyRule;
+        let context = create_context_with_decrypt();
+        let mut state = drm_state();
+
+        let line =
+            r#"#EXT-X-PART:DURATION=0.33334,URI="seg11.mp4",BYTERANGE="2000@4000",INDEPENDENT=YES"#;
+        let result = rule.transform(line, &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with(
+            "#EXT-X-PART:DURATION=0.33334,URI=\"/segment.mp4?url=https%3A%2F%2Fcdn.example.com%2Flive%2Fseg11.mp4"
+        ));
+        assert!(result[0].contains("m=ssa-ctr"));
+        assert!(result[0].contains("br=2000%404000"));
+        assert!(result[0].contains("init=https%3A%2F%2Fcdn.example.com%2Flive%2Finit.mp4"));
+        assert!(result[0].ends_with("\",INDEPENDENT=YES"));
+        assert!(!result[0].contains("BYTERANGE"));
+    }
+
+    #[test]
+    fn test_rewrites_preload_hint() {
+        let rule = PartialSegmentProxyRule;
+        let context = create_context_with_decrypt();
+        let mut state = drm_state();
+
+        let line = r#"#EXT-X-PRELOAD-HINT:TYPE=PART,URI="seg12.mp4",BYTERANGE-START=6000"#;
+        let result = rule.transform(line, &mut state, &context);
+
+        // Open-ended range stays on the tag
+        assert!(result[0].starts_with("#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"/segment.mp4?"));
+        assert!(result[0].ends_with("\",BYTERANGE-START=6000"));
+        assert!(!result[0].contains("br="));
+
+        let line = r#"#EXT-X-PRELOAD-HINT:TYPE=MAP,URI="init2.mp4""#;
+        let result = rule.transform(line, &mut state, &context);
+        assert!(result[0].contains("url=https%3A%2F%2Fcdn.example.com%2Flive%2Finit2.mp4"));
+        assert!(!result[0].contains("init="));
+    }
+
+    #[test]
+    fn test_proxies_clear_part_in_proxy_mode() {
+        let rule = PartialSegmentProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/live/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_proxy_all(true);
+        let mut state = ProcessorState::new();
+
+        assert!(rule.matches(&LineType::ExtXPart, &state, &context));
+
+        let result = rule.transform(
+            r#"#EXT-X-PART:DURATION=0.5,URI="part.ts""#,
+            &mut state,
+            &context,
+        );
+        assert!(result[0].starts_with("#EXT-X-PART:DURATION=0.5,URI=\"/segment.ts?url="));
+        assert!(!result[0].contains("m="));
+    }
+}
@@ -0,0 +1,69 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::attributes;
+
+/// Rule for rewriting #EXT-X-RENDITION-REPORT URIs to go through /manifest.
+pub struct RenditionReportProxyRule;
+
+impl TransformRule for RenditionReportProxyRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        _state: &ProcessorState,
+        _context: &TransformContext,
+    ) -> bool {
+        *line_type == LineType::ExtXRenditionReport
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        _state: &mut ProcessorState,
+        context: &TransformContext,
+    ) -> Vec<String> {
+        let Some(content) = line.trim().strip_prefix("#EXT-X-RENDITION-REPORT:") else {
+            return vec![line.to_string()];
+        };
+
+        // Rebuild the tag, rewriting only the URI attribute
+        let result = attributes::rewrite_uri("#EXT-X-RENDITION-REPORT:", content, |uri| {
+            let resolved = context.resolve_url(uri).ok()?;
+            Some(context.build_manifest_url(&resolved))
+        });
+
+        vec![result]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    #[test]
+    fn test_rewrites_rendition_report() {
+        let rule = RenditionReportProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/live/720p/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        );
+        let mut state = ProcessorState::new();
+
+        assert!(rule.matches(&LineType::ExtXRenditionReport, &state, &context));
+
+        let line =
+            r#"#EXT-X-RENDITION-REPORT:URI="../1080p/playlist.m3u8",LAST-MSN=273,LAST-PART=2"#;
+        let result = rule.transform(line, &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with(
+            "#EXT-X-RENDITION-REPORT:URI=\"/manifest?url=https%3A%2F%2Fcdn.example.com%2Flive%2F1080p%2Fplaylist.m3u8&sig="
+        ));
+        assert!(result[0].ends_with("\",LAST-MSN=273,LAST-PART=2"));
+    }
+}
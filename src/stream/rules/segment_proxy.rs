@@ -1,7 +1,8 @@
 use super::{LineType, ProcessorState, TransformContext, TransformRule};
-use crate::stream::state::PendingContext;
+use crate::{hls::KeyMethod, stream::state::PendingContext};
 
-/// Rule for rewriting segment URLs to go through /segment when decrypting.
+/// Rule for rewriting segment URLs to go through /segment when decrypting,
+/// or for every segment in proxy-only mode.
 pub struct SegmentUrlProxyRule;
 
 impl TransformRule for SegmentUrlProxyRule {
@@ -11,21 +12,17 @@ impl TransformRule for SegmentUrlProxyRule {
         state: &ProcessorState,
         context: &TransformContext,
     ) -> bool {
-        // Only match URIs in media playlists that are segments
-        if *line_type != LineType::Uri {
-            return false;
-        }
-
-        // Must be a segment (after #EXTINF)
-        if !matches!(state.pending_context, Some(PendingContext::Segment)) {
-            return false;
-        }
+        match line_type {
+            // The byte range of a rewritten segment is carried in its URL, so
+            // the tag is dropped and the proxied resource starts at 0
+            LineType::ExtXByteRange => Self::rewrites_segment(state, context),
 
-        // Only rewrite if we're intercepting DRM
-        if let Some(ref key) = state.current_key {
-            context.should_intercept(key.requires_server_decrypt())
-        } else {
-            false
+            // Only match URIs in media playlists that are segments (after #EXTINF)
+            LineType::Uri => {
+                matches!(state.pending_context, Some(PendingContext::Segment))
+                    && Self::rewrites_segment(state, context)
+            }
+            _ => false,
         }
     }
 
@@ -37,13 +34,9 @@ impl TransformRule for SegmentUrlProxyRule {
     ) -> Vec<String> {
         let line = line.trim();
 
-        let Some(ref key) = state.current_key else {
-            return vec![line.to_string()];
-        };
-
-        let Some(method) = key.method.to_segment_param() else {
-            return vec![line.to_string()];
-        };
+        if line.starts_with("#EXT-X-BYTERANGE:") {
+            return Vec::new();
+        }
 
         // Resolve relative URL
         let resolved = match context.resolve_url(line) {
@@ -51,11 +44,28 @@ impl TransformRule for SegmentUrlProxyRule {
             Err(_) => return vec![line.to_string()],
         };
 
+        // Segments we don't decrypt are only proxied
+        let Some(key) = state
+            .current_key
+            .as_ref()
+            .filter(|key| context.should_intercept_key(key))
+        else {
+            return vec![
+                context.build_passthrough_url(&resolved, state.current_byterange.as_ref()),
+            ];
+        };
+
+        let Some(method) = key.method.to_segment_param() else {
+            return vec![line.to_string()];
+        };
+
         let iv = state.current_iv();
         let byterange = state.current_byterange.as_ref();
 
-        // Get init segment info if present
-        let (init_url, init_byterange) = if let Some(ref map) = state.current_map {
+        // Get init segment info if present (AES-128 decrypts segments standalone)
+        let (init_url, init_byterange) = if let Some(ref map) = state.current_map
+            && key.method != KeyMethod::Aes128
+        {
             let init_resolved = context.resolve_url(&map.uri).ok();
             (init_resolved, map.byterange.as_ref())
         } else {
@@ -68,14 +78,27 @@ impl TransformRule for SegmentUrlProxyRule {
             method,
             &iv,
             byterange,
-            init_url.as_ref(),
-            init_byterange,
+            init_url.as_ref().map(|url| (url, init_byterange)),
+            context.key_uri(key).as_ref(),
         );
 
         vec![proxied]
     }
 }
 
+impl SegmentUrlProxyRule {
+    /// Check if the current segment is rewritten: when we're intercepting
+    /// DRM, or proxying everything.
+    fn rewrites_segment(state: &ProcessorState, context: &TransformContext) -> bool {
+        match state.current_key {
+            Some(ref key) if context.should_intercept_key(key) => {
+                key.method.to_segment_param().is_some()
+            }
+            _ => context.proxy_all,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,10 +115,12 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            Some(DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap()),
-            true,
             SigningKey::test_key(),
         )
+        .with_decryption_key(Some(
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+        ))
+        .with_decrypt(true)
     }
 
     #[test]
@@ -137,6 +162,104 @@ mod tests {
         assert!(!rule.matches(&LineType::Uri, &state, &context));
     }
 
+    #[test]
+    fn test_rewrites_aes128_segment_when_opted_in() {
+        let rule = SegmentUrlProxyRule;
+        let context = create_context_with_decrypt().with_aes128_decrypt(true);
+
+        let mut state = ProcessorState::new();
+        state.update_media_sequence(0);
+        state.set_pending_segment();
+        state.update_key(KeyInfo {
+            method: KeyMethod::Aes128,
+            uri: Some("https://key.server/key".to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+        state.update_map(crate::stream::state::MapInfo {
+            uri: "init.mp4".to_string(),
+            byterange: None,
+        });
+
+        assert!(rule.matches(&LineType::Uri, &state, &context));
+
+        let result = rule.transform("segment001.mp4", &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with("/segment.mp4?"));
+        assert!(result[0].contains("m=aes"));
+        assert!(!result[0].contains("init="));
+    }
+
+    #[test]
+    fn test_passes_key_uri_without_key() {
+        let rule = SegmentUrlProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_decrypt(true);
+
+        let mut state = ProcessorState::new();
+        state.update_media_sequence(0);
+        state.set_pending_segment();
+        state.update_key(KeyInfo {
+            method: KeyMethod::SampleAes,
+            uri: Some("keys/1.key".to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+
+        assert!(rule.matches(&LineType::Uri, &state, &context));
+
+        let result = rule.transform("segment001.ts", &mut state, &context);
+        assert!(result[0].contains("ku=https%3A%2F%2Fcdn.example.com%2Fkeys%2F1.key"));
+        assert!(!result[0].contains("k="));
+
+        // DRM key URIs carry license data, not keys
+        state.update_key(KeyInfo {
+            method: KeyMethod::SampleAesCenc,
+            uri: Some("data:text/plain;base64,AAAA".to_string()),
+            iv: None,
+            keyformat: None,
+            keyformatversions: None,
+        });
+        assert!(!rule.matches(&LineType::Uri, &state, &context));
+    }
+
+    #[test]
+    fn test_proxies_clear_segment_in_proxy_mode() {
+        let rule = SegmentUrlProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/playlist.m3u8").unwrap(),
+            None,
+            Some("c2g".to_string()),
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        );
+
+        let mut state = ProcessorState::new();
+        state.update_media_sequence(0);
+        state.set_pending_segment();
+        assert!(!rule.matches(&LineType::Uri, &state, &context));
+
+        let context = context.with_proxy_all(true);
+        assert!(rule.matches(&LineType::Uri, &state, &context));
+
+        let result = rule.transform("segment001.ts", &mut state, &context);
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with("/segment.ts?url="));
+        assert!(result[0].contains("h=c2g"));
+        assert!(!result[0].contains("m="));
+    }
+
     #[test]
     fn test_rewrites_segment_url() {
         let rule = SegmentUrlProxyRule;
@@ -159,4 +282,31 @@ mod tests {
         assert!(result[0].starts_with("/segment.ts?"));
         assert!(result[0].contains("m=ssa"));
     }
+
+    #[test]
+    fn test_drops_byterange_of_rewritten_segment() {
+        let rule = SegmentUrlProxyRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        );
+
+        let mut state = ProcessorState::new();
+        state.update_media_sequence(0);
+        state.set_pending_segment();
+        assert!(!rule.matches(&LineType::ExtXByteRange, &state, &context));
+
+        // The proxied segment is only the range, so the player must not
+        // request it at its offset in the original resource
+        let context = context.with_proxy_all(true);
+        assert!(rule.matches(&LineType::ExtXByteRange, &state, &context));
+        assert!(
+            rule.transform("#EXT-X-BYTERANGE:1000@720", &mut state, &context)
+                .is_empty()
+        );
+    }
 }
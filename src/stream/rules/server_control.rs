@@ -0,0 +1,80 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::attributes;
+
+/// Rule for removing playlist delta update support from
+/// #EXT-X-SERVER-CONTROL.
+///
+/// Segments are numbered, and their IVs derived, from the full playlist,
+/// so players must not ask for delta updates with `_HLS_skip`.
+pub struct ServerControlRule;
+
+impl TransformRule for ServerControlRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        _state: &ProcessorState,
+        _context: &TransformContext,
+    ) -> bool {
+        *line_type == LineType::ExtXServerControl
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        _state: &mut ProcessorState,
+        _context: &TransformContext,
+    ) -> Vec<String> {
+        let Some(content) = line.trim().strip_prefix("#EXT-X-SERVER-CONTROL:") else {
+            return vec![line.to_string()];
+        };
+
+        let attrs: Vec<&str> = attributes::split(content)
+            .into_iter()
+            .filter(|attr| {
+                let name = attr.split_once('=').map_or(*attr, |(name, _)| name);
+                !matches!(
+                    name.trim().to_uppercase().as_str(),
+                    "CAN-SKIP-UNTIL" | "CAN-SKIP-DATERANGES"
+                )
+            })
+            .collect();
+
+        if attrs.is_empty() {
+            return Vec::new();
+        }
+        vec![format!("#EXT-X-SERVER-CONTROL:{}", attrs.join(","))]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    #[test]
+    fn test_removes_delta_updates() {
+        let rule = ServerControlRule;
+        let context = TransformContext::new(
+            Url::parse("https://cdn.example.com/live/playlist.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        );
+        let mut state = ProcessorState::new();
+
+        assert!(rule.matches(&LineType::ExtXServerControl, &state, &context));
+
+        let line = "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,CAN-SKIP-UNTIL=12.0,CAN-SKIP-DATERANGES=YES,PART-HOLD-BACK=1.0";
+        assert_eq!(
+            rule.transform(line, &mut state, &context),
+            vec!["#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.0"]
+        );
+
+        let line = "#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=12.0";
+        assert!(rule.transform(line, &mut state, &context).is_empty());
+    }
+}
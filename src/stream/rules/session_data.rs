@@ -0,0 +1,86 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+use crate::hls::attributes;
+
+/// Rule for rewriting #EXT-X-SESSION-DATA URIs to go through /proxy with
+/// the manifest headers.
+pub struct SessionDataProxyRule;
+
+impl TransformRule for SessionDataProxyRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        _state: &ProcessorState,
+        _context: &TransformContext,
+    ) -> bool {
+        *line_type == LineType::ExtXSessionData
+    }
+
+    fn transform(
+        &self,
+        line: &str,
+        _state: &mut ProcessorState,
+        context: &TransformContext,
+    ) -> Vec<String> {
+        let Some(content) = line.trim().strip_prefix("#EXT-X-SESSION-DATA:") else {
+            return vec![line.to_string()];
+        };
+
+        // Rebuild the tag, rewriting only the URI attribute; inline data
+        // needs no fetching
+        let result = attributes::rewrite_uri("#EXT-X-SESSION-DATA:", content, |uri| {
+            if uri.starts_with("data:") {
+                return None;
+            }
+            let resolved = context.resolve_url(uri).ok()?;
+            Some(context.build_proxy_url(&resolved, context.manifest_headers.as_deref()))
+        });
+
+        vec![result]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    fn create_test_context() -> TransformContext {
+        TransformContext::new(
+            Url::parse("https://cdn.example.com/master.m3u8").unwrap(),
+            Some("bWFuaWZlc3Q".to_string()),
+            Some("c2VnbWVudA".to_string()),
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+    }
+
+    #[test]
+    fn test_rewrites_session_data_uri() {
+        let rule = SessionDataProxyRule;
+        let context = create_test_context();
+        let mut state = ProcessorState::new();
+
+        let line =
+            r#"#EXT-X-SESSION-DATA:DATA-ID="com.example.lyrics",URI="lyrics.json",LANGUAGE="en""#;
+        let result = rule.transform(line, &mut state, &context);
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].starts_with(
+            "#EXT-X-SESSION-DATA:DATA-ID=\"com.example.lyrics\",URI=\"/proxy?url=https%3A%2F%2Fcdn.example.com%2Flyrics.json&h=bWFuaWZlc3Q&sig="
+        ));
+        assert!(result[0].ends_with("\",LANGUAGE=\"en\""));
+    }
+
+    #[test]
+    fn test_keeps_session_data_value() {
+        let rule = SessionDataProxyRule;
+        let context = create_test_context();
+        let mut state = ProcessorState::new();
+
+        let line = r#"#EXT-X-SESSION-DATA:DATA-ID="com.example.title",VALUE="Title""#;
+        assert_eq!(rule.transform(line, &mut state, &context), vec![line]);
+    }
+}
@@ -0,0 +1,104 @@
+use super::{LineType, ProcessorState, TransformContext, TransformRule};
+
+/// Rule for removing #EXT-X-SESSION-KEY tags when segments are decrypted
+/// by the server, so players don't start a DRM session for clear content.
+pub struct SessionKeyRewriteRule;
+
+impl TransformRule for SessionKeyRewriteRule {
+    fn matches(
+        &self,
+        line_type: &LineType,
+        state: &ProcessorState,
+        context: &TransformContext,
+    ) -> bool {
+        if *line_type != LineType::ExtXSessionKey {
+            return false;
+        }
+
+        // Only handle if we're intercepting this key
+        match state.session_key {
+            Some(ref key) => context.should_intercept_key(key),
+            None => false,
+        }
+    }
+
+    fn transform(
+        &self,
+        _line: &str,
+        _state: &mut ProcessorState,
+        _context: &TransformContext,
+    ) -> Vec<String> {
+        // Segments using this key are decrypted by the /segment endpoint
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt::DecryptionKey;
+    use crate::hls::{KeyInfo, KeyMethod};
+    use crate::server::SigningKey;
+    use std::collections::HashMap;
+    use url::Url;
+
+    fn create_context(decrypt: bool) -> TransformContext {
+        TransformContext::new(
+            Url::parse("https://cdn.example.com/master.m3u8").unwrap(),
+            None,
+            None,
+            HashMap::new(),
+            HashMap::new(),
+            SigningKey::test_key(),
+        )
+        .with_decryption_key(Some(
+            DecryptionKey::parse("0123456789abcdef0123456789abcdef").unwrap(),
+        ))
+        .with_decrypt(decrypt)
+    }
+
+    fn state_with_session_key(method: KeyMethod) -> ProcessorState {
+        let mut state = ProcessorState::new();
+        state.update_session_key(KeyInfo {
+            method,
+            uri: Some("skd://key".to_string()),
+            iv: None,
+            keyformat: Some("com.apple.streamingkeydelivery".to_string()),
+            keyformatversions: None,
+        });
+        state
+    }
+
+    #[test]
+    fn test_removes_intercepted_session_key() {
+        let rule = SessionKeyRewriteRule;
+        let context = create_context(true);
+        let mut state = state_with_session_key(KeyMethod::SampleAes);
+
+        assert!(rule.matches(&LineType::ExtXSessionKey, &state, &context));
+        let line = r#"#EXT-X-SESSION-KEY:METHOD=SAMPLE-AES,URI="skd://key""#;
+        assert!(rule.transform(line, &mut state, &context).is_empty());
+    }
+
+    #[test]
+    fn test_keeps_fairplay_session_key_without_key() {
+        let rule = SessionKeyRewriteRule;
+        let mut context = create_context(true);
+        context.decryption_key = None;
+
+        // skd:// keys cannot be fetched, so FairPlay stays with the player
+        let state = state_with_session_key(KeyMethod::SampleAes);
+        assert!(!rule.matches(&LineType::ExtXSessionKey, &state, &context));
+    }
+
+    #[test]
+    fn test_keeps_session_key_without_decrypt() {
+        let rule = SessionKeyRewriteRule;
+
+        let state = state_with_session_key(KeyMethod::SampleAes);
+        assert!(!rule.matches(&LineType::ExtXSessionKey, &state, &create_context(false)));
+
+        let state = state_with_session_key(KeyMethod::Aes128);
+        assert!(!rule.matches(&LineType::ExtXSessionKey, &state, &create_context(true)));
+    }
+}
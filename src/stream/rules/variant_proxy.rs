@@ -55,8 +55,6 @@ mod tests {
             None,
             HashMap::new(),
             HashMap::new(),
-            None,
-            false,
             SigningKey::test_key(),
         )
     }
@@ -95,4 +93,22 @@ mod tests {
         assert!(result[0].starts_with("/manifest?"));
         assert!(result[0].contains("url=https%3A%2F%2Fcdn.example.com%2F720p%2Fplaylist.m3u8"));
     }
+
+    #[test]
+    fn test_propagates_variant_filter() {
+        let rule = VariantUrlProxyRule;
+        let filter = crate::stream::VariantFilter::from_params(
+            Some(3_000_000),
+            Some("720p"),
+            Some("avc1"),
+            None,
+        )
+        .unwrap();
+        let context = create_test_context().with_variant_filter(filter);
+        let mut state = ProcessorState::new();
+
+        let result = rule.transform("720p/playlist.m3u8", &mut state, &context);
+
+        assert!(result[0].contains("&max_bw=3000000&max_res=720&codecs=avc1&sig="));
+    }
 }
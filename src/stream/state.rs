@@ -1,4 +1,4 @@
-use crate::hls::{ByteRange, KeyInfo, StreamInfo};
+use crate::hls::{ByteRange, KeyInfo, StreamInfo, attributes};
 
 /// Represents the type of playlist being processed.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -7,6 +7,26 @@ pub enum PlaylistType {
     Media,
 }
 
+/// Value of #EXT-X-PLAYLIST-TYPE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaPlaylistType {
+    /// Segments are only appended.
+    Event,
+    /// The playlist never changes.
+    Vod,
+}
+
+impl MediaPlaylistType {
+    pub fn parse(line: &str) -> Option<Self> {
+        let value = line.strip_prefix("#EXT-X-PLAYLIST-TYPE:")?.trim();
+        match value.to_uppercase().as_str() {
+            "EVENT" => Some(Self::Event),
+            "VOD" => Some(Self::Vod),
+            _ => None,
+        }
+    }
+}
+
 /// Represents pending context for the next URI.
 #[derive(Debug, Clone)]
 pub enum PendingContext {
@@ -30,7 +50,7 @@ impl MapInfo {
         let mut uri = None;
         let mut byterange = None;
 
-        for attr in Self::parse_attributes(content) {
+        for attr in attributes::split(content) {
             if let Some((key, value)) = attr.split_once('=') {
                 let key = key.trim().to_uppercase();
                 let value = value.trim().trim_matches('"');
@@ -45,29 +65,6 @@ impl MapInfo {
 
         uri.map(|uri| Self { uri, byterange })
     }
-
-    fn parse_attributes(s: &str) -> Vec<&str> {
-        let mut attrs = Vec::new();
-        let mut start = 0;
-        let mut in_quotes = false;
-
-        for (i, c) in s.char_indices() {
-            match c {
-                '"' => in_quotes = !in_quotes,
-                ',' if !in_quotes => {
-                    attrs.push(s[start..i].trim());
-                    start = i + 1;
-                }
-                _ => {}
-            }
-        }
-
-        if start < s.len() {
-            attrs.push(s[start..].trim());
-        }
-
-        attrs
-    }
 }
 
 /// State maintained during stream processing.
@@ -79,6 +76,9 @@ pub struct ProcessorState {
     /// Current encryption context.
     pub current_key: Option<KeyInfo>,
 
+    /// Key advertised by #EXT-X-SESSION-KEY (master playlists).
+    pub session_key: Option<KeyInfo>,
+
     /// Current init segment info.
     pub current_map: Option<MapInfo>,
 
@@ -91,6 +91,15 @@ pub struct ProcessorState {
     /// Pending context for the next URI.
     pub pending_context: Option<PendingContext>,
 
+    /// Target duration in seconds from #EXT-X-TARGETDURATION.
+    pub target_duration: Option<u64>,
+
+    /// Playlist type from #EXT-X-PLAYLIST-TYPE.
+    pub media_playlist_type: Option<MediaPlaylistType>,
+
+    /// Whether #EXT-X-ENDLIST was seen.
+    pub end_list: bool,
+
     /// Current byte range for the next segment.
     pub current_byterange: Option<ByteRange>,
 
@@ -103,10 +112,14 @@ impl ProcessorState {
         Self {
             playlist_type: None,
             current_key: None,
+            session_key: None,
             current_map: None,
             media_sequence: 0,
             segment_index: 0,
             pending_context: None,
+            target_duration: None,
+            media_playlist_type: None,
+            end_list: false,
             current_byterange: None,
             last_byterange_end: None,
         }
@@ -120,6 +133,13 @@ impl ProcessorState {
         matches!(self.playlist_type, Some(PlaylistType::Media))
     }
 
+    /// Check if this is a media playlist that may still change.
+    pub fn is_live(&self) -> bool {
+        self.is_media_playlist()
+            && !self.end_list
+            && self.media_playlist_type != Some(MediaPlaylistType::Vod)
+    }
+
     /// Calculate IV for current segment (from explicit IV or sequence number).
     pub fn current_iv(&self) -> [u8; 16] {
         self.current_key
@@ -154,9 +174,31 @@ impl ProcessorState {
     }
 
     pub fn set_pending_segment(&mut self) {
+        self.playlist_type = Some(PlaylistType::Media);
         self.pending_context = Some(PendingContext::Segment);
     }
 
+    /// Mark the playlist as a media playlist (e.g. from #EXT-X-TARGETDURATION),
+    /// for playlists without #EXT-X-MEDIA-SEQUENCE.
+    pub fn mark_media_playlist(&mut self) {
+        self.playlist_type = Some(PlaylistType::Media);
+    }
+
+    pub fn set_target_duration(&mut self, duration: u64) {
+        self.playlist_type = Some(PlaylistType::Media);
+        self.target_duration = Some(duration);
+    }
+
+    pub fn set_media_playlist_type(&mut self, playlist_type: MediaPlaylistType) {
+        self.playlist_type = Some(PlaylistType::Media);
+        self.media_playlist_type = Some(playlist_type);
+    }
+
+    pub fn mark_end_list(&mut self) {
+        self.playlist_type = Some(PlaylistType::Media);
+        self.end_list = true;
+    }
+
     pub fn take_pending(&mut self) -> Option<PendingContext> {
         self.pending_context.take()
     }
@@ -165,6 +207,10 @@ impl ProcessorState {
         self.current_key = Some(key);
     }
 
+    pub fn update_session_key(&mut self, key: KeyInfo) {
+        self.session_key = Some(key);
+    }
+
     pub fn update_map(&mut self, map: MapInfo) {
         self.current_map = Some(map);
     }
@@ -228,4 +274,24 @@ mod tests {
         assert_eq!(br.length, 617);
         assert_eq!(br.offset, Some(0));
     }
+
+    #[test]
+    fn test_is_live() {
+        let mut state = ProcessorState::new();
+        assert!(!state.is_live());
+
+        state.set_target_duration(6);
+        assert!(state.is_live());
+
+        state.set_media_playlist_type(MediaPlaylistType::Event);
+        assert!(state.is_live());
+
+        state.mark_end_list();
+        assert!(!state.is_live());
+
+        let mut state = ProcessorState::new();
+        state
+            .set_media_playlist_type(MediaPlaylistType::parse("#EXT-X-PLAYLIST-TYPE:VOD").unwrap());
+        assert!(!state.is_live());
+    }
 }